@@ -32,11 +32,15 @@ pub enum StatementBody {
     Impl(Implementation),
     If(If),
     For(For),
+    While(While),
+    Sync(SyncBlock),
     Error(Error),
     Extract(Expression),
 
     Break,
     Continue,
+    /// Source code of a statement that couldn't be parsed
+    Invalid(String),
     //EOI,
 }
 #[derive(Clone, PartialEq, Debug)]
@@ -265,6 +269,18 @@ pub struct For {
     pub array: Expression,
     pub body: Vec<Statement>,
 }
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct While {
+    pub condition: Expression,
+    pub body: Vec<Statement>,
+}
+#[derive(Clone, PartialEq, Debug)]
+pub struct SyncBlock {
+    pub group: String,
+    pub part: Expression,
+    pub body: Vec<Statement>,
+}
 #[derive(Clone, PartialEq, Debug)]
 pub enum CaseType {
     Value(Expression),
@@ -4,7 +4,8 @@ use crate::ast::ObjectMode;
 use crate::compiler::{RuntimeError, BUILTIN_STORAGE, CONTEXT_MAX, NULL_STORAGE};
 use crate::compiler_types::*;
 use crate::levelstring::*;
-use std::collections::HashMap;
+use crate::objschema;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
@@ -25,13 +26,13 @@ macro_rules! arg_length {
 
 pub type ArbitraryID = u16;
 pub type SpecificID = u16;
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ID {
     Specific(SpecificID),
     Arbitrary(ArbitraryID), // will be given specific ids at the end of compilation
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Group {
     pub id: ID,
 }
@@ -53,7 +54,7 @@ impl Group {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Color {
     pub id: ID,
 }
@@ -118,17 +119,19 @@ impl Item {
     }
 }
 
-pub fn context_trigger(context: &Context, uid_counter: &mut usize) -> GDObj {
+pub fn context_trigger(context: &Context, uid_counter: &mut usize, info: &CompilerInfo) -> GDObj {
     let mut params = HashMap::new();
     params.insert(57, ObjParam::Group(context.start_group));
     (*uid_counter) += 1;
     GDObj {
-        params: HashMap::new(),
+        params: BTreeMap::new(),
         func_id: context.func_id,
         mode: ObjectMode::Trigger,
         unique_id: *uid_counter,
         sync_group: context.sync_group,
         sync_part: context.sync_part,
+        source: Some(info.source_location()),
+        raw: None,
     }
 }
 
@@ -285,6 +288,8 @@ pub const BUILTIN_LIST: &[&str] = &[
     "time",
     "get_input",
     "spwn_version",
+    "assert",
+    "assert_eq",
     //operators
     "_or_",
     "_and_",
@@ -358,6 +363,60 @@ pub fn built_in_function(
             Value::Str(env!("CARGO_PKG_VERSION").to_string())
         }
 
+        "assert" => {
+            if arguments.is_empty() || arguments.len() > 2 {
+                return Err(RuntimeError::BuiltinError {
+                    message: "Expected a condition and an optional message".to_string(),
+                    info,
+                });
+            }
+
+            match globals.stored_values[arguments[0]] {
+                Value::Bool(true) => Value::Null,
+                Value::Bool(false) => {
+                    let message = match arguments.get(1) {
+                        Some(m) => match &globals.stored_values[*m] {
+                            Value::Str(s) => s.clone(),
+                            a => a.to_str(globals),
+                        },
+                        None => "condition was false".to_string(),
+                    };
+                    return Err(RuntimeError::RuntimeError {
+                        message: format!("Assertion failed: {}", message),
+                        info,
+                    });
+                }
+                _ => {
+                    return Err(RuntimeError::TypeError {
+                        expected: "@bool".to_string(),
+                        found: globals.get_type_str(arguments[0]),
+                        info,
+                    })
+                }
+            }
+        }
+
+        "assert_eq" => {
+            arg_length!(
+                info,
+                2,
+                arguments,
+                "Expected two arguments: the values that should be equal".to_string()
+            );
+
+            if !value_equality(arguments[0], arguments[1], globals) {
+                return Err(RuntimeError::RuntimeError {
+                    message: format!(
+                        "Assertion failed: {} is not equal to {}",
+                        globals.stored_values[arguments[0]].to_str(globals),
+                        globals.stored_values[arguments[1]].to_str(globals)
+                    ),
+                    info,
+                });
+            }
+            Value::Null
+        }
+
         "get_input" => {
             arg_length!(info, 0, arguments, "Expected no arguments".to_string());
             let mut out = String::new();
@@ -499,14 +558,17 @@ pub fn built_in_function(
             match &globals.stored_values[arguments[0]] {
                 // if its an object
                 Value::Obj(obj, mode) => {
-                    let c_t = context_trigger(context, &mut globals.uid_counter); // i dont know
+                    let c_t = context_trigger(context, &mut globals.uid_counter, &info); // i dont know
 
-                    let mut obj_map = HashMap::<u16, ObjParam>::new();
+                    let mut obj_map = BTreeMap::<u16, ObjParam>::new();
 
                     for p in obj {
                         obj_map.insert(p.0, p.1.clone());
                         // add params into map
                     }
+                    if let Err(message) = globals.target.check_object(&obj_map) {
+                        return Err(RuntimeError::BuiltinError { message, info });
+                    }
 
                     match mode {
                         ObjectMode::Object => {
@@ -524,6 +586,8 @@ pub fn built_in_function(
                                 unique_id: globals.uid_counter,
                                 sync_group: context.sync_group,
                                 sync_part: context.sync_part,
+                                source: Some(info.source_location()),
+                                raw: None,
                             };
                             (*globals).objects.push(obj)
                         }
@@ -649,7 +713,7 @@ pub fn built_in_function(
             }
 
             let (okey, oval) = match globals.stored_values[arguments[0]].clone() {
-                Value::Obj(_o, m) => {
+                Value::Obj(o, m) => {
                     //println!("{:?}", *o);
                     //println!("context {:?}", context);
                     if context.start_group.id != ID::Specific(0) {
@@ -809,6 +873,17 @@ pub fn built_in_function(
                         }
                     };
 
+                    // properties set with object keys are checked against the schema
+                    if pattern.is_some() {
+                        let obj_id = match (key, &out_val) {
+                            (1, ObjParam::Number(n)) => Some(*n as u16),
+                            _ => objschema::obj_id(&o),
+                        };
+                        if let Err(message) = objschema::check_prop(obj_id, key, &out_val) {
+                            return Err(RuntimeError::BuiltinError { message, info });
+                        }
+                    }
+
                     (key, out_val)
                 }
                 _ => {
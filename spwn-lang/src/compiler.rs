@@ -1,19 +1,20 @@
 //! Tools for compiling SPWN into GD object strings
 use crate::ast;
 use crate::builtin::*;
+use crate::idrange;
 use crate::levelstring::*;
+use crate::target::TargetProfile;
 use crate::STD_PATH;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::parser::{ParseNotes, SyntaxError};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::compiler_types::*;
-use crate::print_with_color;
 pub const CONTEXT_MAX: usize = 2;
-
-use termcolor::Color as TColor;
+/// How many times the body of a while loop can run before it's assumed to never stop
+pub const WHILE_ITERATION_LIMIT: usize = 10_000;
 
 #[derive(Debug)]
 pub enum RuntimeError {
@@ -44,89 +45,9 @@ pub enum RuntimeError {
         info: CompilerInfo,
     },
 }
-pub fn print_error_intro(pos: crate::parser::FileRange, file: &PathBuf) {
-    use std::io::Write;
-    use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-
-    let mut stdout = StandardStream::stderr(ColorChoice::Always);
-
-    let mut write_with_color = |text: &str, color: Color| {
-        stdout
-            .set_color(ColorSpec::new().set_fg(Some(color)))
-            .unwrap();
-        write!(&mut stdout, "{}", text).unwrap();
-    };
-
-    let path_str = format!(
-        "{}:{}:{}",
-        file.to_string_lossy().to_string(),
-        pos.0 .0,
-        pos.0 .1 + 1
-    );
-
-    write_with_color("Error", TColor::Red);
-    write_with_color(&format!(" at {}\n", path_str), TColor::White);
-
-    if pos.0 .0 == pos.1 .0 {
-        use std::io::BufRead;
-        if let Ok(file) = fs::File::open(&file) {
-            if let Some(Ok(line)) = std::io::BufReader::new(file).lines().nth(pos.0 .0 - 1) {
-                let line_num = pos.1 .0.to_string();
-
-                let mut spacing = String::new();
-
-                for _ in 0..line_num.len() {
-                    spacing += " ";
-                }
-
-                let squiggly_line = "^";
-
-                write_with_color(
-                    (spacing.clone() + " |\n" + &line_num + " |").as_str(),
-                    TColor::Cyan,
-                );
-                write_with_color((line.replace("\t", " ") + "\n").as_str(), TColor::White);
-                write_with_color((spacing + " |").as_str(), TColor::Cyan);
-                let mut out = String::new();
-                for _ in 0..(pos.0 .1) {
-                    out += " ";
-                }
-                for _ in 0..(pos.1 .1 - pos.0 .1) {
-                    out += squiggly_line;
-                }
-                out += "\n";
-                write_with_color(&out, TColor::Red);
-                stdout
-                    .set_color(ColorSpec::new().set_fg(Some(TColor::White)))
-                    .unwrap();
-            }
-        }
-    };
-}
 
 impl std::fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let info = match self {
-            RuntimeError::UndefinedErr {
-                undefined: _,
-                desc: _,
-                info,
-            } => info,
-            RuntimeError::PackageSyntaxError { err: _, info } => info,
-
-            RuntimeError::TypeError {
-                expected: _,
-                found: _,
-                info,
-            } => info,
-
-            RuntimeError::RuntimeError { message: _, info } => info,
-
-            RuntimeError::BuiltinError { message: _, info } => info,
-        };
-
-        print_error_intro(info.pos, &info.current_file);
-
         match self {
             RuntimeError::UndefinedErr {
                 undefined,
@@ -166,6 +87,7 @@ pub fn compile_spwn(
     path: PathBuf,
     included_paths: Vec<PathBuf>,
     notes: ParseNotes,
+    target: Option<&'static TargetProfile>,
 ) -> Result<Globals, RuntimeError> {
     //variables that get changed throughout the compiling
     let mut globals = Globals::new(path.clone());
@@ -179,6 +101,9 @@ pub fn compile_spwn(
                 current_file: path,
                 current_module: String::new(),
                 includes: vec![],
+                call_stack: Vec::new(),
+                assigned_vars: None,
+                statement_label: None,
             },
         });
     }
@@ -194,42 +119,53 @@ pub fn compile_spwn(
         current_file: path,
         current_module: String::new(),
         includes: included_paths,
+        call_stack: Vec::new(),
+        assigned_vars: None,
+        statement_label: None,
     };
-    use std::time::Instant;
-
-    //println!("Importing standard library...");
-    print_with_color("Building script ...", TColor::Cyan);
-    print_with_color("———————————————————————————\n", TColor::White);
-    let start_time = Instant::now();
 
-    if !notes.tag.tags.iter().any(|x| x.0 == "no_std") {
-        let standard_lib = import_module(
-            &ImportType::Lib(STD_PATH.to_string()),
-            &start_context,
-            &mut globals,
-            start_info.clone(),
-            false,
-        )?;
-
-        if standard_lib.len() != 1 {
-            return Err(RuntimeError::RuntimeError {
-                message: "The standard library can not split the context".to_string(),
-                info: start_info,
-            });
+    // #[target("2.2")] chooses the version of GD, unless it was given to the compiler
+    globals.target = match (target, notes.tag.get("target")) {
+        (Some(target), _) => target,
+        (None, Some(args)) => {
+            let name = match args.first().and_then(|a| a.value.values.first()) {
+                Some(v) if args.len() == 1 && v.operator.is_none() => match &v.value.body {
+                    ast::ValueBody::Str(s) => Some(s.clone()),
+                    ast::ValueBody::Number(n) => Some(format!("{:.1}", n)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let target = name
+                .ok_or_else(|| "Expected a version like #[target(\"2.2\")]".to_string())
+                .and_then(|name| TargetProfile::from_name(&name));
+            target.map_err(|message| RuntimeError::RuntimeError {
+                message,
+                info: start_info.clone(),
+            })?
         }
+        (None, None) => globals.target,
+    };
 
-        start_context = standard_lib[0].1.clone();
-
-        if let Value::Dict(d) = &globals.stored_values[standard_lib[0].0] {
-            start_context.variables.extend(d.clone());
-        } else {
-            return Err(RuntimeError::RuntimeError {
-                message: "The standard library must return a dictionary".to_string(),
-                info: start_info,
-            });
+    // #[reserve(groups = 1..200)] keeps IDs free for objects placed by hand
+    for (name, args) in &notes.tag.tags {
+        if name == "reserve" {
+            let ranges = idrange::tag_ranges("reserve", args).map_err(|message| {
+                RuntimeError::RuntimeError {
+                    message,
+                    info: start_info.clone(),
+                }
+            })?;
+            for (class, range) in ranges {
+                globals.id_config.reserve(class, range);
+            }
         }
     }
 
+    if !notes.tag.tags.iter().any(|x| x.0 == "no_std") {
+        start_context = import_std(&start_context, &mut globals, start_info.clone())?;
+    }
+
     let (contexts, _) = compile_scope(
         &statements,
         smallvec![start_context],
@@ -246,20 +182,75 @@ pub fn compile_spwn(
         }
     }
 
-    print_with_color("———————————————————————————\n", TColor::White);
-    print_with_color(
-        &format!(
-            "Built in {} milliseconds!",
-            start_time.elapsed().as_millis()
-        ),
-        TColor::Green,
-    );
+    globals.add_sync_toggles();
 
     Ok(globals)
 }
 
+/// Imports the standard library, and returns a context with all of its members defined
+pub fn import_std(
+    context: &Context,
+    globals: &mut Globals,
+    info: CompilerInfo,
+) -> Result<Context, RuntimeError> {
+    let standard_lib = import_module(
+        &ImportType::Lib(STD_PATH.to_string()),
+        context,
+        globals,
+        info.clone(),
+        false,
+    )?;
+
+    if standard_lib.len() != 1 {
+        return Err(RuntimeError::RuntimeError {
+            message: "The standard library can not split the context".to_string(),
+            info,
+        });
+    }
+
+    let mut std_context = standard_lib[0].1.clone();
+
+    if let Value::Dict(d) = &globals.stored_values[standard_lib[0].0] {
+        std_context.variables.extend(d.clone());
+        Ok(std_context)
+    } else {
+        Err(RuntimeError::RuntimeError {
+            message: "The standard library must return a dictionary".to_string(),
+            info,
+        })
+    }
+}
+
 use smallvec::{smallvec, SmallVec};
 
+// name of the variable in `name = ...` or `let name = ...`
+fn assigned_var(statement: &ast::StatementBody) -> Option<String> {
+    match statement {
+        ast::StatementBody::Expr(e) if e.operators.first() == Some(&ast::Operator::Assign) => {
+            match (&e.values[0].value.body, e.values[0].path.is_empty()) {
+                (ast::ValueBody::Symbol(name), true) => Some(name.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// the start of a statement's source, for naming the arbitrary IDs it makes
+fn statement_label(statement: &ast::StatementBody) -> String {
+    use crate::fmt::SpwnFmt;
+    let text = statement.fmt(0);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let label: String = words.join(" ").chars().take(40).collect();
+    label.trim_end().to_string()
+}
+
+pub(crate) fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
 pub fn compile_scope(
     statements: &[ast::Statement],
     mut contexts: SmallVec<[Context; CONTEXT_MAX]>,
@@ -288,6 +279,9 @@ pub fn compile_scope(
     }
 
     globals.stored_values.increment_lifetimes();
+    // only for this scope, not the ones inside it
+    let keep_values = std::mem::replace(&mut globals.keep_scope_values, false);
+    let outer_vars = info.assigned_vars.clone();
 
     for statement in statements.iter() {
         //find out what kind of statement this is
@@ -301,6 +295,18 @@ pub fn compile_scope(
         //     contexts.len()
         // );
         info.pos = statement.pos;
+        info.assigned_vars = match assigned_var(&statement.body) {
+            Some(name) => Some(match &outer_vars {
+                Some(outer) => format!("{}.{}", outer, name),
+                None if info.call_stack.is_empty() => name,
+                // assigned in an imported file, outside of any assignment in the main file
+                None => format!("{}::{}", file_stem(&info.current_file), name),
+            }),
+            None => outer_vars.clone(),
+        };
+        if info.assigned_vars.is_none() && info.call_stack.is_empty() {
+            info.statement_label = Some(statement_label(&statement.body));
+        }
         if contexts.is_empty() {
             return Err(RuntimeError::RuntimeError {
                 message: "No context! This is probably a bug, please contact sputnix".to_string(),
@@ -345,7 +351,7 @@ pub fn compile_scope(
                                 let storage = symbol.define(&mut new_context, globals, &info)?;
 
                                 //pick a start group
-                                let start_group = Group::next_free(&mut globals.closed_groups);
+                                let start_group = globals.next_start_group(&new_context, &info);
                                 //store value
                                 globals.stored_values[storage] =
                                     Value::TriggerFunc(TriggerFunction { start_group });
@@ -662,7 +668,7 @@ pub fn compile_scope(
                 //let mut obj_list = Vec::<GDObj>::new();
                 for (func, context) in all_values {
                     contexts.push(context.clone());
-                    let mut params = HashMap::new();
+                    let mut params = BTreeMap::new();
                     params.insert(
                         51,
                         match &globals.stored_values[func] {
@@ -680,13 +686,16 @@ pub fn compile_scope(
                         },
                     );
                     params.insert(1, ObjParam::Number(1268.0));
+                    if let Err(message) = globals.target.check_object(&params) {
+                        return Err(RuntimeError::RuntimeError { message, info });
+                    }
                     (*globals).trigger_order += 1;
 
                     (*globals).func_ids[context.func_id].obj_list.push((
                         GDObj {
                             params,
 
-                            ..context_trigger(&context, &mut globals.uid_counter)
+                            ..context_trigger(&context, &mut globals.uid_counter, &info)
                         }
                         .context_parameters(&context),
                         globals.trigger_order,
@@ -963,6 +972,156 @@ pub fn compile_scope(
                     }
                 }
             }
+            While(w) => {
+                let start_contexts = contexts;
+                contexts = SmallVec::new();
+                for context in start_contexts {
+                    // contexts that are still looping, the condition is checked again for them
+                    let mut new_contexts: SmallVec<[Context; CONTEXT_MAX]> =
+                        smallvec![context.clone()];
+                    // out contexts: contexts that are done with the loop
+                    let mut out_contexts: SmallVec<[Context; CONTEXT_MAX]> = SmallVec::new();
+                    let mut iterations = 0;
+
+                    while !new_contexts.is_empty() {
+                        let mut all_values: Returns = SmallVec::new();
+                        for c in &mut new_contexts {
+                            // reset all variables per context
+                            c.variables = context.variables.clone();
+                            let (evaled, inner_returns) =
+                                w.condition.eval(c, globals, info.clone(), true)?;
+                            returns.extend(inner_returns);
+                            all_values.extend(evaled);
+                        }
+
+                        let mut body_contexts: SmallVec<[Context; CONTEXT_MAX]> = SmallVec::new();
+                        for (val, c) in all_values {
+                            match &globals.stored_values[val] {
+                                Value::Bool(true) => body_contexts.push(c),
+                                Value::Bool(false) => out_contexts.push(c),
+                                a => {
+                                    return Err(RuntimeError::RuntimeError {
+                                        message: format!(
+                                            "Expected boolean condition in while loop, found {}",
+                                            a.to_str(globals)
+                                        ),
+                                        info,
+                                    })
+                                }
+                            }
+                        }
+
+                        new_contexts = SmallVec::new();
+                        if body_contexts.is_empty() {
+                            break;
+                        }
+
+                        iterations += 1;
+                        if iterations > WHILE_ITERATION_LIMIT {
+                            return Err(RuntimeError::RuntimeError {
+                                message: format!(
+                                    "While loop ran more than {} times, it might never end",
+                                    WHILE_ITERATION_LIMIT
+                                ),
+                                info,
+                            });
+                        }
+
+                        let (end_contexts, inner_returns) =
+                            compile_scope(&w.body, body_contexts, globals, info.clone())?;
+                        returns.extend(inner_returns);
+
+                        for mut c in end_contexts {
+                            // add contexts made in the loop to the new_contexts, if they dont have a break
+                            match c.broken {
+                                Some((_, BreakType::Loop)) => {
+                                    c.broken = None;
+                                    out_contexts.push(c)
+                                }
+                                Some((_, BreakType::Macro)) => out_contexts.push(c),
+                                Some((_, BreakType::ContinueLoop)) => {
+                                    c.broken = None;
+                                    new_contexts.push(c)
+                                }
+                                _ => new_contexts.push(c),
+                            }
+                        }
+                    }
+
+                    contexts.extend(out_contexts.iter().map(|c| Context {
+                        variables: context.variables.clone(),
+                        ..c.clone()
+                    }));
+                }
+            }
+            Sync(sync_block) => {
+                let mut all_values: Returns = SmallVec::new();
+                for context in &contexts {
+                    let (evaled, inner_returns) =
+                        sync_block.part.eval(context, globals, info.clone(), true)?;
+                    returns.extend(inner_returns);
+                    all_values.extend(evaled);
+                }
+                contexts = SmallVec::new();
+                for (val, context) in all_values {
+                    // a part is identified by the group that turns it on and off
+                    let part_group = match &globals.stored_values[val] {
+                        Value::Group(g) => *g,
+                        a => {
+                            return Err(RuntimeError::RuntimeError {
+                                message: format!(
+                                    "Expected group as sync part, found {}",
+                                    a.to_str(globals)
+                                ),
+                                info,
+                            })
+                        }
+                    };
+                    let (sync_group, sync_part) =
+                        globals.get_sync_part(&sync_block.group, part_group, &info.current_file);
+
+                    globals.trigger_order += 1;
+                    let entry = SyncEntry {
+                        part: sync_part,
+                        index: globals.func_ids[context.func_id].obj_list.len(),
+                        order: globals.trigger_order,
+                        trigger: context_trigger(&context, &mut globals.uid_counter, &info)
+                            .context_parameters(&context),
+                    };
+                    globals.sync_groups[sync_group].entries.push(entry);
+
+                    let mut part_context = context.clone();
+                    part_context.sync_group = sync_group;
+                    part_context.sync_part = sync_part;
+
+                    let (end_contexts, inner_returns) = compile_scope(
+                        &sync_block.body,
+                        smallvec![part_context],
+                        globals,
+                        info.clone(),
+                    )?;
+                    returns.extend(inner_returns);
+
+                    for c in end_contexts {
+                        contexts.push(if c.start_group == context.start_group {
+                            Context {
+                                variables: context.variables.clone(),
+                                sync_group: context.sync_group,
+                                sync_part: context.sync_part,
+                                ..c
+                            }
+                        } else {
+                            // the part is still running after a delay, so the rest
+                            // of this context stays in it
+                            Context {
+                                variables: context.variables.clone(),
+                                ..c
+                            }
+                        });
+                    }
+                }
+            }
+
             Break => {
                 //set all contexts to broken
                 for c in &mut contexts {
@@ -979,6 +1138,13 @@ pub fn compile_scope(
                 break;
             }
 
+            Invalid(source) => {
+                return Err(RuntimeError::RuntimeError {
+                    message: format!("Cannot compile invalid statement: {}", source),
+                    info,
+                })
+            }
+
             Return(return_val) => {
                 match return_val {
                     Some(val) => {
@@ -1069,7 +1235,7 @@ pub fn compile_scope(
         //try to merge contexts
         //if statement_index < statements.len() - 1 {
         loop {
-            if !merge_contexts(&mut contexts, globals) {
+            if !merge_contexts(&mut contexts, globals, &info) {
                 break;
             }
         }
@@ -1089,6 +1255,17 @@ pub fn compile_scope(
             .stored_values
             .increment_single_lifetime(*val, 1, &mut HashSet::new());
     }
+    if keep_values {
+        for context in contexts.iter().chain(returns.iter().map(|(_, c)| c)) {
+            for val in context.variables.values() {
+                if globals.get_lifetime(*val) == 1 {
+                    globals
+                        .stored_values
+                        .increment_single_lifetime(*val, 1, &mut HashSet::new());
+                }
+            }
+        }
+    }
 
     globals.stored_values.decrement_lifetimes();
     //collect garbage
@@ -1194,12 +1371,35 @@ pub fn import_module(
         Err(err) => return Err(RuntimeError::PackageSyntaxError { err, info }),
     };
 
+    // #[id_range(groups = 800..900)] keeps the IDs of the module in its own range
+    if let Some(args) = notes.tag.get("id_range") {
+        let mut ranges: [Option<idrange::IdRange>; 4] = Default::default();
+        let tag_ranges = idrange::tag_ranges("id_range", &args).map_err(|message| {
+            RuntimeError::RuntimeError {
+                message,
+                info: info.clone(),
+            }
+        })?;
+        for (class, range) in tag_ranges {
+            ranges[class] = Some(range);
+        }
+        let (name, path) = match path {
+            ImportType::Lib(name) => (name.clone(), module_path.parent().unwrap().to_path_buf()),
+            ImportType::Script(p) => (p.to_string_lossy().to_string(), module_path.clone()),
+        };
+        if !globals.id_config.modules.iter().any(|m| m.path == path) {
+            globals
+                .id_config
+                .add_module(idrange::ModuleIds { name, path, ranges });
+        }
+    }
+
     let mut start_context = Context::new();
 
     let mut stored_impl = None;
     if let ImportType::Lib(_) = path {
         stored_impl = Some(globals.implementations.clone());
-        globals.implementations = HashMap::new();
+        globals.implementations = BTreeMap::new();
     }
 
     if !notes.tag.tags.iter().any(|x| x.0 == "no_std") {
@@ -1235,7 +1435,7 @@ pub fn import_module(
 
     let mut new_info = info;
 
-    new_info.current_file = module_path;
+    new_info.enter_file(module_path);
     new_info.pos = ((0, 0), (0, 0));
 
     if let ImportType::Lib(l) = path {
@@ -2,10 +2,14 @@
 use crate::ast;
 use crate::builtin::*;
 use crate::levelstring::*;
+use crate::idrange::IdConfig;
+use crate::lockfile::IdNames;
+use crate::objschema;
+use crate::target::{self, TargetProfile};
 
 use crate::parser::FileRange;
 //use std::boxed::Box;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 
@@ -17,9 +21,10 @@ use crate::compiler::{compile_scope, import_module, RuntimeError, BUILTIN_STORAG
 pub type TypeID = u16;
 //                                                               This bool is for if this value
 //                                                               was implemented in the current module
-pub type Implementations = HashMap<TypeID, HashMap<String, (StoredValue, bool)>>;
+pub type Implementations = BTreeMap<TypeID, BTreeMap<String, (StoredValue, bool)>>;
 pub type StoredValue = usize; //index to stored value in globals.stored_values
 
+#[derive(Clone)]
 pub struct ValStorage {
     pub map: HashMap<usize, StoredValData>, //val, fn context, mutable, lifetime
 }
@@ -345,6 +350,14 @@ pub struct CompilerInfo {
     pub current_module: String, // empty string means script
     pub pos: FileRange,
     pub includes: Vec<PathBuf>,
+    // macro calls and imports that went into another file, outermost first
+    pub call_stack: Vec<(PathBuf, FileRange)>,
+    // variables assigned by the statements that are being compiled, outermost first
+    // (like `a.b`), for naming arbitrary IDs in spwn.lock
+    pub assigned_vars: Option<String>,
+    // the statement in the main file that is being compiled, for naming arbitrary IDs
+    // made outside of any assignment
+    pub statement_label: Option<String>,
 }
 
 impl CompilerInfo {
@@ -356,8 +369,28 @@ impl CompilerInfo {
             current_module: String::new(),
             pos: ((0, 0), (0, 0)),
             includes: vec![],
+            call_stack: Vec::new(),
+            assigned_vars: None,
+            statement_label: None,
         }
     }
+
+    /// Where objects made right now come from
+    pub fn source_location(&self) -> SourceLocation {
+        SourceLocation {
+            file: self.current_file.clone(),
+            pos: self.pos,
+            call_stack: self.call_stack.clone(),
+        }
+    }
+
+    // enters code in another file, remembering where it was entered from
+    pub fn enter_file(&mut self, file: PathBuf) {
+        if file != self.current_file {
+            self.call_stack.push((self.current_file.clone(), self.pos));
+        }
+        self.current_file = file;
+    }
 }
 
 impl Context {
@@ -449,7 +482,11 @@ impl Context {
 // }
 
 //will merge one set of context, returning false if no mergable contexts were found
-pub fn merge_contexts(contexts: &mut SmallVec<[Context; CONTEXT_MAX]>, globals: &mut Globals) -> bool {
+pub fn merge_contexts(
+    contexts: &mut SmallVec<[Context; CONTEXT_MAX]>,
+    globals: &mut Globals,
+    info: &CompilerInfo,
+) -> bool {
     
     let mut mergable_ind = Vec::<usize>::new();
     let mut ref_c = 0;
@@ -501,10 +538,10 @@ pub fn merge_contexts(contexts: &mut SmallVec<[Context; CONTEXT_MAX]>, globals:
         }
     }
 
-    let new_group = Group::next_free(&mut globals.closed_groups);
+    let new_group = globals.next_start_group(&contexts[ref_c], info);
     //add spawn triggers
     let mut add_spawn_trigger = |context: &Context| {
-        let mut params = HashMap::new();
+        let mut params = BTreeMap::new();
         params.insert(
             51,
             ObjParam::Group(new_group),
@@ -516,7 +553,7 @@ pub fn merge_contexts(contexts: &mut SmallVec<[Context; CONTEXT_MAX]>, globals:
             (GDObj {
                 params,
 
-                ..context_trigger(&context,&mut globals.uid_counter)
+                ..context_trigger(&context,&mut globals.uid_counter, info)
             }
             .context_parameters(&context),globals.trigger_order)
         )
@@ -568,7 +605,7 @@ pub enum Value {
     Number(f64),
     Bool(bool),
     TriggerFunc(TriggerFunction),
-    Dict(HashMap<String, StoredValue>),
+    Dict(BTreeMap<String, StoredValue>),
     Macro(Box<Macro>),
     Str(String),
     Array(Vec<StoredValue>),
@@ -1121,11 +1158,32 @@ pub struct FunctionID {
 }
 
 pub type SyncPartID = usize;
+#[derive(Clone)]
 pub struct SyncGroup {
-    parts: Vec<SyncPartID>,
-    groups_used: Vec<ArbitraryID>, // groups that are already used by this sync group, and can be reused in later parts
+    pub name: String,
+    pub file: PathBuf,
+    pub parts: Vec<SyncPart>,
+    pub groups_used: Vec<ArbitraryID>, // groups that are already used by this sync group, and can be reused in later parts
+    pub entries: Vec<SyncEntry>,
+}
+
+#[derive(Clone)]
+pub struct SyncPart {
+    pub group: Group, // toggled on while this part runs, and off while any other part runs
+    pub groups_taken: usize, // number of groups_used this part is using
+}
+
+// a place where a part of a sync group is started
+#[derive(Clone)]
+pub struct SyncEntry {
+    pub part: SyncPartID,
+    // the toggle triggers are inserted here when all parts are known
+    pub index: usize,
+    pub order: usize,
+    pub trigger: GDObj,
 }
 
+#[derive(Clone)]
 pub struct Globals {
     //counters for arbitrary groups
     pub closed_groups: u16,
@@ -1153,6 +1211,17 @@ pub struct Globals {
     pub implementations: Implementations,
 
     pub sync_groups: Vec<SyncGroup>,
+
+    // names of the IDs made with ?g, ?c, ?b and ?i, for spwn.lock
+    pub id_names: IdNames,
+    // reserved IDs and the ID ranges of modules
+    pub id_config: IdConfig,
+    // the version of GD the level is built for
+    pub target: &'static TargetProfile,
+
+    // set by the repl, where every line continues the same scope: the next
+    // compile_scope call keeps the values of its variables alive
+    pub keep_scope_values: bool,
 }
 
 impl Globals {
@@ -1196,6 +1265,95 @@ impl Globals {
 
     
 
+    // start group for a new trigger function. parts of a sync group never run at the same time,
+    // so a part can reuse the groups other parts of its sync group have used
+    pub fn next_start_group(&mut self, context: &Context, info: &CompilerInfo) -> Group {
+        if context.sync_group == 0 {
+            let group = Group::next_free(&mut self.closed_groups);
+            self.id_config.claim(0, self.closed_groups, &info.current_file);
+            return group;
+        }
+        let sync_group = &mut self.sync_groups[context.sync_group];
+        let part = &mut sync_group.parts[context.sync_part];
+        let id = match sync_group.groups_used.get(part.groups_taken) {
+            Some(id) => *id,
+            None => {
+                self.closed_groups += 1;
+                self.id_config.claim(0, self.closed_groups, &info.current_file);
+                sync_group.groups_used.push(self.closed_groups);
+                self.closed_groups
+            }
+        };
+        part.groups_taken += 1;
+        Group { id: ID::Arbitrary(id) }
+    }
+
+    // finds the part of sync group `name` that toggles `group`, or adds it
+    pub fn get_sync_part(&mut self, name: &str, group: Group, file: &PathBuf) -> (usize, SyncPartID) {
+        let sync_group = match self
+            .sync_groups
+            .iter()
+            .position(|g| g.name == name && &g.file == file)
+        {
+            Some(i) => i,
+            None => {
+                self.sync_groups.push(SyncGroup {
+                    name: name.to_string(),
+                    file: file.clone(),
+                    parts: Vec::new(),
+                    groups_used: Vec::new(),
+                    entries: Vec::new(),
+                });
+                self.sync_groups.len() - 1
+            }
+        };
+        let parts = &mut self.sync_groups[sync_group].parts;
+        let part = match parts.iter().position(|p| p.group == group) {
+            Some(i) => i,
+            None => {
+                parts.push(SyncPart {
+                    group,
+                    groups_taken: 0,
+                });
+                parts.len() - 1
+            }
+        };
+        (sync_group, part)
+    }
+
+    // adds the toggle triggers at the start of every sync part, which turn the part on
+    // and all other parts of its sync group off
+    pub fn add_sync_toggles(&mut self) {
+        let mut inserts = Vec::new();
+        for sync_group in &self.sync_groups[1..] {
+            // a sync group with only one part doesn't need to be synced with anything
+            if sync_group.parts.len() < 2 {
+                continue;
+            }
+            for entry in &sync_group.entries {
+                let toggles: Vec<(GDObj, usize)> = sync_group
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, part)| {
+                        let mut trigger = entry.trigger.clone();
+                        trigger.params.insert(1, ObjParam::Number(1049.0));
+                        trigger.params.insert(51, ObjParam::Group(part.group));
+                        trigger.params.insert(56, ObjParam::Bool(i == entry.part));
+                        (trigger, entry.order)
+                    })
+                    .collect();
+                inserts.push((entry.trigger.func_id, entry.index, toggles));
+            }
+        }
+        // insert from the back so the indexes stay correct
+        inserts.sort_by_key(|(_, index, _)| std::cmp::Reverse(*index));
+        for (func_id, index, toggles) in inserts {
+            let obj_list = &mut self.func_ids[func_id].obj_list;
+            obj_list.splice(index..index, toggles);
+        }
+    }
+
     pub fn get_type_str(&self, p: StoredValue) -> String {
         let val = &self.stored_values[p];
         let typ = match val {
@@ -1242,11 +1400,22 @@ impl Globals {
                 obj_list: Vec::new(),
             }],
             objects: Vec::new(),
-            implementations: HashMap::new(),
+            implementations: BTreeMap::new(),
+            // sync group 0 is used for everything outside of sync blocks
             sync_groups: vec![SyncGroup {
-                parts: vec![0],
-                groups_used: Vec::new()
-            }]
+                name: String::new(),
+                file: PathBuf::new(),
+                parts: vec![SyncPart {
+                    group: Group::new(0),
+                    groups_taken: 0,
+                }],
+                groups_used: Vec::new(),
+                entries: Vec::new(),
+            }],
+            id_names: IdNames::default(),
+            id_config: IdConfig::default(),
+            target: &target::GD_2_1,
+            keep_scope_values: false,
         };
 
         
@@ -1747,7 +1916,7 @@ Should be used like this: value.macro(arguments)".to_string(), info
         new_contexts.push(new_context);
     }
     let mut new_info = info;
-    new_info.current_file = m.def_file;
+    new_info.enter_file(m.def_file);
     let mut compiled = compile_scope(&m.body, new_contexts, globals, new_info)?;
 
     // stop break chain
@@ -1858,7 +2027,7 @@ pub fn eval_dict(
     inner_returns.extend(returns);
     let mut out = Returns::new();
     for expressions in evaled {
-        let mut dict_out: HashMap<String, StoredValue> = HashMap::new();
+        let mut dict_out: BTreeMap<String, StoredValue> = BTreeMap::new();
         for (expr_index, def) in dict.iter().enumerate() {
             match def {
                 ast::DictDef::Def(d) => {
@@ -1932,28 +2101,44 @@ impl ast::Variable {
                     match id.class_name {
                         IDClass::Group => {
                             if id.unspecified {
-                                Value::Group(Group::next_free(&mut globals.closed_groups))
+                                let g = Group::next_free(&mut globals.closed_groups);
+                                globals.id_names.name(0, globals.closed_groups, &info);
+                                let file = &info.current_file;
+                                globals.id_config.claim(0, globals.closed_groups, file);
+                                Value::Group(g)
                             } else {
                                 Value::Group(Group::new(id.number))
                             }
                         }
                         IDClass::Color => {
                             if id.unspecified {
-                                Value::Color(Color::next_free(&mut globals.closed_colors))
+                                let c = Color::next_free(&mut globals.closed_colors);
+                                globals.id_names.name(1, globals.closed_colors, &info);
+                                let file = &info.current_file;
+                                globals.id_config.claim(1, globals.closed_colors, file);
+                                Value::Color(c)
                             } else {
                                 Value::Color(Color::new(id.number))
                             }
                         }
                         IDClass::Block => {
                             if id.unspecified {
-                                Value::Block(Block::next_free(&mut globals.closed_blocks))
+                                let b = Block::next_free(&mut globals.closed_blocks);
+                                globals.id_names.name(2, globals.closed_blocks, &info);
+                                let file = &info.current_file;
+                                globals.id_config.claim(2, globals.closed_blocks, file);
+                                Value::Block(b)
                             } else {
                                 Value::Block(Block::new(id.number))
                             }
                         }
                         IDClass::Item => {
                             if id.unspecified {
-                                Value::Item(Item::next_free(&mut globals.closed_items))
+                                let i = Item::next_free(&mut globals.closed_items);
+                                globals.id_names.name(3, globals.closed_items, &info);
+                                let file = &info.current_file;
+                                globals.id_config.claim(3, globals.closed_items, file);
+                                Value::Item(i)
                             } else {
                                 Value::Item(Item::new(id.number))
                             }
@@ -2219,6 +2404,8 @@ impl ast::Variable {
                 inner_returns.extend(returns);
                 for (expressions, context) in evaled {
                     let mut obj: Vec<(u16, ObjParam)> = Vec::new();
+                    // keys given as object keys, which are checked against the schema
+                    let mut checked_keys = Vec::new();
                     for i in 0..(o.props.len()) {
 
                         let o_key = expressions[i * 2]; 
@@ -2292,6 +2479,9 @@ impl ast::Variable {
                                 })
                             }
                         };
+                        if pattern.is_some() {
+                            checked_keys.push(key);
+                        }
 
                         obj.push((
                             key,
@@ -2369,6 +2559,15 @@ impl ast::Variable {
                             },
                         ))
                     }
+
+                    let obj_id = objschema::obj_id(&obj);
+                    for (key, value) in &obj {
+                        if checked_keys.contains(key) {
+                            if let Err(message) = objschema::check_prop(obj_id, *key, value) {
+                                return Err(RuntimeError::RuntimeError { message, info });
+                            }
+                        }
+                    }
                     
                     start_val.push((
                         store_const_value(Value::Obj(obj, o.mode), 1, globals, &context),
@@ -2669,7 +2868,7 @@ impl ast::Variable {
                                                         },
                                                         
                                                         ObjParam::Epsilon => {
-                                                            let mut map = HashMap::<String, StoredValue>::new();
+                                                            let mut map = BTreeMap::<String, StoredValue>::new();
                                                             let stored = store_const_value(Value::TypeIndicator(20), 1, globals, &index.1);
                                                             map.insert(TYPE_MEMBER_NAME.to_string(), stored);
                                                             Value::Dict(map)
@@ -3278,7 +3477,7 @@ impl ast::Variable {
                                 }
                             }
                             None => {
-                                let mut new_imp = HashMap::new();
+                                let mut new_imp = BTreeMap::new();
                                 new_imp.insert(m.clone(), (value, true));
                                 (*globals).implementations.insert(*t, new_imp);
                                 defined = false;
@@ -3331,7 +3530,7 @@ impl ast::CompoundStatement {
         let start_group = if let Some(g) = start_group {
             g
         } else {
-            Group::next_free(&mut globals.closed_groups)
+            globals.next_start_group(context, &info)
         };
 
         new_context.start_group = start_group;
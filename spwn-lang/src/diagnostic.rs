@@ -0,0 +1,264 @@
+//! Structured compiler errors that can be printed for humans or serialized for tools
+
+use crate::compiler::RuntimeError;
+use crate::parser::{FileRange, SyntaxError};
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value as JsonValue};
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+            Severity::Note => Color::Cyan,
+        }
+    }
+}
+
+/// A range of source code in a file, with an optional explanation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub file: PathBuf,
+    pub range: FileRange,
+    pub label: Option<String>,
+}
+
+impl Span {
+    pub fn new(file: PathBuf, range: FileRange) -> Self {
+        Span {
+            file,
+            range,
+            label: None,
+        }
+    }
+
+    pub fn with_label<S: Into<String>>(mut self, label: S) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    // lines are 1-based and columns 0-based in a FileRange, both are 1-based in json
+    fn to_json(&self, primary: bool) -> JsonValue {
+        json!({
+            "file": self.file.to_string_lossy(),
+            "start": { "line": self.range.0 .0, "column": self.range.0 .1 + 1 },
+            "end": { "line": self.range.1 .0, "column": self.range.1 .1 + 1 },
+            "primary": primary,
+            "label": self.label,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier for the kind of diagnostic, like `type_mismatch`
+    pub code: &'static str,
+    pub message: String,
+    /// Where the problem is. `None` for problems that aren't tied to the source, like a missing file
+    pub primary: Option<Span>,
+    /// Other places that are relevant to the problem
+    pub secondary: Vec<Span>,
+    pub notes: Vec<String>,
+    /// The `CompilerInfo::path` of the error, outermost scope first
+    pub call_stack: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error<S: Into<String>>(code: &'static str, message: S) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            call_stack: Vec::new(),
+        }
+    }
+
+    pub fn with_primary(mut self, span: Span) -> Self {
+        self.primary = Some(span);
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span) -> Self {
+        self.secondary.push(span);
+        self
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut spans = Vec::new();
+        if let Some(primary) = &self.primary {
+            spans.push(primary.to_json(true));
+        }
+        spans.extend(self.secondary.iter().map(|s| s.to_json(false)));
+        json!({
+            "severity": self.severity.as_str(),
+            "code": self.code,
+            "message": self.message,
+            "spans": spans,
+            "notes": self.notes,
+            "call_stack": self.call_stack,
+        })
+    }
+
+    /// Prints the diagnostic to stderr with colors and source snippets
+    pub fn eprint(&self) {
+        let mut stderr = StandardStream::stderr(ColorChoice::Always);
+        let mut write_with_color = |text: &str, color: Color| {
+            stderr
+                .set_color(ColorSpec::new().set_fg(Some(color)))
+                .unwrap();
+            write!(&mut stderr, "{}", text).unwrap();
+        };
+
+        let title = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+            Severity::Note => "Note",
+        };
+        write_with_color(title, self.severity.color());
+
+        if let Some(primary) = &self.primary {
+            write_with_color(
+                &format!(" at {}\n", location_str(&primary.file, primary.range)),
+                Color::White,
+            );
+            print_snippet(primary, self.severity.color(), &mut write_with_color);
+        } else {
+            write_with_color("\n", Color::White);
+        }
+
+        write_with_color(&format!("{}\n", self.message), Color::White);
+
+        for span in &self.secondary {
+            write_with_color(
+                &format!("\n{} ", span.label.as_deref().unwrap_or("Related")),
+                Color::Cyan,
+            );
+            write_with_color(
+                &format!("at {}\n", location_str(&span.file, span.range)),
+                Color::White,
+            );
+            print_snippet(span, Color::Cyan, &mut write_with_color);
+        }
+
+        for note in &self.notes {
+            write_with_color("note: ", Color::Cyan);
+            write_with_color(&format!("{}\n", note), Color::White);
+        }
+
+        if self.call_stack.len() > 1 {
+            write_with_color("call stack: ", Color::Cyan);
+            write_with_color(&format!("{}\n", self.call_stack.join(" > ")), Color::White);
+        }
+
+        stderr.set_color(&ColorSpec::new()).unwrap();
+        writeln!(&mut stderr).unwrap();
+    }
+}
+
+fn location_str(file: &Path, pos: FileRange) -> String {
+    format!("{}:{}:{}", file.to_string_lossy(), pos.0 .0, pos.0 .1 + 1)
+}
+
+fn print_snippet(span: &Span, color: Color, write_with_color: &mut impl FnMut(&str, Color)) {
+    let pos = span.range;
+    if pos.0 .0 != pos.1 .0 || pos.0 .0 == 0 {
+        return;
+    }
+    let line = match fs::read_to_string(&span.file) {
+        Ok(content) => match content.lines().nth(pos.0 .0 - 1) {
+            Some(line) => line.to_string(),
+            None => return,
+        },
+        Err(_) => return,
+    };
+
+    let line_num = pos.1 .0.to_string();
+    let spacing = " ".repeat(line_num.len());
+
+    write_with_color(&(spacing.clone() + " |\n" + &line_num + " |"), Color::Cyan);
+    write_with_color(&(line.replace("\t", " ") + "\n"), Color::White);
+    write_with_color(&(spacing + " |"), Color::Cyan);
+    let out = " ".repeat(pos.0 .1) + &"^".repeat(pos.1 .1.saturating_sub(pos.0 .1)) + "\n";
+    write_with_color(&out, color);
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.primary {
+            Some(span) => write!(
+                f,
+                "{} at {}: {}",
+                self.severity.as_str(),
+                location_str(&span.file, span.range),
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.severity.as_str(), self.message),
+        }
+    }
+}
+
+impl From<&SyntaxError> for Diagnostic {
+    fn from(err: &SyntaxError) -> Self {
+        let (code, pos, file) = match err {
+            SyntaxError::ExpectedErr { pos, file, .. } => ("expected_token", pos, file),
+            SyntaxError::UnexpectedErr { pos, file, .. } => ("unexpected_token", pos, file),
+            SyntaxError::SyntaxError { pos, file, .. } => ("syntax_error", pos, file),
+        };
+        Diagnostic::error(code, err.to_string()).with_primary(Span::new(file.clone(), *pos))
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Self {
+        let (code, info) = match err {
+            RuntimeError::UndefinedErr { info, .. } => ("undefined", info),
+            RuntimeError::PackageSyntaxError { info, .. } => ("library_syntax_error", info),
+            RuntimeError::TypeError { info, .. } => ("type_mismatch", info),
+            RuntimeError::RuntimeError { info, .. } => ("runtime_error", info),
+            RuntimeError::BuiltinError { info, .. } => ("builtin_error", info),
+        };
+
+        let import_span = Span::new(info.current_file.clone(), info.pos);
+        let mut diagnostic = match err {
+            // the error is in the library, point at it and at the import
+            RuntimeError::PackageSyntaxError { err, .. } => {
+                let mut diagnostic = Diagnostic::from(err);
+                diagnostic.code = code;
+                diagnostic.message = format!("Error when parsing library: {}", err);
+                diagnostic.with_secondary(import_span.with_label("Imported"))
+            }
+            _ => Diagnostic::error(code, err.to_string()).with_primary(import_span),
+        };
+        diagnostic.call_stack = info.path.clone();
+        diagnostic
+    }
+}
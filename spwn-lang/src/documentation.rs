@@ -8,7 +8,7 @@ use crate::compiler_types::{
 use std::fs::File;
 
 use std::path::PathBuf;
-use std::{collections::HashMap, env::current_dir};
+use std::{collections::BTreeMap, env::current_dir};
 fn create_doc_file(mut dir: PathBuf, name: String, content: &str) {
     use std::io::Write;
     dir.push(format!("{}.md", name));
@@ -85,14 +85,14 @@ pub fn document_lib(path: &str) -> Result<(), RuntimeError> {
     );
     doc += "# Type Implementations:\n";
 
-    let mut list: Vec<(&u16, HashMap<String, usize>)> = implementations
+    let mut list: Vec<(&u16, BTreeMap<String, usize>)> = implementations
         .iter()
         .map(|(key, val)| {
             (
                 key,
                 val.iter()
                     .map(|(key, val)| (key.clone(), val.0))
-                    .collect::<HashMap<String, usize>>(),
+                    .collect::<BTreeMap<String, usize>>(),
             )
         })
         .collect();
@@ -120,7 +120,7 @@ pub fn document_lib(path: &str) -> Result<(), RuntimeError> {
     Ok(())
 }
 
-fn document_dict(dict: &HashMap<String, usize>, globals: &mut Globals) -> String {
+fn document_dict(dict: &BTreeMap<String, usize>, globals: &mut Globals) -> String {
     let mut doc = String::new(); //String::from("<details>\n<summary> View members </summary>\n");
 
     let mut macro_list: Vec<(&String, &usize)> = dict
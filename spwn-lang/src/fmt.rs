@@ -1,6 +1,9 @@
 // tools for automatically formatting spwn files
 
 use crate::ast::*;
+use crate::compiler_types::ImportType;
+use crate::parser::{ParseNotes, Token};
+use logos::Logos;
 
 pub trait SpwnFmt {
     fn fmt(&self, ind: Indent) -> String;
@@ -8,24 +11,93 @@ pub trait SpwnFmt {
 
 type Indent = u16;
 
-fn tabs(mut num: Indent) -> String {
+fn tabs(num: Indent) -> String {
+    " ".repeat(num as usize)
+}
+
+/// Formats a parsed file, including the tags at the top of it and the comments above them
+pub fn format(statements: &[Statement], notes: &ParseNotes) -> String {
     let mut out = String::new();
-    while num > 4 {
-        out += "\t";
-        num -= 4;
+    let tag = &notes.tag;
+    if let Some(comment) = &notes.top_comment {
+        let (same_line, mut lines) = split_comment(comment);
+        if let Some(c) = same_line {
+            lines.insert(0, Some(c));
+        }
+        out += &indent_comment(&lines, 0);
+    }
+    if !tag.tags.is_empty() {
+        out += &format!("#{}\n", element_list(&tag.tags, '[', ']', 0));
+        if !statements.is_empty() {
+            out += "\n";
+        }
     }
 
-    for _ in 0..num {
-        out += " ";
+    out += &CompoundStatement {
+        statements: statements.to_vec(),
     }
+    .fmt(0);
 
-    out
+    // comments inside of expressions can leave trailing whitespace and blank lines behind
+    let mut cleaned = String::new();
+    let mut blank = false;
+    for line in out.lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank && !cleaned.is_empty() {
+            cleaned.push('\n');
+        }
+        blank = false;
+        cleaned += line;
+        cleaned.push('\n');
+    }
+    cleaned
+}
+
+/// Every comment in a script, with whitespace at the start of their lines taken out.
+/// Formatting should never change these
+pub fn comments(source: &str) -> Vec<String> {
+    Token::lexer(source)
+        .spanned()
+        .filter(|(t, _)| matches!(t, Token::Comment | Token::MultiCommentStart))
+        .map(|(_, range)| {
+            source[range]
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect()
 }
 
-pub fn _format(input: Vec<Statement>) -> String {
+// one entry per line, followed by a comma
+fn vertical_entry(el: &str, ind: Indent) -> String {
     let mut out = String::new();
-    for s in input {
-        out += &s.fmt(0).to_string();
+    let lines: Vec<&str> = el.lines().collect();
+    // the comma goes after the element, not after the comments following it
+    let comma_line = lines
+        .iter()
+        .rposition(|l| !l.trim_start().starts_with("//"))
+        .unwrap_or(lines.len() - 1);
+
+    for (i, line) in lines.iter().enumerate() {
+        if !line.is_empty() {
+            out += &tabs(ind);
+        }
+        if i == comma_line {
+            let (code, comment) = line.split_at(comment_start(line));
+            out += code.trim_end();
+            out.push(',');
+            if !comment.is_empty() {
+                out.push(' ');
+                out += comment;
+            }
+        } else {
+            out += line;
+        }
+        out.push('\n');
     }
     out
 }
@@ -41,14 +113,17 @@ fn element_list(elements: &[impl SpwnFmt], open: char, closing: char, ind: Inden
     let last = elements.len() - 1;
 
     for (_i, el) in elements.iter().enumerate() {
-        let text = el.fmt(0);
+        let text = el.fmt(0).trim_start_matches('\n').to_string();
 
-        sum += text.lines().next().unwrap().len();
+        sum += text.lines().next().unwrap_or_default().len();
 
         elem_text.push(text)
     }
 
-    let vertical = if elements.len() == 1 {
+    let vertical = if elem_text.iter().any(|x| has_line_comment(x)) {
+        // a comment would swallow everything after it on the line
+        true
+    } else if elements.len() == 1 {
         sum > 150
     } else {
         elem_text.iter().enumerate().any(|(i, x)| {
@@ -64,17 +139,8 @@ fn element_list(elements: &[impl SpwnFmt], open: char, closing: char, ind: Inden
         let mut out = format!("{}\n", open);
 
         for el in &elem_text {
-            for line in el.lines() {
-                out += &format!("{}{}\n", tabs(ind + 4), line);
-            }
-            out.pop();
-            out += ",\n";
+            out += &vertical_entry(el, ind + 4);
         }
-        /*if elements.len() == 1 {
-            out.pop();
-            out.pop();
-            out += "\n";
-        }*/
 
         out + &format!("{}{}", tabs(ind), closing)
     } else {
@@ -87,10 +153,14 @@ fn element_list(elements: &[impl SpwnFmt], open: char, closing: char, ind: Inden
         }
 
         let mut last_elem_lines = last_elem.lines();
-        out += &last_elem_lines.next().unwrap();
+        out += last_elem_lines.next().unwrap_or_default();
 
         for line in last_elem_lines {
-            out += &format!("\n{}{}", tabs(ind), line);
+            if line.is_empty() {
+                out.push('\n');
+            } else {
+                out += &format!("\n{}{}", tabs(ind), line);
+            }
         }
 
         out.push(closing);
@@ -98,6 +168,39 @@ fn element_list(elements: &[impl SpwnFmt], open: char, closing: char, ind: Inden
     }
 }
 
+// where a comment at the end of a line starts, skipping over strings
+fn comment_start(line: &str) -> usize {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                } else if c == '/' && previous == '/' {
+                    return i - 1;
+                }
+            }
+        }
+        previous = c;
+    }
+    line.len()
+}
+
+fn has_line_comment(text: &str) -> bool {
+    text.lines().any(|l| l.trim_start().starts_with("//"))
+}
+
 impl SpwnFmt for DictDef {
     fn fmt(&self, ind: Indent) -> String {
         match self {
@@ -107,107 +210,134 @@ impl SpwnFmt for DictDef {
     }
 }
 
-fn trim_start_tabs(string: &str) -> (&str, Indent) {
-    //https://doc.rust-lang.org/src/core/str/mod.rs.html#4082-4090
-    let mut ind = 0;
-    for (i, c) in string.chars().enumerate() {
-        match c {
-            '\t' => ind += 4,
-            ' ' => ind += 1,
-            _ => return (unsafe { string.get_unchecked(i..string.len()) }, ind),
+// a comment is the text between two tokens: the comment on the line the text starts on,
+// and the lines after it (`None` for a blank line)
+fn split_comment(comment: &str) -> (Option<&str>, Vec<Option<&str>>) {
+    let mut segments = comment_lines(comment).into_iter().map(|s| s.trim());
+    let same_line = segments.next().filter(|s| !s.is_empty());
+    let mut lines: Vec<Option<&str>> = segments
+        .map(|s| if s.is_empty() { None } else { Some(s) })
+        .collect();
+    // the last segment is only the indentation of the next token
+    if lines.last() == Some(&None) {
+        lines.pop();
+    }
+    (same_line, lines)
+}
+
+// splits a comment into lines, keeping block comments that span several lines together
+// so the text in them stays exactly as it was written
+fn comment_lines(comment: &str) -> Vec<&str> {
+    let bytes = comment.as_bytes();
+    let mut lines = Vec::new();
+    let mut depth = 0;
+    let mut line_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if depth > 0 && bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+        } else if depth == 0 && bytes[i..].starts_with(b"//") {
+            // a line comment can't start a block comment
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else {
+            if depth == 0 && bytes[i] == b'\n' {
+                lines.push(&comment[line_start..i]);
+                line_start = i + 1;
+            }
+            i += 1;
         }
     }
-    ("", ind)
-}
-
-fn indent_comment(comment: &str, ind: Indent) -> String {
-    let mut in_comment = false;
-    let mut current_off = 0;
-    let mut out = String::new();
-    for line in comment.lines() {
-        let (trimmed, ind_offset) = trim_start_tabs(line);
-        if !in_comment {
-            if trimmed.starts_with("//") {
-                out += &format!("{}{}\r\n", tabs(ind), trimmed);
-            } else if trimmed.starts_with("/*") {
-                in_comment = true;
-                current_off = ind_offset;
-                out += &format!("{}{}\r\n", tabs(ind), trimmed);
+    lines.push(&comment[line_start..]);
+    lines
+}
+
+// block comments can be followed by more code on the same line, unlike line comments
+fn ends_with_block_comment(line: &str) -> bool {
+    let bytes = line.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if depth > 0 && bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 && i == bytes.len() {
+                return true;
             }
+        } else if depth == 0 && bytes[i..].starts_with(b"//") {
+            return false;
         } else {
-            out += &format!("{}{}\r\n", tabs(ind_offset - current_off), trimmed);
-        }
-
-        if line.trim_end().ends_with("*/") {
-            in_comment = false
+            i += 1;
         }
     }
-    out
+    false
 }
 
-/*#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn test() {
-        println!(
-            "{}",
-            indent_comment(
-                &String::from(
-                    "
-//hello
-                /*
-                a = {
-                    b = 2
-                    c = 3
-                    a = {
-                        b = 2
-                        c = 3
-                        a = {
-                            b = 2
-                            c = 3
-                        }
-                    }
+// comment lines indented to `ind`, with at most one blank line in a row
+fn indent_comment(lines: &[Option<&str>], ind: Indent) -> String {
+    let mut out = String::new();
+    let mut blank = false;
+    for line in lines {
+        match line {
+            Some(line) => {
+                if blank {
+                    out.push('\n');
+                    blank = false;
                 }
-                */
-
-//bruh
-    //bruh
-        //bruh
-        "
-                ),
-                0
-            )
-        )
+                out += &format!("{}{}\n", tabs(ind), line);
+            }
+            None => blank = true,
+        }
+    }
+    if blank {
+        out.push('\n');
     }
-}*/
+    out
+}
 
 impl SpwnFmt for Statement {
     fn fmt(&self, ind: Indent) -> String {
         let mut out = String::new();
+        let mut inline = None;
         if let Some(comment) = &self.comment.0 {
-            //out += "[stmt pre]";
-
-            out += &indent_comment(comment, ind);
-            //
-            if !comment.ends_with('\n') {
-                out += "\n";
+            let (same_line, mut lines) = split_comment(comment);
+            match same_line {
+                // `/* ... */ statement` stays on one line
+                Some(c) if lines.is_empty() && ends_with_block_comment(c) => inline = Some(c),
+                Some(c) => lines.insert(0, Some(c)),
+                None => (),
             }
-            out += &tabs(ind);
+            out += &indent_comment(&lines, ind);
         }
-        out += &if self.arrow {
-            format!("-> {}\n", self.body.fmt(ind))
-        } else {
-            format!("{}\n", self.body.fmt(ind))
-        };
-        if let Some(comment) = &self.comment.1 {
-            if !comment.starts_with('\n') {
-                out += "\n";
-            }
 
-            //out += &tabs(ind);
-            //out += "[stmt post]";
-            out += &indent_comment(comment, ind);
+        out += &tabs(ind);
+        if let Some(c) = inline {
+            out += &format!("{} ", c);
+        }
+        if self.arrow {
+            out += "-> ";
+        }
+        out += &self.body.fmt(ind);
+
+        match &self.comment.1 {
+            Some(comment) => {
+                let (same_line, lines) = split_comment(comment);
+                if let Some(c) = same_line {
+                    out += &format!(" {}", c);
+                }
+                out.push('\n');
+                // comments at the end of a block, keeping a blank line above them if there was one
+                out += &indent_comment(&lines, ind);
+            }
+            None => out.push('\n'),
         }
         out
     }
@@ -219,7 +349,7 @@ impl SpwnFmt for StatementBody {
             //StatementBody::Definition(def) => format!("{}", def.fmt(ind)),
             StatementBody::Call(call) => call.fmt(ind),
             StatementBody::Expr(x) => x.fmt(ind),
-            StatementBody::TypeDef(x) => format!("type {}", x),
+            StatementBody::TypeDef(x) => format!("type @{}", x),
             StatementBody::Return(x) => match x {
                 Some(expr) => format!("return {}", expr.fmt(ind)),
                 None => "return".to_string(),
@@ -227,10 +357,13 @@ impl SpwnFmt for StatementBody {
             StatementBody::Impl(x) => x.fmt(ind),
             StatementBody::If(x) => x.fmt(ind),
             StatementBody::For(x) => x.fmt(ind),
+            StatementBody::While(x) => x.fmt(ind),
+            StatementBody::Sync(x) => x.fmt(ind),
             StatementBody::Error(x) => x.fmt(ind),
             StatementBody::Extract(x) => format!("extract {}", x.fmt(ind)),
             StatementBody::Break => String::from("break"),
             StatementBody::Continue => String::from("continue"),
+            StatementBody::Invalid(x) => x.clone(),
         }
     }
 }
@@ -242,24 +375,47 @@ impl SpwnFmt for (Expression, Expression) {
     }
 }
 
+fn escape_str(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            c => out.push(c),
+        }
+    }
+    out
+}
+
 impl SpwnFmt for ValueBody {
     fn fmt(&self, ind: Indent) -> String {
         use ValueBody::*;
         match self {
             ID(x) => x.fmt(ind),
             Number(x) => format!("{}", x),
-            CmpStmt(x) => format!("{{\n{}\n{}}}", x.fmt(ind + 4), tabs(ind)),
+            CmpStmt(x) => format!("!{{\n{}\n{}}}", x.fmt(ind + 4), tabs(ind)),
             Dictionary(x) => element_list(x, '{', '}', ind),
             Array(x) => element_list(x, '[', ']', ind),
             Symbol(x) => x.to_string(),
             Bool(x) => format!("{}", x),
             Expression(x) => format!("({})", x.fmt(ind)),
-            Str(x) => format!("\"{}\"", x),
-            Import(x, f) => format!("import{} {:?}", if *f { "!" } else { "" }, x),
+            Str(x) => format!("\"{}\"", escape_str(x)),
+            Import(x, f) => format!(
+                "import{} {}",
+                if *f { "!" } else { "" },
+                match x {
+                    ImportType::Script(path) =>
+                        format!("\"{}\"", escape_str(&path.to_string_lossy())),
+                    ImportType::Lib(name) => name.clone(),
+                }
+            ),
             Obj(x) => {
                 (match x.mode {
-                    ObjectMode::Object => "obj".to_string(),
-                    ObjectMode::Trigger => "trigger".to_string(),
+                    ObjectMode::Object => "obj ".to_string(),
+                    ObjectMode::Trigger => "trigger ".to_string(),
                 }) + &element_list(&x.props, '{', '}', ind)
             }
             Macro(x) => x.fmt(ind),
@@ -267,12 +423,39 @@ impl SpwnFmt for ValueBody {
             TypeIndicator(x) => format!("@{}", x),
             Null => "null".to_string(),
             SelfVal => "self".to_string(),
-            Ternary(t) => format!("{} if {} else {}", t.do_if.fmt(ind), t.conditional.fmt(ind), t.do_else.fmt(ind)),
-            Switch(_, _) => "switch".to_string(),
+            Ternary(t) => t.fmt(ind),
+            Switch(value, cases) => {
+                let mut out = format!("switch {} {{\n", value.fmt(ind));
+                for case in cases {
+                    out += &vertical_entry(case.fmt(0).trim_start_matches('\n'), ind + 4);
+                }
+                out + &tabs(ind) + "}"
+            }
+        }
+    }
+}
+
+impl SpwnFmt for Case {
+    fn fmt(&self, ind: Indent) -> String {
+        match &self.typ {
+            CaseType::Value(v) => format!("case {}: {}", v.fmt(ind), self.body.fmt(ind)),
+            CaseType::Pattern(p) => format!("{}: {}", p.fmt(ind), self.body.fmt(ind)),
+            CaseType::Default => format!("else: {}", self.body.fmt(ind)),
         }
     }
 }
 
+impl SpwnFmt for Ternary {
+    fn fmt(&self, ind: Indent) -> String {
+        format!(
+            "{} if {} else {}",
+            self.do_if.fmt(ind),
+            self.conditional.fmt(ind),
+            self.do_else.fmt(ind)
+        )
+    }
+}
+
 impl SpwnFmt for ValueLiteral {
     fn fmt(&self, ind: Indent) -> String {
         self.body.fmt(ind)
@@ -324,14 +507,31 @@ impl SpwnFmt for Call {
 impl SpwnFmt for For {
     fn fmt(&self, ind: Indent) -> String {
         format!(
-            "for {} in {} {{\n{}\n{}}}",
+            "for {} in {} {}",
             self.symbol,
             self.array.fmt(ind),
-            CompoundStatement {
-                statements: self.body.clone()
-            }
-            .fmt(ind + 4),
-            tabs(ind)
+            block(&self.body, ind)
+        )
+    }
+}
+
+impl SpwnFmt for While {
+    fn fmt(&self, ind: Indent) -> String {
+        format!(
+            "while {} {}",
+            self.condition.fmt(ind),
+            block(&self.body, ind)
+        )
+    }
+}
+
+impl SpwnFmt for SyncBlock {
+    fn fmt(&self, ind: Indent) -> String {
+        format!(
+            "sync {}[{}] {}",
+            self.group,
+            self.part.fmt(ind),
+            block(&self.body, ind)
         )
     }
 }
@@ -341,14 +541,28 @@ impl SpwnFmt for Variable {
         let mut out = String::new();
 
         if let Some(comment) = &self.comment.0 {
-            //out += "[var pre]";
-            out += &indent_comment(comment, ind);
-
-            if comment.ends_with('\n') {
+            let (same_line, mut lines) = split_comment(comment);
+            match same_line {
+                // `/* ... */ value` stays on one line
+                Some(c) if lines.iter().all(Option::is_none) && ends_with_block_comment(c) => {
+                    out += &format!("{} ", c);
+                }
+                Some(c) => lines.insert(0, Some(c)),
+                None => (),
+            }
+            let lines: Vec<Option<&str>> = lines.into_iter().flatten().map(Some).collect();
+            if !lines.is_empty() {
+                out += "\n";
+                out += &indent_comment(&lines, ind);
                 out += &tabs(ind);
             }
         }
 
+        // macros print their own tags
+        if !matches!(self.value.body, ValueBody::Macro(_)) {
+            out += &self.tag.fmt(ind);
+        }
+
         if let Some(op) = &self.operator {
             out += &op.fmt(ind);
         }
@@ -360,31 +574,76 @@ impl SpwnFmt for Variable {
         }
 
         if let Some(comment) = &self.comment.1 {
-            //out += "[var post]";
-
-            out += &indent_comment(comment, ind);
-
-            /*if comment.ends_with("\n") {
+            let (same_line, lines) = split_comment(comment);
+            if let Some(c) = same_line {
+                out += &format!(" {}", c);
+            }
+            let lines: Vec<Option<&str>> = lines.into_iter().flatten().map(Some).collect();
+            if matches!(same_line, Some(c) if !ends_with_block_comment(c)) || !lines.is_empty() {
+                out += "\n";
+                out += &indent_comment(&lines, ind);
                 out += &tabs(ind);
-            }*/
+            }
         }
 
         out
     }
 }
 
+// lowest precedence of the operators in an expression, like in the parser
+fn precedence(expr: &Expression) -> Option<u8> {
+    expr.operators.iter().map(operator_precedence).min()
+}
+
+fn operator_precedence(op: &Operator) -> u8 {
+    use Operator::*;
+    match op {
+        As => 10,
+        Power => 9,
+        Either => 8,
+        Modulo | Star | Slash | IntDividedBy => 7,
+        Plus | Minus => 6,
+        Range => 5,
+        MoreOrEqual | LessOrEqual => 4,
+        More | Less => 3,
+        Equal | Has | NotEqual => 2,
+        Or | And => 1,
+        Assign | Add | Subtract | Multiply | Divide | IntDivide | Exponate | Modulate | Swap => 0,
+    }
+}
+
 impl SpwnFmt for Expression {
     fn fmt(&self, ind: Indent) -> String {
         let mut out = String::new();
+        let outer = precedence(self);
+
+        // the parser nests operators with a higher precedence in parentheses
+        let value = |i: usize| {
+            let var = &self.values[i];
+            if let (ValueBody::Expression(inner), Some(outer)) = (&var.value.body, outer) {
+                let plain = var.operator.is_none()
+                    && var.path.is_empty()
+                    && var.tag.tags.is_empty()
+                    && var.comment == (None, None);
+                if let (true, Some(p)) = (plain, precedence(inner)) {
+                    // operators with the same precedence are grouped from the left
+                    if p > outer || (p == outer && i == 0 && outer != 0) {
+                        return inner.fmt(ind);
+                    }
+                }
+            }
+            var.fmt(ind)
+        };
+
         for (i, op) in self.operators.iter().enumerate() {
             if let Operator::Range = op {
-                out += &format!("{}{}", self.values[i].fmt(ind), (*op).fmt(ind));
+                out += &format!("{}{}", value(i), (*op).fmt(ind));
             } else {
-                out += &format!("{} {} ", self.values[i].fmt(ind), (*op).fmt(ind));
+                out += &format!("{} {} ", value(i), (*op).fmt(ind));
             }
         }
 
-        out += &self.values.last().unwrap().fmt(ind);
+        out += &value(self.values.len() - 1);
 
         out
     }
@@ -458,16 +717,7 @@ impl SpwnFmt for Definition {
 
 impl SpwnFmt for Error {
     fn fmt(&self, ind: Indent) -> String {
-        format!("error {}", self.message.fmt(ind))
-    }
-}
-
-fn trim_newline(s: &mut String) {
-    if s.ends_with('\n') {
-        s.pop();
-        if s.ends_with('\r') {
-            s.pop();
-        }
+        format!("throw {}", self.message.fmt(ind))
     }
 }
 
@@ -475,16 +725,43 @@ impl SpwnFmt for CompoundStatement {
     fn fmt(&self, ind: Indent) -> String {
         let mut out = String::new();
 
-        for s in &self.statements {
-            out += &format!("{}{}", tabs(ind), s.fmt(ind));
+        for (i, s) in self.statements.iter().enumerate() {
+            let text = s.fmt(ind);
+            if i == 0 {
+                out += text.trim_start_matches('\n');
+            } else {
+                // keep a blank line where there was one
+                let previous = &self.statements[i - 1];
+                if s.comment.0.is_none() && s.pos.0 .0 > previous.pos.1 .0 + 1 {
+                    out.push('\n');
+                }
+                out += &text;
+            }
         }
 
-        trim_newline(&mut out);
+        while out.ends_with('\n') {
+            out.pop();
+        }
 
         out
     }
 }
 
+// a block of statements, including the curly brackets
+fn block(statements: &[Statement], ind: Indent) -> String {
+    if statements.is_empty() {
+        return "{}".to_string();
+    }
+    format!(
+        "{{\n{}\n{}}}",
+        CompoundStatement {
+            statements: statements.to_vec()
+        }
+        .fmt(ind + 4),
+        tabs(ind)
+    )
+}
+
 impl SpwnFmt for Implementation {
     fn fmt(&self, ind: Indent) -> String {
         format!("impl {} ", self.symbol.fmt(ind)) + &element_list(&self.members, '{', '}', ind)
@@ -494,24 +771,21 @@ impl SpwnFmt for Implementation {
 impl SpwnFmt for If {
     fn fmt(&self, ind: Indent) -> String {
         let mut out = format!(
-            "if {} {{\n{}\n{}}}",
+            "if {} {}",
             self.condition.fmt(ind),
-            CompoundStatement {
-                statements: self.if_body.clone()
-            }
-            .fmt(ind + 4),
-            tabs(ind)
+            block(&self.if_body, ind)
         );
 
         if let Some(body) = &self.else_body {
-            out += &format!(
-                " else {{\n{}\n{}}}",
-                CompoundStatement {
-                    statements: body.clone()
-                }
-                .fmt(ind + 4),
-                tabs(ind)
-            );
+            match &body[..] {
+                [Statement {
+                    body: StatementBody::If(else_if),
+                    arrow: false,
+                    comment: (None, None),
+                    ..
+                }] => out += &format!(" else {}", else_if.fmt(ind)),
+                _ => out += &format!(" else {}", block(body, ind)),
+            }
         }
 
         out
@@ -542,14 +816,18 @@ impl SpwnFmt for Macro {
         out += &self.properties.fmt(ind);
 
         out += &element_list(&self.args, '(', ')', ind);
-        out += &format!(" {{\n{}\n{}}}", &self.body.fmt(ind + 4), tabs(ind));
+        out += &format!(" {}", block(&self.body.statements, ind));
         out
     }
 }
 
 impl SpwnFmt for (String, Vec<Argument>) {
     fn fmt(&self, ind: Indent) -> String {
-        self.0.clone() + &element_list(&self.1, '(', ')', ind)
+        if self.1.is_empty() {
+            self.0.clone()
+        } else {
+            self.0.clone() + &element_list(&self.1, '(', ')', ind)
+        }
     }
 }
 
@@ -0,0 +1,275 @@
+//! Trigger graph of a compiled script, for viewing how trigger functions connect
+//!
+//! Every group that has triggers in it becomes a node, and every trigger that activates
+//! another group (spawn, toggle, collision, ...) becomes an edge to that group.
+
+use crate::ast::ObjectMode;
+use crate::builtin::{Group, ID};
+use crate::compiler_types::FunctionID;
+use crate::levelstring::{GDObj, ObjParam};
+use crate::optimize::{get_role, TriggerRole};
+use crate::target::TargetProfile;
+
+use serde_json::{json, Value as JsonValue};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Spawn,
+    Toggle { on: bool },
+    Collision,
+    Count,
+    Touch,
+    Death,
+}
+
+impl EdgeKind {
+    fn from_trigger(obj: &GDObj) -> Option<Self> {
+        Some(match obj.obj_id()? {
+            1268 => EdgeKind::Spawn,
+            1049 => EdgeKind::Toggle {
+                on: matches!(obj.params.get(&56), Some(ObjParam::Bool(true))),
+            },
+            1815 => EdgeKind::Collision,
+            1611 | 1811 => EdgeKind::Count,
+            1595 => EdgeKind::Touch,
+            1812 => EdgeKind::Death,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EdgeKind::Spawn => "spawn",
+            EdgeKind::Toggle { on: true } => "toggle on",
+            EdgeKind::Toggle { on: false } => "toggle off",
+            EdgeKind::Collision => "collision",
+            EdgeKind::Count => "count",
+            EdgeKind::Touch => "touch",
+            EdgeKind::Death => "death",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub group: Group,
+    /// Function IDs that have triggers in this group
+    pub func_ids: Vec<usize>,
+    /// Object ID and role of every trigger in the group
+    pub triggers: Vec<(u16, TriggerRole)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub from: Group,
+    pub to: Group,
+    pub kind: EdgeKind,
+    /// Spawn delay in seconds
+    pub delay: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TriggerGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Trigger graphs from before and after optimization
+#[derive(Debug, Clone)]
+pub struct GraphViews {
+    pub before: TriggerGraph,
+    /// `None` if the script wasn't optimized
+    pub after: Option<TriggerGraph>,
+}
+
+// the main group is group 0
+fn trigger_groups(obj: &GDObj) -> Vec<Group> {
+    match obj.params.get(&57) {
+        Some(ObjParam::Group(g)) => vec![*g],
+        Some(ObjParam::GroupList(l)) if !l.is_empty() => l.clone(),
+        _ => vec![Group::new(0)],
+    }
+}
+
+fn group_name(group: Group) -> String {
+    match group.id {
+        ID::Specific(0) => "main".to_string(),
+        ID::Specific(n) => format!("{}g", n),
+        ID::Arbitrary(n) => format!("?{}g", n),
+    }
+}
+
+fn node_id(prefix: &str, group: Group) -> String {
+    match group.id {
+        ID::Specific(n) => format!("{}g{}", prefix, n),
+        ID::Arbitrary(n) => format!("{}a{}", prefix, n),
+    }
+}
+
+impl TriggerGraph {
+    pub fn new(func_ids: &[FunctionID], target: &TargetProfile) -> Self {
+        let mut graph = TriggerGraph::default();
+        let mut node_indexes = HashMap::<Group, usize>::new();
+
+        for (f, fn_id) in func_ids.iter().enumerate() {
+            for (obj, _) in &fn_id.obj_list {
+                let obj_id = match (obj.mode, obj.obj_id()) {
+                    (ObjectMode::Trigger, Some(id)) => id,
+                    _ => continue,
+                };
+                let hd = matches!(obj.params.get(&103), Some(ObjParam::Bool(true)));
+                let role = get_role(obj_id, hd, target);
+                let groups = trigger_groups(obj);
+
+                for group in &groups {
+                    let index = *node_indexes.entry(*group).or_insert_with(|| {
+                        graph.nodes.push(Node {
+                            group: *group,
+                            func_ids: Vec::new(),
+                            triggers: Vec::new(),
+                        });
+                        graph.nodes.len() - 1
+                    });
+                    let node = &mut graph.nodes[index];
+                    if !node.func_ids.contains(&f) {
+                        node.func_ids.push(f);
+                    }
+                    node.triggers.push((obj_id, role));
+                }
+
+                let target = match (EdgeKind::from_trigger(obj), obj.params.get(&51)) {
+                    (Some(kind), Some(ObjParam::Group(target))) => (kind, *target),
+                    _ => continue,
+                };
+                let delay = match obj.params.get(&63) {
+                    Some(ObjParam::Epsilon) => Some(0.0),
+                    _ => obj.param_number(63),
+                };
+                for group in groups {
+                    graph.edges.push(Edge {
+                        from: group,
+                        to: target.1,
+                        kind: target.0,
+                        delay,
+                    });
+                }
+            }
+        }
+
+        // groups that are only targeted still get a node
+        let targets: Vec<Group> = graph.edges.iter().map(|e| e.to).collect();
+        for group in targets {
+            if let Entry::Vacant(e) = node_indexes.entry(group) {
+                e.insert(graph.nodes.len());
+                graph.nodes.push(Node {
+                    group,
+                    func_ids: Vec::new(),
+                    triggers: Vec::new(),
+                });
+            }
+        }
+        graph
+    }
+
+    // nodes and edges of the graph in the DOT language, with node IDs starting with `prefix`
+    fn dot_body(&self, prefix: &str, indent: &str) -> String {
+        let mut out = String::new();
+        for node in &self.nodes {
+            let mut roles = [0; 3];
+            for (_, role) in &node.triggers {
+                roles[match role {
+                    TriggerRole::Spawn => 0,
+                    TriggerRole::Func => 1,
+                    TriggerRole::Output => 2,
+                }] += 1;
+            }
+            let mut label = group_name(node.group);
+            for (count, name) in roles.iter().zip(&["spawn", "func", "output"]) {
+                if *count > 0 {
+                    label += &format!("\\n{} {}", count, name);
+                }
+            }
+            let shape = if node.triggers.is_empty() {
+                "ellipse"
+            } else {
+                "box"
+            };
+            out += &format!(
+                "{}{} [label=\"{}\", shape={}];\n",
+                indent,
+                node_id(prefix, node.group),
+                label,
+                shape
+            );
+        }
+        for edge in &self.edges {
+            let label = match edge.delay {
+                Some(d) if d > 0.0 => format!("{} ({}s)", edge.kind.as_str(), d),
+                _ => edge.kind.as_str().to_string(),
+            };
+            let style = match edge.kind {
+                EdgeKind::Spawn => "solid",
+                EdgeKind::Toggle { .. } => "dashed",
+                _ => "bold",
+            };
+            out += &format!(
+                "{}{} -> {} [label=\"{}\", style={}];\n",
+                indent,
+                node_id(prefix, edge.from),
+                node_id(prefix, edge.to),
+                label,
+                style
+            );
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "nodes": self.nodes.iter().map(|n| json!({
+                "id": node_id("", n.group),
+                "group": group_name(n.group),
+                "func_ids": n.func_ids,
+                "triggers": n.triggers.iter().map(|(obj_id, role)| json!({
+                    "obj_id": obj_id,
+                    "role": format!("{:?}", role).to_lowercase(),
+                })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "edges": self.edges.iter().map(|e| json!({
+                "from": node_id("", e.from),
+                "to": node_id("", e.to),
+                "kind": e.kind.as_str(),
+                "delay": e.delay,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl GraphViews {
+    /// Graphviz DOT graph with a cluster for each view
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph triggers {\n    rankdir=LR;\n");
+        let mut views = vec![("before", "before optimization", &self.before)];
+        if let Some(after) = &self.after {
+            views.push(("after", "after optimization", after));
+        }
+        for (prefix, label, graph) in views {
+            out += &format!(
+                "    subgraph cluster_{} {{\n        label=\"{}\";\n",
+                prefix, label
+            );
+            out += &graph.dot_body(&format!("{}_", prefix), "        ");
+            out += "    }\n";
+        }
+        out + "}\n"
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "before": self.before.to_json(),
+            "after": self.after.as_ref().map(|g| g.to_json()),
+        })
+    }
+}
@@ -0,0 +1,155 @@
+//! Which specific IDs arbitrary IDs can get
+//!
+//! Ranges can be reserved with `#[reserve(groups = 1..200)]` at the top of the main file
+//! (or `--reserve-groups 1..200`), so they are left for objects placed by hand. A module
+//! can keep its IDs in a range of its own with `#[id_range(items = 500..600)]` at the top of
+//! its file. IDs made by code in the module are only taken from that range, and IDs made
+//! anywhere else never are.
+
+use crate::ast::{Argument, Operator, ValueBody};
+use crate::builtin::{ArbitraryID, SpecificID};
+
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Names of the ID classes, as used in tags and command line flags
+pub const ID_CLASSES: [&str; 4] = ["groups", "colors", "blocks", "items"];
+
+pub type IdRange = Range<SpecificID>;
+
+/// Parses a range like `500..600`
+pub fn parse_range(s: &str) -> Result<IdRange, String> {
+    let invalid = || format!("Invalid ID range \"{}\", expected something like 1..200", s);
+    let mut parts = s.splitn(2, "..");
+    let start = parts.next().and_then(|n| n.trim().parse().ok());
+    let end = parts.next().and_then(|n| n.trim().parse().ok());
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => Ok(start..end),
+        _ => Err(invalid()),
+    }
+}
+
+/// Ranges in the arguments of a tag like `#[reserve(groups = 1..200, items = 500..600)]`,
+/// with the index of their ID class
+pub fn tag_ranges(tag: &str, args: &[Argument]) -> Result<Vec<(usize, IdRange)>, String> {
+    let mut out = Vec::new();
+    for arg in args {
+        let class = match &arg.symbol {
+            Some(name) => ID_CLASSES.iter().position(|c| c == name),
+            None => None,
+        };
+        let class = class.ok_or_else(|| {
+            format!(
+                "Arguments of #[{}] should be named {}",
+                tag,
+                ID_CLASSES.join(", ")
+            )
+        })?;
+
+        let number = |i: usize| match arg.value.values.get(i).map(|v| &v.value.body) {
+            Some(ValueBody::Number(n)) if n.fract() == 0.0 && *n >= 0.0 => Some(*n as SpecificID),
+            _ => None,
+        };
+        let range = match (&arg.value.operators[..], number(0), number(1)) {
+            ([Operator::Range], Some(start), Some(end)) if start < end => start..end,
+            _ => {
+                return Err(format!(
+                    "Expected a range of numbers like 1..200 for {} in #[{}]",
+                    ID_CLASSES[class], tag
+                ))
+            }
+        };
+        out.push((class, range));
+    }
+    Ok(out)
+}
+
+/// A module that takes its IDs from its own ranges
+#[derive(Debug, Clone)]
+pub struct ModuleIds {
+    pub name: String,
+    /// Files in this folder (or this file) belong to the module
+    pub path: PathBuf,
+    pub ranges: [Option<IdRange>; 4],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IdConfig {
+    /// IDs that are never given to arbitrary IDs, for every ID class
+    pub reserved: [Vec<IdRange>; 4],
+    pub modules: Vec<ModuleIds>,
+    /// Lets arbitrary groups that can't affect each other share a specific group
+    /// (see [`levelstring::append_objects`](crate::levelstring::append_objects))
+    pub recycle_groups: bool,
+    // module of the arbitrary IDs made by modules with their own ranges
+    owners: [BTreeMap<ArbitraryID, usize>; 4],
+}
+
+impl IdConfig {
+    pub fn reserve(&mut self, class: usize, range: IdRange) {
+        self.reserved[class].push(range);
+    }
+
+    pub fn add_module(&mut self, module: ModuleIds) {
+        self.modules.push(module);
+    }
+
+    /// Remembers which module made an arbitrary ID, from the file it was made in
+    pub fn claim(&mut self, class: usize, id: ArbitraryID, file: &Path) {
+        if let Some(m) = self
+            .modules
+            .iter()
+            .position(|m| m.ranges[class].is_some() && file.starts_with(&m.path))
+        {
+            self.owners[class].insert(id, m);
+        }
+    }
+
+    // the module an arbitrary ID has to take its specific ID from, and its range
+    fn owner(&self, class: usize, id: ArbitraryID) -> Option<(&ModuleIds, &IdRange)> {
+        let module = &self.modules[*self.owners[class].get(&id)?];
+        Some((module, module.ranges[class].as_ref()?))
+    }
+
+    /// Whether an arbitrary ID can be given a specific ID
+    pub fn allows(&self, class: usize, arbitrary: ArbitraryID, id: SpecificID) -> bool {
+        if self.reserved[class].iter().any(|r| r.contains(&id)) {
+            return false;
+        }
+        match self.owner(class, arbitrary) {
+            Some((_, range)) => range.contains(&id),
+            None => !self
+                .modules
+                .iter()
+                .any(|m| matches!(&m.ranges[class], Some(r) if r.contains(&id))),
+        }
+    }
+
+    /// First specific ID up to `limit` an arbitrary ID can be given that isn't in `closed`
+    pub fn allocate(
+        &self,
+        class: usize,
+        arbitrary: ArbitraryID,
+        closed: &HashSet<SpecificID>,
+        limit: SpecificID,
+    ) -> Result<SpecificID, String> {
+        let range = match self.owner(class, arbitrary) {
+            Some((_, range)) => range.start..range.end.min(limit + 1),
+            None => 1..limit + 1,
+        };
+        let mut candidates = range.clone();
+        candidates
+            .find(|id| !closed.contains(id) && self.allows(class, arbitrary, *id))
+            .ok_or_else(|| match self.owner(class, arbitrary) {
+                Some((module, _)) => format!(
+                    "The module \"{}\" ran out of {} (its range is {}..{})",
+                    module.name, ID_CLASSES[class], range.start, range.end
+                ),
+                None => format!(
+                    "This level exceeds the {} limit!",
+                    ["group", "color", "block ID", "item ID"][class]
+                ),
+            })
+    }
+}
@@ -2,7 +2,10 @@
 use crate::ast::ObjectMode;
 use crate::builtin::*;
 use crate::compiler_types::*;
-use std::collections::{HashMap, HashSet};
+use crate::idrange::IdConfig;
+use crate::parser::FileRange;
+use crate::target::TargetProfile;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum ObjParam {
@@ -62,11 +65,41 @@ pub struct GDObj {
     pub target: Group,
     pub spawn_triggered: bool,*/
     pub func_id: usize,
-    pub params: HashMap<u16, ObjParam>,
+    pub params: BTreeMap<u16, ObjParam>,
     pub mode: ObjectMode,
     pub unique_id: usize,
     pub sync_group: usize,
     pub sync_part: SyncPartID,
+    /// Where in the SPWN source the object was made, `None` for objects from a level
+    pub source: Option<SourceLocation>,
+    /// The object as it was written in a level string, if it came from one. It's written back
+    /// like this as long as `params` still match it, so objects SPWN doesn't change keep their
+    /// key order, duplicate keys and trailing commas
+    pub raw: Option<String>,
+}
+
+/// A position in SPWN source code
+#[derive(Clone, PartialEq, Debug)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub pos: FileRange,
+    /// Macro calls and imports that led to `file` from other files, outermost first
+    pub call_stack: Vec<(PathBuf, FileRange)>,
+}
+
+impl Default for GDObj {
+    fn default() -> Self {
+        GDObj {
+            func_id: 0,
+            params: BTreeMap::new(),
+            mode: ObjectMode::Object,
+            unique_id: 0,
+            sync_group: 0,
+            sync_part: 0,
+            source: None,
+            raw: None,
+        }
+    }
 }
 
 impl GDObj {
@@ -75,75 +108,218 @@ impl GDObj {
 
         (*self).clone()
     }
+
+    /// Value of a parameter as a number, if it is one
+    pub fn param_number(&self, key: u16) -> Option<f64> {
+        match self.params.get(&key)? {
+            ObjParam::Number(n) => Some(*n),
+            ObjParam::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            ObjParam::Text(t) => t.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Specific IDs in a parameter, like the groups of an object or the target of a trigger
+    pub fn param_ids(&self, key: u16) -> Vec<SpecificID> {
+        let specific = |id: &ID| match id {
+            ID::Specific(n) => Some(*n),
+            ID::Arbitrary(_) => None,
+        };
+        match self.params.get(&key) {
+            Some(ObjParam::Group(Group { id }))
+            | Some(ObjParam::Color(Color { id }))
+            | Some(ObjParam::Block(Block { id }))
+            | Some(ObjParam::Item(Item { id })) => specific(id).into_iter().collect(),
+            Some(ObjParam::GroupList(list)) => {
+                list.iter().filter_map(|g| specific(&g.id)).collect()
+            }
+            Some(ObjParam::Number(n)) => vec![*n as SpecificID],
+            // group lists are separated by dots
+            Some(ObjParam::Text(t)) => t.split('.').filter_map(|id| id.parse().ok()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Object ID (key 1)
+    pub fn obj_id(&self) -> Option<u16> {
+        self.param_number(1).map(|n| n as u16)
+    }
+
+    /// X position (key 2)
+    pub fn x(&self) -> Option<f64> {
+        self.param_number(2)
+    }
+
+    /// Y position (key 3)
+    pub fn y(&self) -> Option<f64> {
+        self.param_number(3)
+    }
+
+    /// Groups of the object (key 57)
+    pub fn groups(&self) -> Vec<SpecificID> {
+        self.param_ids(57)
+    }
+
+    /// Target group or color of a trigger (key 51)
+    pub fn target(&self) -> Option<SpecificID> {
+        self.param_ids(51).first().copied()
+    }
+
+    /// Item ID, or block ID for collision triggers (key 80)
+    pub fn item_id(&self) -> Option<SpecificID> {
+        self.param_ids(80).first().copied()
+    }
+}
+
+impl fmt::Display for GDObj {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(raw) = &self.raw {
+            if parse_params(raw).as_ref() == Ok(&self.params) {
+                return write!(f, "{}", raw);
+            }
+        }
+        let mut param_list = self.params.iter().collect::<Vec<(&u16, &ObjParam)>>();
+        param_list.sort_by(|a, b| (*a.0).cmp(b.0));
+
+        let params: Vec<String> = param_list
+            .iter()
+            .map(|(key, val)| format!("{},{}", key, val))
+            .collect();
+        write!(f, "{}", params.join(","))
+    }
+}
+
+/// A level string split into the level settings and the objects in the level
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LevelString {
+    /// Level settings (`kS38`, `kA13`, ...), in the order they were written
+    pub header: Vec<(String, String)>,
+    /// Objects of the level. Parsed values are kept as they were written, as `ObjParam::Text`
+    pub objects: Vec<GDObj>,
+    // the level settings as they were written, used as long as `header` still matches them
+    raw_header: Option<String>,
+}
+
+// keys and values of an object or the level settings, which are separated by commas.
+// objects are sometimes written with a trailing comma
+fn split_pairs(text: &str) -> Result<Vec<(&str, &str)>, String> {
+    let mut props: Vec<&str> = text.split(',').collect();
+    if props.len() % 2 == 1 && props.last() == Some(&"") {
+        props.pop();
+    }
+    if props.len() % 2 == 1 {
+        return Err("has a key without a value".to_string());
+    }
+    Ok(props.chunks(2).map(|kv| (kv[0], kv[1])).collect())
 }
 
-pub fn get_used_ids(ls: &str) -> [HashSet<u16>; 4] {
+// parameters of an object from a level. if a key is there more than once, the last value is used
+fn parse_params(text: &str) -> Result<BTreeMap<u16, ObjParam>, String> {
+    let mut params = BTreeMap::new();
+    for (key, value) in split_pairs(text)? {
+        let key = key
+            .parse::<u16>()
+            .map_err(|_| format!("has an invalid key: \"{}\"", key))?;
+        params.insert(key, ObjParam::Text(value.to_string()));
+    }
+    Ok(params)
+}
+
+impl LevelString {
+    /// Parses a level string. Writing it again with `to_string` gives back the same text, apart
+    /// from changes made to it in between
+    pub fn parse(ls: &str) -> Result<Self, String> {
+        let mut out = LevelString::default();
+
+        for (i, obj) in ls.split(';').enumerate() {
+            if obj.is_empty() {
+                continue;
+            }
+            let error = |e: String| format!("Object {} in the level {}", i, e);
+
+            // the level settings are the only keys that aren't numbers
+            if i == 0 && obj.starts_with('k') {
+                out.header = split_pairs(obj)
+                    .map_err(error)?
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
+                out.raw_header = Some(obj.to_string());
+                continue;
+            }
+
+            out.objects.push(GDObj {
+                params: parse_params(obj).map_err(error)?,
+                raw: Some(obj.to_string()),
+                ..GDObj::default()
+            });
+        }
+
+        Ok(out)
+    }
+}
+
+impl fmt::Display for LevelString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let raw_header = self.raw_header.as_deref().filter(|raw| {
+            matches!(split_pairs(raw), Ok(pairs) if pairs.len() == self.header.len()
+                && pairs.iter().zip(&self.header).all(|((k, v), (hk, hv))| k == hk && v == hv))
+        });
+        if let Some(raw) = raw_header {
+            write!(f, "{};", raw)?;
+        } else if !self.header.is_empty() {
+            let header: Vec<String> = self
+                .header
+                .iter()
+                .map(|(k, v)| format!("{},{}", k, v))
+                .collect();
+            write!(f, "{};", header.join(","))?;
+        }
+        for obj in &self.objects {
+            write!(f, "{};", obj)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn get_used_ids(ls: &LevelString) -> [HashSet<u16>; 4] {
     let mut out = [
         HashSet::<u16>::new(),
         HashSet::<u16>::new(),
         HashSet::<u16>::new(),
         HashSet::<u16>::new(),
     ];
-    let objects = ls.split(';');
-    for obj in objects {
-        let props: Vec<&str> = obj.split(',').collect();
-        let mut map = HashMap::new();
-
-        for i in (0..props.len() - 1).step_by(2) {
-            map.insert(props[i], props[i + 1]);
-        }
-
-        for (key, value) in &map {
-            match *key {
-                "57" => {
-                    //GROUPS
-                    let groups = value.split('.');
-                    for g in groups {
-                        let group = g.parse().unwrap();
-
-                        out[0].insert(group);
-                    }
-                }
-                "51" => {
-                    match (map.get("1"), map.get("52")) {
-                        (Some(&"1006"), Some(&"1")) => out[0].insert(value.parse().unwrap()),
-                        (Some(&"1006"), _) => out[1].insert(value.parse().unwrap()),
-                        _ => out[0].insert(value.parse().unwrap()),
-                    };
-                }
-                "71" => {
-                    out[0].insert(value.parse().unwrap());
-                }
+    for obj in &ls.objects {
+        let obj_id = obj.obj_id();
+        for key in obj.params.keys() {
+            let ids = obj.param_ids(*key);
+            let class_index = match *key {
+                //GROUPS
+                57 | 71 => 0,
+                51 => match (obj_id, obj.param_number(52)) {
+                    (Some(1006), Some(1.0)) => 0,
+                    (Some(1006), _) => 1,
+                    _ => 0,
+                },
                 //colors
-                "21" => {
-                    out[1].insert(value.parse().unwrap());
-                }
-                "22" => {
-                    out[1].insert(value.parse().unwrap());
-                }
-                "23" => {
-                    out[1].insert(value.parse().unwrap());
-                }
-
-                "80" => {
-                    match map.get("1") {
-                        //if collision trigger, add block id
-                        Some(&"1815") => out[2].insert(value.parse().unwrap()),
-                        //counter display => do nothing
-                        Some(&"1615") => false,
-                        // else add item id
-                        _ => out[3].insert(value.parse().unwrap()),
-                    };
-                }
-
-                "95" => {
-                    out[2].insert(value.parse().unwrap());
-                }
+                21..=23 => 1,
+
+                80 => match obj_id {
+                    //if collision trigger, add block id
+                    Some(1815) => 2,
+                    //counter display => do nothing
+                    Some(1615) => continue,
+                    // else add item id
+                    _ => 3,
+                },
+
+                95 => 2,
                 //some of these depends on what object it is
                 //pulse target depends on group mode/color mode
                 //figure this out, future me
-                _ => (),
-            }
+                _ => continue,
+            };
+            out[class_index].extend(ids);
         }
     }
     out
@@ -157,74 +333,385 @@ pub const SPWN_SIGNATURE_GROUP: Group = Group {
 };
 //use crate::ast::ObjectMode;
 
-pub fn remove_spwn_objects(file_content: &mut String) {
-    let spwn_group = match SPWN_SIGNATURE_GROUP.id {
-        ID::Specific(n) => n.to_string(),
+/// Signature groups of the scripts that add objects to a level. It is stored in the level
+/// as a text object, so several scripts can build into the same level without removing
+/// each other's objects.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScriptRegistry {
+    pub scripts: BTreeMap<String, SpecificID>,
+}
+
+const REGISTRY_KEY: &str = "spwn_scripts";
+
+impl ScriptRegistry {
+    // the text of a text object, if it is the registry
+    fn from_obj(obj: &GDObj) -> Option<Self> {
+        if obj.obj_id() != Some(914) {
+            return None;
+        }
+        let text = match obj.params.get(&31) {
+            Some(ObjParam::Text(t)) => base64::decode(t).ok()?,
+            _ => return None,
+        };
+        let json: serde_json::Value = serde_json::from_slice(&text).ok()?;
+        let scripts = json.get(REGISTRY_KEY)?.as_object()?;
+        Some(ScriptRegistry {
+            scripts: scripts
+                .iter()
+                .filter_map(|(name, id)| Some((name.clone(), id.as_u64()? as SpecificID)))
+                .collect(),
+        })
+    }
+
+    pub fn read(level: &LevelString) -> Self {
+        level
+            .objects
+            .iter()
+            .find_map(ScriptRegistry::from_obj)
+            .unwrap_or_default()
+    }
+
+    /// Signature group of a script. Scripts that aren't registered yet get one that isn't
+    /// used in the level, starting with the group older versions of SPWN used for everything.
+    pub fn signature(&mut self, script: &str, level: &LevelString) -> Group {
+        let id = match self.scripts.get(script) {
+            Some(id) => *id,
+            None => {
+                let used = &get_used_ids(level)[0];
+                let spwn_group = match SPWN_SIGNATURE_GROUP.id {
+                    ID::Specific(n) => n,
+                    _ => unreachable!(),
+                };
+                let id = (spwn_group..)
+                    .find(|id| {
+                        !self.scripts.values().any(|s| s == id)
+                            && (*id == spwn_group || !used.contains(id))
+                    })
+                    .unwrap();
+                self.scripts.insert(script.to_string(), id);
+                id
+            }
+        };
+        Group {
+            id: ID::Specific(id),
+        }
+    }
+
+    /// Replaces the registry in the level with this one
+    pub fn write(&self, level: &mut LevelString) {
+        level
+            .objects
+            .retain(|obj| ScriptRegistry::from_obj(obj).is_none());
+        if self.scripts.is_empty() {
+            return;
+        }
+        let text = serde_json::json!({ REGISTRY_KEY: self.scripts }).to_string();
+        let mut params = BTreeMap::new();
+        for (key, value) in [
+            (1, "914".to_string()),
+            (2, "-300".to_string()),
+            (3, "75".to_string()),
+            (31, base64::encode(text.as_bytes())),
+            (32, "0.5".to_string()),
+        ] {
+            params.insert(key, ObjParam::Text(value));
+        }
+        level.objects.push(GDObj {
+            params,
+            ..GDObj::default()
+        });
+    }
+}
+
+/// Removes the objects of earlier builds of a script from the level, and returns the
+/// signature group the script's new objects should be marked with. Without a script name
+/// the objects of every script are removed, like older versions of SPWN did.
+pub fn remove_script_objects(ls: &mut LevelString, script: Option<&str>) -> Group {
+    match script {
+        Some(script) => {
+            let mut registry = ScriptRegistry::read(ls);
+            let signature = registry.signature(script, ls);
+            remove_spwn_objects(ls, signature);
+            registry.write(ls);
+            signature
+        }
+        None => {
+            let registry = ScriptRegistry::read(ls);
+            for id in registry.scripts.values() {
+                remove_spwn_objects(
+                    ls,
+                    Group {
+                        id: ID::Specific(*id),
+                    },
+                );
+            }
+            remove_spwn_objects(ls, SPWN_SIGNATURE_GROUP);
+            ScriptRegistry::default().write(ls);
+            SPWN_SIGNATURE_GROUP
+        }
+    }
+}
+
+/// Removes the objects of a script and its entry in the registry of the level. Returns
+/// false if no script with that name has added objects to the level.
+pub fn unregister_script(ls: &mut LevelString, script: &str) -> bool {
+    let mut registry = ScriptRegistry::read(ls);
+    match registry.scripts.remove(script) {
+        Some(id) => {
+            remove_spwn_objects(
+                ls,
+                Group {
+                    id: ID::Specific(id),
+                },
+            );
+            registry.write(ls);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes the objects marked with a signature group
+pub fn remove_spwn_objects(ls: &mut LevelString, signature: Group) {
+    let spwn_group = match signature.id {
+        ID::Specific(n) => n,
         _ => unreachable!(),
     };
-    (*file_content) = file_content
-        //remove previous spwn objects
-        .split(';')
-        .map(|obj| {
-            let key_val: Vec<&str> = obj.split(',').collect();
-            let mut ret = obj;
-            for i in (0..key_val.len()).step_by(2) {
-                if key_val[i] == "57" {
-                    let mut groups = key_val[i + 1].split('.');
-                    if groups.any(|x| x == spwn_group) {
-                        ret = "";
+    //remove previous spwn objects
+    ls.objects.retain(|obj| !obj.groups().contains(&spwn_group));
+}
+
+/// ID class (0 for groups, 1 for colors, 2 for block IDs and 3 for item IDs) and IDs
+/// of a parameter, if it has any
+pub fn param_ids_mut(prop: &mut ObjParam) -> Option<(usize, Vec<&mut ID>)> {
+    Some(match prop {
+        ObjParam::Group(g) => (0, vec![&mut g.id]),
+        ObjParam::GroupList(l) => (0, l.iter_mut().map(|g| &mut g.id).collect()),
+        ObjParam::Color(c) => (1, vec![&mut c.id]),
+        ObjParam::Block(b) => (2, vec![&mut b.id]),
+        ObjParam::Item(i) => (3, vec![&mut i.id]),
+        _ => return None,
+    })
+}
+
+/// What the objects of a script do to an arbitrary group, and what is in it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct GroupUse {
+    /// Spawn triggered triggers in the group, which run when it's spawned
+    triggers: bool,
+    /// Other objects in the group, including triggers the player activates by passing them
+    objects: bool,
+    spawned: bool,
+    toggled_on: bool,
+    toggled_off: bool,
+    /// The objects in the group are moved, rotated, faded and so on
+    changed: bool,
+    /// The position of the objects in the group is used, like the center of a rotation
+    referenced: bool,
+    /// A stop trigger stops what the triggers in the group started
+    stopped: bool,
+}
+
+impl GroupUse {
+    // whether anything done to this group would change what `other` does if they were
+    // the same group
+    fn affects(&self, other: &GroupUse) -> bool {
+        ((self.spawned || self.stopped) && other.triggers)
+            || (self.toggled_off && (other.triggers || other.objects))
+            // turning the group on would undo the other group being turned off
+            || (self.toggled_on && other.toggled_off)
+            || ((self.changed || self.referenced) && other.objects)
+    }
+
+    // what a trigger does to the group in `key`
+    fn of_target(obj: &GDObj, key: u16) -> GroupUse {
+        let activate = obj.param_number(56) == Some(1.0);
+        match (obj.obj_id(), key) {
+            (Some(1268), 51) => GroupUse {
+                spawned: true,
+                ..GroupUse::default()
+            },
+            (Some(1049), 51) => GroupUse {
+                toggled_on: activate,
+                toggled_off: !activate,
+                ..GroupUse::default()
+            },
+            (Some(1616), 51) => GroupUse {
+                stopped: true,
+                ..GroupUse::default()
+            },
+            // touch triggers can toggle the group back when the player lets go
+            (Some(1595), 51) => GroupUse {
+                spawned: true,
+                toggled_on: true,
+                toggled_off: true,
+                ..GroupUse::default()
+            },
+            // count, instant count, collision and death triggers
+            (Some(1611), 51) | (Some(1811), 51) | (Some(1815), 51) | (Some(1812), 51) => GroupUse {
+                spawned: activate,
+                toggled_on: activate,
+                toggled_off: !activate,
+                ..GroupUse::default()
+            },
+            (Some(id), 51) if OBJECT_TRIGGERS.contains(&id) => GroupUse {
+                changed: true,
+                ..GroupUse::default()
+            },
+            // like the center group of a rotate trigger, or the group a move trigger moves to
+            (Some(id), _) if OBJECT_TRIGGERS.contains(&id) => GroupUse {
+                referenced: true,
+                ..GroupUse::default()
+            },
+            // anything could happen
+            _ => GroupUse {
+                spawned: true,
+                toggled_on: true,
+                toggled_off: true,
+                changed: true,
+                referenced: true,
+                stopped: true,
+                ..GroupUse::default()
+            },
+        }
+    }
+
+    fn add(&mut self, other: &GroupUse) {
+        self.triggers |= other.triggers;
+        self.objects |= other.objects;
+        self.spawned |= other.spawned;
+        self.toggled_on |= other.toggled_on;
+        self.toggled_off |= other.toggled_off;
+        self.changed |= other.changed;
+        self.referenced |= other.referenced;
+        self.stopped |= other.stopped;
+    }
+}
+
+// triggers that only change where objects are or what they look like, which doesn't
+// matter for spawn triggered triggers
+const OBJECT_TRIGGERS: &[u16] = &[901, 1006, 1007, 1346, 1347, 1585, 1814];
+
+/// Which arbitrary groups of a script can't share a specific group, because a trigger that
+/// spawns, toggles, moves or stops one of them would also do that to the other one while it
+/// matters. Spawning a group runs the spawn triggered triggers in it, toggling it turns all
+/// of its objects on or off, and moving it (or using it as the center of a rotation and so
+/// on) only matters for objects that aren't spawn triggered triggers.
+///
+/// Groups made in different parts of a sync group already share an arbitrary group when
+/// they are made (see [`SyncGroup::groups_used`]), since only one part can run at a time.
+#[derive(Debug, Default, Clone)]
+pub struct GroupInterference {
+    edges: HashMap<ArbitraryID, HashSet<ArbitraryID>>,
+}
+
+impl GroupInterference {
+    /// Builds the relation from how the objects (including the triggers) use their groups
+    pub fn of_objects(objects: &[GDObj]) -> Self {
+        let mut uses = BTreeMap::<ArbitraryID, GroupUse>::new();
+        for obj in objects {
+            let spawn_triggered =
+                obj.mode == ObjectMode::Trigger && obj.param_number(62) == Some(1.0);
+            for (key, param) in &obj.params {
+                let groups = match param {
+                    ObjParam::Group(g) => vec![*g],
+                    ObjParam::GroupList(l) => l.clone(),
+                    _ => continue,
+                };
+                let used = match *key {
+                    57 => GroupUse {
+                        triggers: spawn_triggered,
+                        objects: !spawn_triggered,
+                        ..GroupUse::default()
+                    },
+                    _ if obj.mode == ObjectMode::Trigger => GroupUse::of_target(obj, *key),
+                    _ => GroupUse::of_target(&GDObj::default(), *key),
+                };
+                for g in groups {
+                    if let ID::Arbitrary(a) = g.id {
+                        uses.entry(a).or_default().add(&used);
                     }
                 }
             }
-            ret
+        }
+
+        // the groups that each kind of use affects
+        let kinds: Vec<(GroupUse, Vec<ArbitraryID>)> = [
+            GroupUse {
+                triggers: true,
+                ..GroupUse::default()
+            },
+            GroupUse {
+                objects: true,
+                ..GroupUse::default()
+            },
+            GroupUse {
+                toggled_off: true,
+                ..GroupUse::default()
+            },
+        ]
+        .iter()
+        .map(|kind| {
+            let groups = uses
+                .iter()
+                .filter(|(_, used)| {
+                    (kind.triggers && used.triggers)
+                        || (kind.objects && used.objects)
+                        || (kind.toggled_off && used.toggled_off)
+                })
+                .map(|(a, _)| *a)
+                .collect();
+            (*kind, groups)
         })
-        .collect::<Vec<&str>>()
-        .join(";");
+        .collect();
+
+        let mut edges = HashMap::<ArbitraryID, HashSet<ArbitraryID>>::new();
+        for (a, used) in &uses {
+            for (kind, groups) in &kinds {
+                if !used.affects(kind) {
+                    continue;
+                }
+                for b in groups.iter().filter(|b| *b != a) {
+                    edges.entry(*a).or_default().insert(*b);
+                    edges.entry(*b).or_default().insert(*a);
+                }
+            }
+        }
+        GroupInterference { edges }
+    }
+
+    /// Whether the two groups can't share a specific group
+    pub fn interferes(&self, a: ArbitraryID, b: ArbitraryID) -> bool {
+        matches!(self.edges.get(&a), Some(e) if e.contains(&b))
+    }
 }
 
-//returns the string to be appended to the old string
+// adds the objects to the level, and gives all arbitrary IDs a specific ID that isn't used in it.
+// with `config.recycle_groups`, arbitrary groups share the specific group of the first
+// groups before them that they don't interfere with (see `GroupInterference`)
 pub fn append_objects(
     mut objects: Vec<GDObj>,
-    old_ls: &str,
-) -> Result<(String, [usize; 4]), String> {
-    let mut closed_ids = get_used_ids(&old_ls);
+    level: &mut LevelString,
+    config: &IdConfig,
+    target: &TargetProfile,
+    signature: Group,
+) -> Result<[usize; 4], String> {
+    let mut closed_ids = get_used_ids(level);
+    // the objects of earlier builds are gone, but their signature group can't be reused
+    if let ID::Specific(n) = signature.id {
+        if n <= target.id_limits[0] {
+            closed_ids[0].insert(n);
+        }
+    }
 
     //collect all specific ids mentioned into closed_[id] lists
-    for obj in &objects {
-        for prop in obj.params.values() {
-            let class_index;
-            let id;
-            match prop {
-                ObjParam::Group(g) => {
-                    class_index = 0;
-                    id = vec![g.id];
-                }
-
-                ObjParam::GroupList(l) => {
-                    class_index = 0;
-
-                    id = l.iter().map(|g| g.id).collect();
-                }
-                ObjParam::Color(g) => {
-                    class_index = 1;
-                    id = vec![g.id];
-                }
-                ObjParam::Block(g) => {
-                    class_index = 2;
-                    id = vec![g.id];
-                }
-                ObjParam::Item(g) => {
-                    class_index = 3;
-                    id = vec![g.id];
-                }
-                _ => continue,
-            }
-            for id in id {
-                match id {
-                    ID::Specific(i) => {
-                        closed_ids[class_index].insert(i);
+    for obj in &mut objects {
+        for prop in obj.params.values_mut() {
+            if let Some((class_index, ids)) = param_ids_mut(prop) {
+                for id in ids {
+                    if let ID::Specific(i) = id {
+                        closed_ids[class_index].insert(*i);
                     }
-                    _ => continue,
                 }
             }
         }
@@ -238,58 +725,57 @@ pub fn append_objects(
         HashMap::new(),
     ];
 
-    const ID_MAX: u16 = 999;
+    // the groups given out so far, with the arbitrary groups that have them
+    let interference = if config.recycle_groups {
+        Some(GroupInterference::of_objects(&objects))
+    } else {
+        None
+    };
+    let mut shared: Vec<(SpecificID, Vec<ArbitraryID>)> = Vec::new();
 
     for obj in &mut objects {
         for prop in obj.params.values_mut() {
-            let class_index;
-            let ids: Vec<&mut ID>;
-            match prop {
-                ObjParam::Group(g) => {
-                    class_index = 0;
-                    ids = vec![&mut g.id];
-                }
-                ObjParam::GroupList(g) => {
-                    class_index = 0;
-                    ids = g.iter_mut().map(|x| &mut x.id).collect();
-                }
-                ObjParam::Color(g) => {
-                    class_index = 1;
-                    ids = vec![&mut g.id];
-                }
-                ObjParam::Block(g) => {
-                    class_index = 2;
-                    ids = vec![&mut g.id];
-                }
-                ObjParam::Item(g) => {
-                    class_index = 3;
-                    ids = vec![&mut g.id];
-                }
-                _ => continue,
-            }
+            let (class_index, ids) = match param_ids_mut(prop) {
+                Some(ids) => ids,
+                None => continue,
+            };
             for id in ids {
                 match &id {
                     ID::Arbitrary(i) => {
                         *id = ID::Specific(match id_maps[class_index].get(i) {
                             Some(a) => *a,
                             None => {
-                                let mut out = None;
-                                for i in 1..10000 {
-                                    if !closed_ids[class_index].contains(&i) {
-                                        out = Some(i);
-                                        closed_ids[class_index].insert(i);
-                                        break;
+                                let interference = match class_index {
+                                    0 => interference.as_ref(),
+                                    _ => None,
+                                };
+                                let recycled = interference.and_then(|interference| {
+                                    shared.iter_mut().find(|(id, with)| {
+                                        with.iter().all(|w| !interference.interferes(*w, *i))
+                                            && config.allows(0, *i, *id)
+                                    })
+                                });
+                                let out = match recycled {
+                                    Some((id, with)) => {
+                                        with.push(*i);
+                                        *id
+                                    }
+                                    None => {
+                                        let out = config.allocate(
+                                            class_index,
+                                            *i,
+                                            &closed_ids[class_index],
+                                            target.id_limits[class_index],
+                                        )?;
+                                        closed_ids[class_index].insert(out);
+                                        if interference.is_some() {
+                                            shared.push((out, vec![*i]));
+                                        }
+                                        out
                                     }
-                                }
-                                if let Some(id) = out {
-                                    id_maps[class_index].insert(*i, id);
-                                    id
-                                } else {
-                                    return Err(format!(
-                                        "This level exeeds the {} limit!",
-                                        ["group", "color", "block ID", "item ID"][class_index]
-                                    ));
-                                }
+                                };
+                                id_maps[class_index].insert(*i, out);
+                                out
                             }
                         })
                     }
@@ -299,102 +785,71 @@ pub fn append_objects(
         }
     }
     for (i, list) in closed_ids.iter().enumerate() {
-        if list.len() > ID_MAX as usize {
+        if list.len() > target.id_limits[i] as usize {
             return Err(format!(
-                "This level exeeds the {} limit! ({}/{})",
+                "This level exceeds the {} limit! ({}/{})",
                 ["group", "color", "block ID", "item ID"][i],
                 list.len(),
-                ID_MAX
+                target.id_limits[i]
             ));
         }
     }
 
     //println!("group_map: {:?}", id_maps[0]);
 
-    fn serialize_obj(mut trigger: GDObj) -> String {
-        let mut obj_string = String::new();
-        match trigger.mode {
-            ObjectMode::Object => {
-                match trigger.params.get_mut(&57) {
-                    Some(ObjParam::GroupList(l)) => (*l).push(SPWN_SIGNATURE_GROUP),
-                    Some(ObjParam::Group(g)) => {
-                        let group = *g;
-                        trigger
-                            .params
-                            .insert(57, ObjParam::GroupList(vec![group, SPWN_SIGNATURE_GROUP]));
-                    }
-                    _ => {
-                        trigger
-                            .params
-                            .insert(57, ObjParam::Group(SPWN_SIGNATURE_GROUP));
-                    }
-                };
-
-                let mut param_list = trigger.params.iter().collect::<Vec<(&u16, &ObjParam)>>();
-
-                param_list.sort_by(|a, b| (*a.0).cmp(b.0));
-
-                for param in param_list {
-                    obj_string += &format!("{},{},", param.0, param.1);
-                }
-
-                obj_string + ";"
+    for mut obj in objects {
+        // groups that were given the same specific group
+        if let Some(ObjParam::GroupList(l)) = obj.params.get_mut(&57) {
+            let mut seen = HashSet::new();
+            l.retain(|g| seen.insert(*g));
+        }
+        // mark the object so it can be removed in the next build
+        match obj.params.get_mut(&57) {
+            Some(ObjParam::GroupList(l)) => (*l).push(signature),
+            Some(ObjParam::Group(g)) => {
+                let group = *g;
+                obj.params
+                    .insert(57, ObjParam::GroupList(vec![group, signature]));
             }
-            ObjectMode::Trigger => {
-                match trigger.params.get_mut(&57) {
-                    Some(ObjParam::GroupList(l)) => {
-                        (*l).push(SPWN_SIGNATURE_GROUP);
-                        //list
-                    }
-                    Some(ObjParam::Group(g)) => {
-                        let group = *g;
-                        trigger
-                            .params
-                            .insert(57, ObjParam::GroupList(vec![group, SPWN_SIGNATURE_GROUP]));
-                    }
-                    _ => {
-                        trigger
-                            .params
-                            .insert(57, ObjParam::Group(SPWN_SIGNATURE_GROUP));
-                        //Vec::new()
-                    }
-                };
-
-                /*let spawned = match trigger.params.get(&62) {
-                    Some(ObjParam::Bool(b)) => *b,
-                    _ => groups.iter().any(|x| x.id != ID::Specific(0)),
-                };
-
-                if spawned {
-                    obj_string += "87,1,";
-                }*/
-
-                let mut param_list = trigger.params.iter().collect::<Vec<(&u16, &ObjParam)>>();
-
-                param_list.sort_by(|a, b| (*a.0).cmp(b.0));
-
-                for param in param_list {
-                    obj_string += &format!("{},{},", param.0, param.1);
-                }
-                obj_string + "108,1;" //linked group
+            _ => {
+                obj.params.insert(57, ObjParam::Group(signature));
             }
+        };
+        if obj.mode == ObjectMode::Trigger {
+            //linked group
+            obj.params.insert(108, ObjParam::Number(1.0));
         }
+        level.objects.push(obj);
     }
 
-    let mut full_obj_string = String::new();
+    Ok([
+        closed_ids[0].len(),
+        closed_ids[1].len(),
+        closed_ids[2].len(),
+        closed_ids[3].len(),
+    ])
+}
 
+// adds the group of their sync part to all triggers in a sync group, so they can be
+// turned off while another part of the sync group is running
+pub fn apply_sync_groups(objects: &mut [GDObj], sync_groups: &[SyncGroup]) {
     for obj in objects {
-        full_obj_string += &serialize_obj(obj)
+        if obj.mode != ObjectMode::Trigger || sync_groups[obj.sync_group].parts.len() < 2 {
+            continue;
+        }
+        let part_group = sync_groups[obj.sync_group].parts[obj.sync_part].group;
+        match obj.params.get_mut(&57) {
+            Some(ObjParam::GroupList(l)) => l.push(part_group),
+            Some(ObjParam::Group(g)) if g.id != ID::Specific(0) => {
+                let group = *g;
+                obj.params
+                    .insert(57, ObjParam::GroupList(vec![group, part_group]));
+            }
+            _ => {
+                obj.params.insert(57, ObjParam::Group(part_group));
+            }
+        }
     }
-    Ok((
-        full_obj_string,
-        [
-            closed_ids[0].len(),
-            closed_ids[1].len(),
-            closed_ids[2].len(),
-            closed_ids[3].len(),
-        ],
-    ))
 }
 
 pub fn apply_fn_ids(func_ids: &[FunctionID]) -> Vec<GDObj> {
@@ -652,7 +1107,68 @@ pub fn serialize_triggers_old(func_ids: Vec<FunctionID>) -> String {
 //</OLD>
 
 use libflate::{gzip, zlib};
-use std::io::Read;
+use std::io::{Read, Write};
+
+#[derive(Debug)]
+pub enum SaveFileError {
+    Io { path: PathBuf, err: std::io::Error },
+    // the savefile itself couldn't be decrypted or encrypted
+    Crypt(String),
+    Xml { position: usize, message: String },
+    // the changed savefile couldn't be written as xml
+    XmlWrite(String),
+    LevelNotFound(Option<String>),
+    LevelNotInitialized,
+    // the level string of a level couldn't be decoded or encoded
+    LevelData(String),
+}
+
+impl fmt::Display for SaveFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveFileError::Io { path, err } => write!(f, "{}: {}", path.display(), err),
+            SaveFileError::Crypt(message) => {
+                write!(f, "Savefile could not be decrypted: {}", message)
+            }
+            SaveFileError::Xml { position, message } => {
+                write!(f, "Invalid savefile at position {}: {}", position, message)
+            }
+            SaveFileError::XmlWrite(message) => {
+                write!(f, "Savefile could not be written: {}", message)
+            }
+            SaveFileError::LevelNotFound(Some(name)) => {
+                write!(f, "Level named \"{}\" was not found!", name)
+            }
+            SaveFileError::LevelNotFound(None) => write!(
+                f,
+                "No level found! Please create a level for SPWN to operate on!"
+            ),
+            SaveFileError::LevelNotInitialized => write!(
+                f,
+                "Level is not initialized! Please open the level, place some objects, then save and quit to initialize the level."
+            ),
+            SaveFileError::LevelData(message) => {
+                write!(f, "Level data could not be decoded: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveFileError::Io { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> SaveFileError + '_ {
+    move |err| SaveFileError::Io {
+        path: path.to_path_buf(),
+        err,
+    }
+}
 
 fn xor(data: Vec<u8>, key: u8) -> Vec<u8> {
     let mut new_data = Vec::new();
@@ -663,38 +1179,48 @@ fn xor(data: Vec<u8>, key: u8) -> Vec<u8> {
     }
     new_data
 }
-fn base_64_decrypt(encoded: Vec<u8>) -> Vec<u8> {
+fn base_64_decrypt(encoded: Vec<u8>) -> Result<Vec<u8>, SaveFileError> {
     let mut new_data = encoded;
     while new_data.len() % 4 != 0 {
         new_data.push(b'=')
     }
-    base64::decode(String::from_utf8(new_data).unwrap().as_str()).unwrap()
+    let text =
+        String::from_utf8(new_data).map_err(|e| SaveFileError::LevelData(format!("{}", e)))?;
+    base64::decode(text.as_str()).map_err(|e| SaveFileError::LevelData(format!("{}", e)))
 }
 
 use quick_xml::events::{BytesText, Event};
 use quick_xml::Reader;
+
+fn xml_error(reader: &Reader<&[u8]>, e: quick_xml::Error) -> SaveFileError {
+    SaveFileError::Xml {
+        position: reader.buffer_position(),
+        message: format!("{:?}", e),
+    }
+}
+
+const IOS_KEY: &[u8] = &[
+    0x69, 0x70, 0x75, 0x39, 0x54, 0x55, 0x76, 0x35, 0x34, 0x79, 0x76, 0x5D, 0x69, 0x73, 0x46, 0x4D,
+    0x68, 0x35, 0x40, 0x3B, 0x74, 0x2E, 0x35, 0x77, 0x33, 0x34, 0x45, 0x32, 0x52, 0x79, 0x40, 0x7B,
+];
+
 //use std::io::BufReader;
-fn decrypt_savefile(mut sf: Vec<u8>) -> Result<Vec<u8>, String> {
+fn decrypt_savefile(mut sf: Vec<u8>) -> Result<Vec<u8>, SaveFileError> {
     if cfg!(target_os = "macos") {
         use aes::Aes256;
 
         use block_modes::block_padding::Pkcs7;
         use block_modes::{BlockMode, Ecb};
 
-        const IOS_KEY: &[u8] = &[
-            0x69, 0x70, 0x75, 0x39, 0x54, 0x55, 0x76, 0x35, 0x34, 0x79, 0x76, 0x5D, 0x69, 0x73,
-            0x46, 0x4D, 0x68, 0x35, 0x40, 0x3B, 0x74, 0x2E, 0x35, 0x77, 0x33, 0x34, 0x45, 0x32,
-            0x52, 0x79, 0x40, 0x7B,
-        ];
-
         type AesEcb = Ecb<Aes256, Pkcs7>;
 
         // re-create cipher mode instance
-        let cipher = AesEcb::new_var(&IOS_KEY, &[]).unwrap();
+        let cipher =
+            AesEcb::new_var(&IOS_KEY, &[]).map_err(|e| SaveFileError::Crypt(format!("{}", e)))?;
 
         Ok(match cipher.decrypt(&mut sf) {
             Ok(v) => v,
-            Err(e) => return Err(format!("{}", e)),
+            Err(e) => return Err(SaveFileError::Crypt(format!("{}", e))),
         }
         .to_vec())
     } else {
@@ -705,15 +1231,95 @@ fn decrypt_savefile(mut sf: Vec<u8>) -> Result<Vec<u8>, String> {
             .replace("\0", "");
         let b64 = match base64::decode(replaced.as_str()) {
             Ok(b) => b,
-            Err(e) => return Err(format!("{}", e)),
+            Err(e) => return Err(SaveFileError::Crypt(format!("{}", e))),
         };
-        let mut decoder = gzip::Decoder::new(&b64[..]).unwrap();
+        let mut decoder =
+            gzip::Decoder::new(&b64[..]).map_err(|e| SaveFileError::Crypt(format!("{}", e)))?;
         let mut data = Vec::new();
-        decoder.read_to_end(&mut data).unwrap();
+        decoder
+            .read_to_end(&mut data)
+            .map_err(|e| SaveFileError::Crypt(format!("{}", e)))?;
         Ok(data)
     }
 }
-pub fn get_level_string(ls: Vec<u8>, level_name: Option<String>) -> Result<String, String> {
+
+fn encrypt_savefile(bytes: Vec<u8>) -> Result<Vec<u8>, SaveFileError> {
+    let crypt_error = |e: std::io::Error| SaveFileError::Crypt(format!("{}", e));
+    if cfg!(target_os = "macos") {
+        use aes::Aes256;
+
+        use block_modes::block_padding::Pkcs7;
+        use block_modes::{BlockMode, Ecb};
+
+        type AesEcb = Ecb<Aes256, Pkcs7>;
+
+        // re-create cipher mode instance
+        let cipher =
+            AesEcb::new_var(&IOS_KEY, &[]).map_err(|e| SaveFileError::Crypt(format!("{}", e)))?;
+
+        Ok(cipher.encrypt_vec(&bytes))
+    } else {
+        let mut encoder = zlib::Encoder::new(Vec::new()).map_err(crypt_error)?;
+        encoder.write_all(&bytes).map_err(crypt_error)?;
+        let compressed = encoder.finish().into_result().map_err(crypt_error)?;
+        use crc32fast::Hasher;
+
+        let mut hasher = Hasher::new();
+        hasher.update(&bytes);
+        let checksum = hasher.finalize();
+
+        let data_size = bytes.len() as u32;
+
+        let mut with_signature = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x0b".to_vec();
+        with_signature.extend(&compressed[2..compressed.len() - 4]);
+        with_signature.extend(checksum.to_le_bytes().to_vec());
+        with_signature.extend(data_size.to_le_bytes().to_vec());
+
+        let encoded = base64::encode(&with_signature)
+            .replace("+", "-")
+            .replace("/", "_")
+            .as_bytes()
+            .to_vec();
+
+        Ok(xor(encoded, 11))
+    }
+}
+
+// names of all the local levels in a savefile, in the order they are stored
+pub fn get_level_names(ls: Vec<u8>) -> Result<Vec<String>, SaveFileError> {
+    let content = decrypt_savefile(ls)?;
+    let string_content = String::from_utf8_lossy(&content);
+
+    let mut reader = Reader::from_str(&string_content);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut names = Vec::new();
+    let mut k2_detected = false;
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Text(e)) => {
+                let text = e
+                    .unescape_and_decode(&reader)
+                    .map_err(|e| xml_error(&reader, e))?;
+                if k2_detected {
+                    names.push(text);
+                    k2_detected = false;
+                } else if text == "k2" {
+                    k2_detected = true;
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(xml_error(&reader, e)),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(names)
+}
+
+pub fn get_level_string(ls: Vec<u8>, level_name: Option<String>) -> Result<String, SaveFileError> {
     //decrypting the savefile
     let content = decrypt_savefile(ls)?;
     let string_content = String::from_utf8_lossy(&content);
@@ -733,15 +1339,14 @@ pub fn get_level_string(ls: Vec<u8>, level_name: Option<String>) -> Result<Strin
         match reader.read_event(&mut buf) {
             // unescape and decode the text event using the reader encoding
             Ok(Event::Text(e)) => {
-                let text = e.unescape_and_decode(&reader).unwrap();
+                let text = e
+                    .unescape_and_decode(&reader)
+                    .map_err(|e| xml_error(&reader, e))?;
 
                 if text == "k2" {
                     k2_detected = true;
                     if level_detected {
-                        return Err(
-                            "Level is not initialized! Please open the level, place some objects, then save and quit to initialize the level."
-                            .to_string()
-                        );
+                        return Err(SaveFileError::LevelNotInitialized);
                     }
                 } else if k2_detected {
                     if let Some(level_name) = level_name.clone() {
@@ -762,7 +1367,7 @@ pub fn get_level_string(ls: Vec<u8>, level_name: Option<String>) -> Result<Strin
             }
 
             Ok(Event::Eof) => break, // exits the loop when reaching end of file
-            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+            Err(e) => return Err(xml_error(&reader, e)),
             _ => (), // There are several other `Event`s we do not consider here
         }
 
@@ -771,32 +1376,11 @@ pub fn get_level_string(ls: Vec<u8>, level_name: Option<String>) -> Result<Strin
     }
     buf.clear();
     if level_detected && !k4_detected {
-        return Err(
-            "Level is not initialized! Please open the level, place some objects, then save and quit to initialize the level."
-            .to_string()
-        );
+        return Err(SaveFileError::LevelNotInitialized);
     } else if !k4_detected {
-        if let Some(level_name) = level_name {
-            return Err(format!("Level named \"{}\" was not found!", level_name));
-        } else {
-            return Err(
-                "No level found! Please create a level for SPWN to operate on!".to_string(),
-            );
-        }
+        return Err(SaveFileError::LevelNotFound(level_name));
     }
 
-    /*let mut k4_detected = false;
-    for token in xmlparser::Tokenizer::from(String::from_utf8(buf).unwrap().as_str()) {
-        if let xmlparser::Token::Text { text } = token.unwrap() {
-            if k4_detected {
-                level_string = text.as_str().to_string();
-                break;
-            }
-            if text.as_str() == "k4" {
-                k4_detected = true;
-            }
-        }
-    }*/
     //decrypting level string
     let ls_b64 = base_64_decrypt(
         level_string
@@ -805,31 +1389,99 @@ pub fn get_level_string(ls: Vec<u8>, level_name: Option<String>) -> Result<Strin
             .replace("\0", "")
             .as_bytes()
             .to_vec(),
-    );
+    )?;
 
     //println!("{}", String::from_utf8(ls_b64.clone()).unwrap());
 
-    let mut ls_decoder = gzip::Decoder::new(&ls_b64[..]).unwrap();
+    let data_error = |e: std::io::Error| SaveFileError::LevelData(format!("{}", e));
+    let mut ls_decoder = gzip::Decoder::new(&ls_b64[..]).map_err(data_error)?;
     let mut ls_buf = Vec::new();
-    ls_decoder.read_to_end(&mut ls_buf).unwrap();
+    ls_decoder.read_to_end(&mut ls_buf).map_err(data_error)?;
 
-    Ok(String::from_utf8(ls_buf).unwrap())
+    String::from_utf8(ls_buf).map_err(|e| SaveFileError::LevelData(format!("{}", e)))
 }
 
 use quick_xml::Writer;
 use std::fs;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// How many backups of a savefile are kept. The oldest ones are deleted when a new one is made
+pub const SAVEFILE_BACKUPS: usize = 5;
+
+// copies the savefile next to itself, with the current time in its name
+fn backup_savefile(path: &Path) -> Result<PathBuf, SaveFileError> {
+    use std::time::SystemTime;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+
+    let mut backup_name = path.file_name().unwrap_or_default().to_os_string();
+    backup_name.push(format!(".{}.bak", now));
+    let backup_path = path.with_file_name(backup_name);
+
+    fs::copy(path, &backup_path).map_err(io_error(path))?;
+    remove_old_backups(path, SAVEFILE_BACKUPS)?;
+    Ok(backup_path)
+}
+
+// deletes all but the newest `keep` backups of the savefile
+fn remove_old_backups(path: &Path, keep: usize) -> Result<(), SaveFileError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
 
+    let mut backups: Vec<(u128, PathBuf)> = fs::read_dir(dir)
+        .map_err(io_error(dir))?
+        .filter_map(|entry| {
+            let backup = entry.ok()?.path();
+            // <savefile>.<time>.bak
+            let time = backup
+                .file_name()?
+                .to_str()?
+                .strip_prefix(name.as_ref())?
+                .strip_prefix('.')?
+                .strip_suffix(".bak")?
+                .parse()
+                .ok()?;
+            Some((time, backup))
+        })
+        .collect();
+    backups.sort();
+
+    let old = backups.len().saturating_sub(keep);
+    for (_, backup) in &backups[..old] {
+        fs::remove_file(backup).map_err(io_error(backup))?;
+    }
+    Ok(())
+}
+
+// writes to a temporary file first, so a crash in the middle of writing can't corrupt the savefile
+fn write_atomic(path: &Path, data: &[u8]) -> Result<(), SaveFileError> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = fs::File::create(&temp_path).map_err(io_error(&temp_path))?;
+    file.write_all(data).map_err(io_error(&temp_path))?;
+    file.sync_all().map_err(io_error(&temp_path))?;
+    drop(file);
+
+    fs::rename(&temp_path, path).map_err(io_error(path))
+}
+
+/// Replaces the level string of a level in the savefile at `path`. The old savefile is
+/// backed up first, and the path of the backup is returned.
 pub fn encrypt_level_string(
     ls: String,
     old_ls: String,
     path: PathBuf,
     level_name: Option<String>,
-) -> Result<(), String> {
-    let mut file = fs::File::open(path.clone()).unwrap();
-    let mut file_content = Vec::new();
-    file.read_to_end(&mut file_content).unwrap();
+) -> Result<PathBuf, SaveFileError> {
+    let file_content = fs::read(&path).map_err(io_error(&path))?;
 
     //decrypting the savefile
     let content = decrypt_savefile(file_content)?;
@@ -851,30 +1503,37 @@ pub fn encrypt_level_string(
 
     let full_ls = old_ls + &ls;
 
+    let write_error = |e: quick_xml::Error| SaveFileError::XmlWrite(format!("{:?}", e));
+    let data_error = |e: std::io::Error| SaveFileError::LevelData(format!("{}", e));
+
     loop {
         match reader.read_event(&mut buf) {
             // unescape and decode the text event using the reader encoding
             Ok(Event::Text(e)) => {
-                let text = e.unescape_and_decode(&reader).unwrap();
+                let text = e
+                    .unescape_and_decode(&reader)
+                    .map_err(|e| xml_error(&reader, e))?;
                 if k4_detected && level_detected {
                     let encrypted_ls: String = {
-                        let mut ls_encoder = gzip::Encoder::new(Vec::new()).unwrap();
-                        ls_encoder.write_all(&full_ls.as_bytes()).unwrap();
+                        let mut ls_encoder = gzip::Encoder::new(Vec::new()).map_err(data_error)?;
+                        ls_encoder
+                            .write_all(&full_ls.as_bytes())
+                            .map_err(data_error)?;
                         let b64_encrypted =
-                            base64::encode(&ls_encoder.finish().into_result().unwrap());
+                            base64::encode(&ls_encoder.finish().into_result().map_err(data_error)?);
                         let fin = b64_encrypted.replace("+", "-").replace("/", "_");
                         "H4sIAAAAAAAAC".to_string() + &fin[13..]
                     };
 
-                    assert!(writer
+                    writer
                         .write_event(Event::Text(BytesText::from_plain_str(&encrypted_ls)))
-                        .is_ok());
+                        .map_err(write_error)?;
                     done = true;
                     k4_detected = false;
-                } else if k4_detected {
-                    k4_detected = false;
                 } else {
-                    assert!(writer.write_event(Event::Text(e)).is_ok())
+                    // level data of other levels is kept as it is
+                    k4_detected = false;
+                    writer.write_event(Event::Text(e)).map_err(write_error)?;
                 }
 
                 if k2_detected {
@@ -896,65 +1555,84 @@ pub fn encrypt_level_string(
                 }
 
                 if !done && text == "k2" {
+                    if level_detected {
+                        // the level had no level data, don't write to the next one
+                        return Err(SaveFileError::LevelNotInitialized);
+                    }
                     k2_detected = true
                 }
             }
             Ok(Event::Eof) => break, // exits the loop when reaching end of file
-            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
-            Ok(e) => assert!(writer.write_event(e).is_ok()),
+            Err(e) => return Err(xml_error(&reader, e)),
+            Ok(e) => {
+                writer.write_event(e).map_err(write_error)?;
+            }
         }
 
         // if we don't keep a borrow elsewhere, we can clear the buffer to keep memory usage low
         buf.clear();
     }
+
+    if !done {
+        // nothing would change, so don't touch the savefile
+        return Err(if level_detected {
+            SaveFileError::LevelNotInitialized
+        } else {
+            SaveFileError::LevelNotFound(level_name)
+        });
+    }
+
     let bytes = writer.into_inner().into_inner();
     //encrypt level save
-    use std::io::Write;
-
-    if cfg!(target_os = "macos") {
-        use aes::Aes256;
+    let fin = encrypt_savefile(bytes)?;
 
-        use block_modes::block_padding::Pkcs7;
-        use block_modes::{BlockMode, Ecb};
+    let backup_path = backup_savefile(&path)?;
+    write_atomic(&path, &fin)?;
+    Ok(backup_path)
+}
 
-        const IOS_KEY: &[u8] = &[
-            0x69, 0x70, 0x75, 0x39, 0x54, 0x55, 0x76, 0x35, 0x34, 0x79, 0x76, 0x5D, 0x69, 0x73,
-            0x46, 0x4D, 0x68, 0x35, 0x40, 0x3B, 0x74, 0x2E, 0x35, 0x77, 0x33, 0x34, 0x45, 0x32,
-            0x52, 0x79, 0x40, 0x7B,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_backups() {
+        let dir = std::env::temp_dir().join(format!("spwn-backup-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let savefile = dir.join("CCLocalLevels.dat");
+        fs::write(&savefile, "save").unwrap();
+
+        let old_backups: Vec<PathBuf> = (1..=7)
+            .map(|i| dir.join(format!("CCLocalLevels.dat.{}.bak", i)))
+            .collect();
+        for backup in &old_backups {
+            fs::write(backup, "old").unwrap();
+        }
+        // not backups of this savefile
+        let others = [
+            dir.join("CCGameManager.dat.3.bak"),
+            dir.join("CCLocalLevels.dat.bak"),
+            dir.join("CCLocalLevels.dat.old.bak"),
         ];
+        for other in &others {
+            fs::write(other, "other").unwrap();
+        }
 
-        type AesEcb = Ecb<Aes256, Pkcs7>;
-
-        // re-create cipher mode instance
-        let cipher = AesEcb::new_var(&IOS_KEY, &[]).unwrap();
-
-        let fin = cipher.encrypt_vec(&bytes);
-        assert!(fs::write(path, fin).is_ok());
-    } else {
-        let mut encoder = zlib::Encoder::new(Vec::new()).unwrap();
-        encoder.write_all(&bytes).unwrap();
-        let compressed = encoder.finish().into_result().unwrap();
-        use crc32fast::Hasher;
-
-        let mut hasher = Hasher::new();
-        hasher.update(&bytes);
-        let checksum = hasher.finalize();
-
-        let data_size = bytes.len() as u32;
-
-        let mut with_signature = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x0b".to_vec();
-        with_signature.extend(&compressed[2..compressed.len() - 4]);
-        with_signature.extend(checksum.to_le_bytes().to_vec());
-        with_signature.extend(data_size.to_le_bytes().to_vec());
+        let backup = backup_savefile(&savefile).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "save");
 
-        let encoded = base64::encode(&with_signature)
-            .replace("+", "-")
-            .replace("/", "_")
-            .as_bytes()
-            .to_vec();
+        // the new backup and the newest old ones
+        for old in &old_backups[..3] {
+            assert!(!old.exists(), "{}", old.display());
+        }
+        for old in &old_backups[3..] {
+            assert!(old.exists(), "{}", old.display());
+        }
+        for other in &others {
+            assert!(other.exists(), "{}", other.display());
+        }
+        assert!(savefile.exists());
 
-        let fin = xor(encoded, 11);
-        assert!(fs::write(path, fin).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
     }
-    Ok(())
 }
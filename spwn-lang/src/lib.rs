@@ -0,0 +1,475 @@
+//! The SPWN compiler as a library
+//!
+//! The `spwn` command line tool is a thin wrapper around this crate. Other tools
+//! can compile scripts with [`Compiler`] and get the result back as a
+//! [`CompileOutput`] instead of going through the CLI.
+
+// errors carry a full `CompilerInfo`, boxing them everywhere isn't worth it
+#![allow(clippy::result_large_err)]
+// warnings the compiler code already had before it was a library, to be cleaned up
+// on their own
+#![allow(
+    dead_code,
+    unused_imports,
+    unused_variables,
+    semicolon_in_expressions_from_macros,
+    unknown_lints,
+    clippy::collapsible_match,
+    clippy::expect_fun_call,
+    clippy::explicit_auto_deref,
+    clippy::explicit_counter_loop,
+    clippy::manual_is_multiple_of,
+    clippy::manual_map,
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::needless_lifetimes,
+    clippy::new_without_default,
+    clippy::partialeq_to_none,
+    clippy::question_mark,
+    clippy::redundant_pattern_matching,
+    clippy::to_string_in_format_args,
+    clippy::unnecessary_cast,
+    clippy::unused_enumerate_index,
+    clippy::useless_format,
+    clippy::useless_vec,
+    clippy::while_let_loop
+)]
+
+pub mod ast;
+pub mod builtin;
+pub mod compiler;
+pub mod compiler_types;
+pub mod diagnostic;
+pub mod documentation;
+pub mod fmt;
+pub mod graph;
+pub mod idrange;
+pub mod levelstring;
+pub mod lockfile;
+pub mod lsp;
+pub mod objschema;
+pub mod parser;
+pub mod repl;
+pub mod simulate;
+pub mod sourcemap;
+pub mod target;
+pub mod testing;
+
+pub mod optimize;
+
+#[cfg_attr(target_os = "macos", path = "editorlive_mac.rs")]
+#[cfg_attr(windows, path = "editorlive_win.rs")]
+#[cfg_attr(
+    not(any(target_os = "macos", windows)),
+    path = "editorlive_unavailable.rs"
+)]
+pub mod editorlive;
+
+pub use parser::parse_spwn;
+
+use diagnostic::Diagnostic;
+use levelstring::GDObj;
+
+use std::fs;
+use std::path::PathBuf;
+
+use std::io::Write;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+pub const STD_PATH: &str = "std";
+
+pub fn print_with_color(text: &str, color: Color) {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    stdout
+        .set_color(ColorSpec::new().set_fg(Some(color)))
+        .unwrap();
+    writeln!(&mut stdout, "{}", text).unwrap();
+    stdout.set_color(&ColorSpec::new()).unwrap();
+}
+
+pub fn eprint_with_color(text: &str, color: Color) {
+    let mut stdout = StandardStream::stderr(ColorChoice::Always);
+    stdout
+        .set_color(ColorSpec::new().set_fg(Some(color)))
+        .unwrap();
+    writeln!(&mut stdout, "{}", text).unwrap();
+    stdout.set_color(&ColorSpec::new()).unwrap();
+}
+
+/// Everything produced by a single [`Compiler::compile`] call
+#[derive(Debug)]
+pub struct CompileOutput {
+    /// Objects generated by the script, after optimization
+    pub objects: Vec<GDObj>,
+    /// The target level string with the generated objects added, or only the
+    /// generated objects if no level string was given
+    pub level_string: String,
+    /// Number of groups, colors, block IDs and item IDs used by the objects
+    pub used_ids: [usize; 4],
+    /// Tags on the top of the main file (like `#[no_level]`)
+    pub tags: ast::Tag,
+    /// Errors that stopped the compilation, empty if it succeeded
+    pub diagnostics: Vec<Diagnostic>,
+    /// Trigger graph, if it was asked for with [`Compiler::trigger_graph`]
+    pub trigger_graph: Option<graph::GraphViews>,
+    /// Source locations of the added objects, if asked for with [`Compiler::source_map`]
+    pub source_map: Option<sourcemap::SourceMap>,
+    /// Names of the arbitrary IDs made by the script, for [`lockfile::IdLock::apply`]
+    pub id_names: lockfile::IdNames,
+    /// The lock given with [`Compiler::id_lock`], updated with the IDs of this build
+    pub id_lock: Option<lockfile::IdLock>,
+    /// Reserved IDs and module ID ranges, for [`add_to_level_with`]
+    pub id_config: idrange::IdConfig,
+    /// The version of GD the level was built for
+    pub target: &'static target::TargetProfile,
+    /// Name the objects of the script are registered under in the level
+    pub script_name: String,
+    /// Triggers and groups before and after each optimizer pass that ran
+    pub pass_stats: Vec<optimize::PassStats>,
+}
+
+impl CompileOutput {
+    fn failed(err: Diagnostic) -> Self {
+        Self::failed_with(vec![err])
+    }
+
+    fn failed_with(diagnostics: Vec<Diagnostic>) -> Self {
+        CompileOutput {
+            objects: Vec::new(),
+            level_string: String::new(),
+            used_ids: [0; 4],
+            tags: ast::Tag::new(),
+            diagnostics,
+            trigger_graph: None,
+            source_map: None,
+            id_names: lockfile::IdNames::default(),
+            id_lock: None,
+            id_config: idrange::IdConfig::default(),
+            target: &target::GD_2_1,
+            script_name: String::new(),
+            pass_stats: Vec::new(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+/// Builder for compiling a SPWN script
+///
+/// ```no_run
+/// let output = spwn::Compiler::new("main.spwn").optimize(false).compile();
+/// for diagnostic in &output.diagnostics {
+///     eprintln!("{}", diagnostic);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    path: PathBuf,
+    source: Option<String>,
+    included_paths: Vec<PathBuf>,
+    passes: Vec<optimize::Pass>,
+    level_string: Option<String>,
+    trigger_graph: bool,
+    source_map: bool,
+    id_lock: Option<lockfile::IdLock>,
+    reserved: Vec<(usize, idrange::IdRange)>,
+    target: Option<&'static target::TargetProfile>,
+    signature: Option<String>,
+    recycle_groups: bool,
+}
+
+impl Compiler {
+    /// Compiles the script at `path`. The file is only read if no source is
+    /// given with [`Compiler::source`].
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Compiler {
+            path: path.into(),
+            source: None,
+            included_paths: Vec::new(),
+            passes: optimize::Pass::for_level(2),
+            level_string: None,
+            trigger_graph: false,
+            source_map: false,
+            id_lock: None,
+            reserved: Vec::new(),
+            target: None,
+            signature: None,
+            recycle_groups: false,
+        }
+    }
+
+    pub fn source<S: Into<String>>(mut self, source: S) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Adds a directory to search for libraries in
+    pub fn include_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.included_paths.push(path.into());
+        self
+    }
+
+    pub fn include_paths<I: IntoIterator<Item = PathBuf>>(mut self, paths: I) -> Self {
+        self.included_paths.extend(paths);
+        self
+    }
+
+    /// Runs the default optimizer passes, or none of them
+    pub fn optimize(mut self, enabled: bool) -> Self {
+        self.passes = optimize::Pass::for_level(if enabled { 2 } else { 0 });
+        self
+    }
+
+    /// Runs the optimizer passes of an optimization level from 0 to 3
+    /// (see [`optimize::Pass::for_level`])
+    pub fn opt_level(mut self, level: u8) -> Self {
+        self.passes = optimize::Pass::for_level(level);
+        self
+    }
+
+    /// Runs exactly these optimizer passes, in this order
+    pub fn passes<I: IntoIterator<Item = optimize::Pass>>(mut self, passes: I) -> Self {
+        self.passes = passes.into_iter().collect();
+        self
+    }
+
+    /// Level string the generated objects should be added to. Objects from
+    /// earlier SPWN builds are removed from it first. With an [`Compiler::id_lock`],
+    /// the optimizer also keeps the locked groups that the other objects in it use.
+    pub fn level_string<S: Into<String>>(mut self, level_string: S) -> Self {
+        self.level_string = Some(level_string.into());
+        self
+    }
+
+    /// Also builds the trigger graph of the script, from before and after optimization
+    pub fn trigger_graph(mut self, enabled: bool) -> Self {
+        self.trigger_graph = enabled;
+        self
+    }
+
+    /// Also maps the objects in the level string back to the source that made them
+    pub fn source_map(mut self, enabled: bool) -> Self {
+        self.source_map = enabled;
+        self
+    }
+
+    /// Gives the IDs of the script the same specific IDs they had in the builds of the lock
+    pub fn id_lock(mut self, lock: lockfile::IdLock) -> Self {
+        self.id_lock = Some(lock);
+        self
+    }
+
+    /// Keeps a range of IDs free for objects placed by hand, like `#[reserve(...)]` does.
+    /// `class` is an index into [`idrange::ID_CLASSES`].
+    pub fn reserve(mut self, class: usize, range: idrange::IdRange) -> Self {
+        self.reserved.push((class, range));
+        self
+    }
+
+    /// Builds the level for a version of GD, instead of the one chosen with `#[target]`
+    /// (or 2.1)
+    pub fn target(mut self, target: &'static target::TargetProfile) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Name the objects of the script are registered under in the level, instead of the
+    /// name of the script file. Builds of scripts with other names don't remove them.
+    pub fn signature<S: Into<String>>(mut self, name: S) -> Self {
+        self.signature = Some(name.into());
+        self
+    }
+
+    /// Lets arbitrary groups that can't affect each other share a specific group, like a
+    /// group that is spawned and a group of blocks that is moved. Off by default.
+    pub fn recycle_groups(mut self, enabled: bool) -> Self {
+        self.recycle_groups = enabled;
+        self
+    }
+
+    pub fn compile(mut self) -> CompileOutput {
+        let source = match self.source {
+            Some(s) => s,
+            None => match fs::read_to_string(&self.path) {
+                Ok(s) => s,
+                Err(e) => {
+                    return CompileOutput::failed(Diagnostic::error(
+                        "io_error",
+                        format!("Could not read {}: {}", self.path.to_string_lossy(), e),
+                    ))
+                }
+            },
+        };
+
+        // report every syntax error at once instead of only the first one
+        let (statements, notes) = parser::parse_spwn_recovering(source, self.path.clone());
+        if !notes.errors.is_empty() {
+            return CompileOutput::failed_with(notes.errors.iter().map(Diagnostic::from).collect());
+        }
+        let tags = notes.tag.clone();
+        let script_name = match (self.signature.take(), self.path.file_name()) {
+            (Some(name), _) => name,
+            (None, Some(file)) => file.to_string_lossy().to_string(),
+            (None, None) => String::new(),
+        };
+
+        let mut compiled = match compiler::compile_spwn(
+            statements,
+            self.path,
+            self.included_paths,
+            notes,
+            self.target,
+        ) {
+            Ok(g) => g,
+            Err(e) => return CompileOutput::failed(Diagnostic::from(&e)),
+        };
+        let target = compiled.target;
+        for (class, range) in self.reserved {
+            compiled.id_config.reserve(class, range);
+        }
+        compiled.id_config.recycle_groups = self.recycle_groups;
+
+        let mut trigger_graph = if self.trigger_graph {
+            Some(graph::GraphViews {
+                before: graph::TriggerGraph::new(&compiled.func_ids, target),
+                after: None,
+            })
+        } else {
+            None
+        };
+
+        let has_stuff = compiled.func_ids.iter().any(|x| !x.obj_list.is_empty());
+        let mut pass_stats = Vec::new();
+        if !self.passes.is_empty() && has_stuff {
+            // groups that the objects already in the level use
+            let external = match &self.level_string {
+                Some(level_string) => match optimize::external_groups(
+                    level_string,
+                    Some(&script_name),
+                    &compiled.id_names,
+                    self.id_lock.as_ref(),
+                ) {
+                    Ok(groups) => groups,
+                    Err(e) => return CompileOutput::failed(Diagnostic::error("level_error", e)),
+                },
+                None => Default::default(),
+            };
+            let (func_ids, stats) = optimize::run_passes(
+                compiled.func_ids,
+                compiled.closed_groups,
+                target,
+                &external,
+                &optimize::object_groups(&compiled.objects),
+                &self.passes,
+            );
+            compiled.func_ids = func_ids;
+            pass_stats = stats;
+            if let Some(views) = &mut trigger_graph {
+                views.after = Some(graph::TriggerGraph::new(&compiled.func_ids, target));
+            }
+        }
+
+        let mut objects = levelstring::apply_fn_ids(&compiled.func_ids);
+        levelstring::apply_sync_groups(&mut objects, &compiled.sync_groups);
+        objects.extend(compiled.objects);
+
+        let level_string = self.level_string.unwrap_or_default();
+        if let Some(lock) = &mut self.id_lock {
+            let applied = lock.apply(
+                &mut objects,
+                &compiled.id_names,
+                &compiled.id_config,
+                target,
+                &level_string,
+                Some(&script_name),
+            );
+            if let Err(e) = applied {
+                return CompileOutput::failed(Diagnostic::error("level_error", e));
+            }
+        }
+
+        let level = match add_to_level_with(
+            objects.clone(),
+            level_string,
+            &compiled.id_config,
+            target,
+            Some(&script_name),
+            self.source_map,
+        ) {
+            Ok(r) => r,
+            Err(e) => return CompileOutput::failed(Diagnostic::error("level_error", e)),
+        };
+
+        CompileOutput {
+            objects,
+            level_string: level.level_string,
+            used_ids: level.used_ids,
+            tags,
+            diagnostics: Vec::new(),
+            trigger_graph,
+            source_map: level.source_map,
+            id_names: compiled.id_names,
+            id_lock: self.id_lock,
+            id_config: compiled.id_config,
+            target,
+            script_name,
+            pass_stats,
+        }
+    }
+}
+
+/// Removes objects from earlier builds from `level_string` and adds `objects`
+/// to it, returning the new level string and the number of used IDs
+pub fn add_to_level(
+    objects: Vec<GDObj>,
+    level_string: String,
+) -> Result<(String, [usize; 4]), String> {
+    let level = add_to_level_with(
+        objects,
+        level_string,
+        &idrange::IdConfig::default(),
+        &target::GD_2_1,
+        None,
+        false,
+    )?;
+    Ok((level.level_string, level.used_ids))
+}
+
+/// Result of [`add_to_level_with`]
+#[derive(Debug)]
+pub struct LevelOutput {
+    pub level_string: String,
+    /// Number of groups, colors, block IDs and item IDs used by the objects
+    pub used_ids: [usize; 4],
+    /// Source locations of the added objects, if they were asked for
+    pub source_map: Option<sourcemap::SourceMap>,
+}
+
+/// Like [`add_to_level`], but only gives arbitrary IDs the specific IDs that `ids` and
+/// `target` allow (see [`CompileOutput::id_config`]), and can also map the added objects
+/// back to the source that made them. With a `script` name, only the objects of earlier
+/// builds of that script are removed from the level (see [`levelstring::ScriptRegistry`]).
+pub fn add_to_level_with(
+    objects: Vec<GDObj>,
+    level_string: String,
+    ids: &idrange::IdConfig,
+    target: &target::TargetProfile,
+    script: Option<&str>,
+    source_map: bool,
+) -> Result<LevelOutput, String> {
+    let mut level = levelstring::LevelString::parse(&level_string)?;
+    let signature = levelstring::remove_script_objects(&mut level, script);
+    let used_ids = levelstring::append_objects(objects, &mut level, ids, target, signature)?;
+    let source_map = if source_map {
+        Some(sourcemap::SourceMap::new(&level, signature))
+    } else {
+        None
+    };
+    Ok(LevelOutput {
+        level_string: level.to_string(),
+        used_ids,
+        source_map,
+    })
+}
@@ -0,0 +1,202 @@
+//! `spwn.lock`, which keeps the specific IDs given to `?g`, `?c`, `?b` and `?i` the same
+//! between builds
+//!
+//! Arbitrary IDs are named after the variables that are being assigned when they are made,
+//! outermost first, like `a#0` for the first group made while running `a = ...`, or
+//! `a.b#1` for the second one made by `b = ...` in a macro called by `a = ...`. Variables
+//! assigned in an imported file outside of any assignment in the main file start with the
+//! name of the file, like `counter::id#0`. IDs made while no variable is assigned are
+//! named after the start of the statement in the main file, like `` `10g.move(10, 0)`#0 ``.
+//! None of this depends on line numbers or on the other statements, so adding a new `?g`
+//! somewhere in the script doesn't change the IDs of the other named groups, and objects
+//! placed by hand that use them keep working.
+
+use crate::builtin::{ArbitraryID, SpecificID, ID};
+use crate::compiler::file_stem;
+use crate::compiler_types::CompilerInfo;
+use crate::idrange::{IdConfig, ID_CLASSES};
+use crate::levelstring::{self, GDObj, LevelString};
+use crate::target::TargetProfile;
+
+use serde_json::{json, Value as JsonValue};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+pub const LOCKFILE_NAME: &str = "spwn.lock";
+
+/// Names of the arbitrary IDs made by a script, for every ID class
+/// (groups, colors, block IDs and item IDs)
+#[derive(Debug, Clone, Default)]
+pub struct IdNames {
+    names: [BTreeMap<ArbitraryID, String>; 4],
+    counts: HashMap<(usize, String), usize>,
+}
+
+impl IdNames {
+    /// Names a new arbitrary ID after the variables that are being assigned, or after the
+    /// statement in the main file if there aren't any
+    pub fn name(&mut self, class: usize, id: ArbitraryID, info: &CompilerInfo) {
+        let base = match (&info.assigned_vars, &info.statement_label) {
+            (Some(vars), _) => vars.clone(),
+            (None, Some(label)) => format!("`{}`", label),
+            // made by an imported file before the main file runs
+            (None, None) => format!("{}::", file_stem(&info.current_file)),
+        };
+        let count = self.counts.entry((class, base.clone())).or_insert(0);
+        self.names[class].insert(id, format!("{}#{}", base, count));
+        *count += 1;
+    }
+
+    pub fn get(&self, class: usize, id: ArbitraryID) -> Option<&String> {
+        self.names[class].get(&id)
+    }
+
+    /// Named arbitrary IDs of an ID class
+    pub fn iter(&self, class: usize) -> impl Iterator<Item = (ArbitraryID, &String)> {
+        self.names[class].iter().map(|(id, name)| (*id, name))
+    }
+}
+
+/// Specific IDs given to named arbitrary IDs in earlier builds
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdLock {
+    pub ids: [BTreeMap<String, SpecificID>; 4],
+}
+
+impl IdLock {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let json: JsonValue =
+            serde_json::from_str(s).map_err(|e| format!("Invalid lockfile: {}", e))?;
+        let mut lock = IdLock::default();
+        for (class, name) in ID_CLASSES.iter().enumerate() {
+            let ids = match json.get(name) {
+                Some(JsonValue::Object(ids)) => ids,
+                Some(_) => return Err(format!("Invalid lockfile: \"{}\" is not an object", name)),
+                None => continue,
+            };
+            for (key, id) in ids {
+                let id = match id.as_u64() {
+                    Some(id) if id > 0 && id <= SpecificID::MAX as u64 => id as SpecificID,
+                    _ => return Err(format!("Invalid lockfile: bad ID for \"{}\"", key)),
+                };
+                lock.ids[class].insert(key.clone(), id);
+            }
+        }
+        Ok(lock)
+    }
+
+    /// Named arbitrary IDs that were given one of `specific` in earlier builds
+    pub fn locked_to(
+        &self,
+        class: usize,
+        names: &IdNames,
+        specific: &HashSet<SpecificID>,
+    ) -> Vec<ArbitraryID> {
+        names
+            .iter(class)
+            .filter(|(_, name)| match self.ids[class].get(*name) {
+                Some(id) => specific.contains(id),
+                None => false,
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut out = json!({});
+        for (class, ids) in self.ids.iter().enumerate() {
+            out[ID_CLASSES[class]] = json!(ids);
+        }
+        out
+    }
+
+    /// Replaces the named arbitrary IDs in `objects` with the IDs they had in earlier builds,
+    /// and gives the ones that are new the first IDs that aren't used by the level or by
+    /// other objects. Locked IDs that `config` doesn't allow anymore (because they were
+    /// reserved, or moved out of a module's range) are given new IDs as well. Afterwards
+    /// the lock only has the IDs used in this build. `script` is the name the script's
+    /// objects are registered under in the level (see [`levelstring::ScriptRegistry`]).
+    pub fn apply(
+        &mut self,
+        objects: &mut [GDObj],
+        names: &IdNames,
+        config: &IdConfig,
+        target: &TargetProfile,
+        level_string: &str,
+        script: Option<&str>,
+    ) -> Result<(), String> {
+        let mut level = LevelString::parse(level_string)?;
+        let signature = levelstring::remove_script_objects(&mut level, script);
+        let mut closed = levelstring::get_used_ids(&level);
+        if let ID::Specific(n) = signature.id {
+            closed[0].insert(n);
+        }
+
+        // specific IDs of the script, and the named arbitrary IDs it uses
+        let mut script_ids: [HashSet<SpecificID>; 4] = Default::default();
+        let mut used: [BTreeSet<ArbitraryID>; 4] = Default::default();
+        for obj in objects.iter_mut() {
+            for prop in obj.params.values_mut() {
+                if let Some((class, ids)) = levelstring::param_ids_mut(prop) {
+                    for id in ids {
+                        match *id {
+                            ID::Specific(n) => {
+                                script_ids[class].insert(n);
+                            }
+                            ID::Arbitrary(a) if names.get(class, a).is_some() => {
+                                used[class].insert(a);
+                            }
+                            ID::Arbitrary(_) => (),
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut new_ids: [BTreeMap<String, SpecificID>; 4] = Default::default();
+        let mut id_maps: [HashMap<ArbitraryID, SpecificID>; 4] = Default::default();
+        for class in 0..4 {
+            // locked IDs can be used by objects in the level (that's what they're for),
+            // but not by the script itself
+            let mut taken = script_ids[class].clone();
+            let mut new = Vec::new();
+            for a in &used[class] {
+                let name = names.get(class, *a).unwrap();
+                match self.ids[class].get(name) {
+                    Some(id)
+                        if !taken.contains(id)
+                            && *id <= target.id_limits[class]
+                            && config.allows(class, *a, *id) =>
+                    {
+                        taken.insert(*id);
+                        new_ids[class].insert(name.clone(), *id);
+                        id_maps[class].insert(*a, *id);
+                    }
+                    _ => new.push((*a, name)),
+                }
+            }
+            closed[class].extend(taken);
+            for (a, name) in new {
+                let id = config.allocate(class, a, &closed[class], target.id_limits[class])?;
+                closed[class].insert(id);
+                new_ids[class].insert(name.clone(), id);
+                id_maps[class].insert(a, id);
+            }
+        }
+
+        for obj in objects.iter_mut() {
+            for prop in obj.params.values_mut() {
+                if let Some((class, ids)) = levelstring::param_ids_mut(prop) {
+                    for id in ids {
+                        if let ID::Arbitrary(a) = *id {
+                            if let Some(specific) = id_maps[class].get(&a) {
+                                *id = ID::Specific(*specific);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.ids = new_ids;
+        Ok(())
+    }
+}
@@ -0,0 +1,1093 @@
+//! Language server for SPWN scripts, speaking LSP over stdio
+//!
+//! Supports parse diagnostics, go-to-definition for variables, types and imports,
+//! hover text from `#[desc(...)]` tags and completion of `obj_props` keys and type members.
+
+use crate::ast;
+use crate::compiler::import_module;
+use crate::compiler_types::{
+    find_key_for_value, CompilerInfo, Context, Globals, ImportType, Value,
+};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::parser::{parse_spwn_recovering, FileRange};
+use crate::STD_PATH;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value as JsonValue};
+
+// https://microsoft.github.io/language-server-protocol/specifications/specification-3-15/#responseMessage
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// CompletionItemKind
+const KIND_METHOD: u8 = 2;
+const KIND_FIELD: u8 = 5;
+const KIND_VARIABLE: u8 = 6;
+const KIND_CONSTANT: u8 = 21;
+
+/// Something a name can refer to, in a script or a library
+#[derive(Debug, Clone)]
+struct Definition {
+    name: String,
+    file: PathBuf,
+    pos: FileRange,
+    desc: Option<String>,
+    /// Short description of what kind of value this is, like a macro signature
+    detail: String,
+}
+
+/// Names defined by one parsed script
+#[derive(Debug, Default)]
+struct ScriptInfo {
+    variables: Vec<Definition>,
+    // defined with `impl @type { ... }`
+    members: Vec<Definition>,
+    types: Vec<Definition>,
+    imports: Vec<(FileRange, ImportType)>,
+}
+
+/// Everything exported by the standard library
+#[derive(Debug, Default)]
+struct LibraryInfo {
+    exports: Vec<Definition>,
+    // type name -> members
+    members: HashMap<String, Vec<Definition>>,
+    types: Vec<Definition>,
+    obj_props: Vec<String>,
+}
+
+struct Document {
+    path: PathBuf,
+    text: String,
+    info: ScriptInfo,
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    includes: Vec<PathBuf>,
+    library: Option<LibraryInfo>,
+    shutting_down: bool,
+}
+
+/// Runs the language server on stdin/stdout until the client sends `exit`
+pub fn run(includes: Vec<PathBuf>) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+
+    let mut server = Server {
+        documents: HashMap::new(),
+        includes,
+        library: None,
+        shutting_down: false,
+    };
+
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or("").to_string();
+        if method == "exit" {
+            break;
+        }
+
+        let (response, notifications) = server.handle(&method, &message["params"]);
+
+        for notification in notifications {
+            write_message(&mut output, &notification)?;
+        }
+
+        // messages without an id are notifications, and get no response
+        if let Some(id) = message.get("id") {
+            let response = match response {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            };
+            write_message(&mut output, &response)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<JsonValue>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(len) = header.strip_prefix("Content-Length:") {
+            length = len.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = match length {
+        Some(l) => l,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Message is missing Content-Length header",
+            ))
+        }
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &JsonValue) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+type Response = Result<JsonValue, (i64, String)>;
+
+impl Server {
+    /// Handles a request or notification, returning the response and any
+    /// notifications that should be sent to the client
+    fn handle(&mut self, method: &str, params: &JsonValue) -> (Response, Vec<JsonValue>) {
+        let mut notifications = Vec::new();
+        if self.shutting_down {
+            return (
+                Err((INVALID_REQUEST, "The server is shutting down".to_string())),
+                notifications,
+            );
+        }
+        let response = match method {
+            "initialize" => {
+                if let Some(root) = params["rootUri"].as_str().and_then(uri_to_path) {
+                    self.includes.insert(0, root);
+                }
+                Ok(json!({
+                    "capabilities": {
+                        // full document sync
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "hoverProvider": true,
+                        "completionProvider": { "triggerCharacters": [".", ":", "@"] },
+                    },
+                    "serverInfo": { "name": "spwn", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(JsonValue::Null)
+            }
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                notifications.push(self.update_document(uri, text.to_string()));
+                Ok(JsonValue::Null)
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                // with full sync the last change holds the whole document
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    notifications.push(self.update_document(uri, text.to_string()));
+                }
+                Ok(JsonValue::Null)
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    self.documents.remove(uri);
+                }
+                Ok(JsonValue::Null)
+            }
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "initialized" | "$/cancelRequest" | "workspace/didChangeConfiguration" => {
+                Ok(JsonValue::Null)
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method: {}", method))),
+        };
+        (response, notifications)
+    }
+
+    fn update_document(&mut self, uri: &str, text: String) -> JsonValue {
+        let path = uri_to_path(uri).unwrap_or_else(|| PathBuf::from(uri));
+        // statements with syntax errors are skipped, so everything else still has definitions
+        let (statements, notes) = parse_spwn_recovering(text.clone(), path.clone());
+        let info = collect_script_info(&statements, &path);
+        let diagnostics: Vec<Diagnostic> = notes.errors.iter().map(Diagnostic::from).collect();
+
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": uri,
+                "diagnostics": diagnostics
+                    .iter()
+                    .map(|d| lsp_diagnostic(d, &text))
+                    .collect::<Vec<_>>(),
+            },
+        });
+        self.documents
+            .insert(uri.to_string(), Document { path, text, info });
+        notification
+    }
+
+    fn library(&mut self) -> &LibraryInfo {
+        if self.library.is_none() {
+            self.library = Some(load_library(&self.includes).unwrap_or_default());
+        }
+        self.library.as_ref().unwrap()
+    }
+
+    fn word_at(&self, params: &JsonValue) -> Option<(&Document, Word)> {
+        let doc = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        let word = word_at(&doc.text, line, character)?;
+        Some((doc, word))
+    }
+
+    fn definition(&mut self, params: &JsonValue) -> JsonValue {
+        let (path, text, word, import) = match self.word_at(params) {
+            Some((doc, word)) => {
+                let import = doc
+                    .info
+                    .imports
+                    .iter()
+                    .find(|(pos, _)| contains(*pos, word.pos))
+                    .map(|(_, import)| import.clone());
+                (doc.path.clone(), doc.text.clone(), word, import)
+            }
+            None => return JsonValue::Null,
+        };
+
+        if let Some(import) = import {
+            return match resolve_import(&import, &path, &self.includes) {
+                Some(file) => location(&file, ((1, 0), (1, 0)), None),
+                None => JsonValue::Null,
+            };
+        }
+
+        match self.find_definition(params, &word) {
+            Some(def) if def.file == path => location(&def.file, def.pos, Some(&text)),
+            Some(def) if def.file.is_file() => {
+                let text = std::fs::read_to_string(&def.file).ok();
+                location(&def.file, def.pos, text.as_deref())
+            }
+            _ => JsonValue::Null,
+        }
+    }
+
+    fn hover(&mut self, params: &JsonValue) -> JsonValue {
+        let word = match self.word_at(params) {
+            Some((_, word)) => word,
+            None => return JsonValue::Null,
+        };
+        match self.find_definition(params, &word) {
+            Some(def) => {
+                let mut contents = format!("```spwn\n{}\n```", def.detail);
+                if let Some(desc) = &def.desc {
+                    contents += &format!("\n\n{}", desc);
+                }
+                json!({ "contents": { "kind": "markdown", "value": contents } })
+            }
+            None => JsonValue::Null,
+        }
+    }
+
+    /// Looks for the definition of a word in the document first, then in the standard library
+    fn find_definition(&mut self, params: &JsonValue, word: &Word) -> Option<Definition> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        if let Some(doc) = self.documents.get(uri) {
+            let defs = match word.prefix {
+                Prefix::Type => &doc.info.types,
+                Prefix::Member => &doc.info.members,
+                Prefix::None => &doc.info.variables,
+            };
+            if let Some(def) = last_before(defs, &word.name, word.pos) {
+                return Some(def);
+            }
+        }
+
+        let library = self.library();
+        match word.prefix {
+            Prefix::Type => library.types.iter().find(|d| d.name == word.name),
+            Prefix::Member => library
+                .members
+                .values()
+                .flatten()
+                .find(|d| d.name == word.name),
+            Prefix::None => library.exports.iter().find(|d| d.name == word.name),
+        }
+        .cloned()
+    }
+
+    fn completion(&mut self, params: &JsonValue) -> JsonValue {
+        let (line_text, locals) = {
+            let doc = match self
+                .documents
+                .get(params["textDocument"]["uri"].as_str().unwrap_or(""))
+            {
+                Some(d) => d,
+                None => return json!([]),
+            };
+            let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+            let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+            let line_text = doc.text.lines().nth(line).unwrap_or("");
+            let end = byte_index(line_text, character);
+            (line_text[..end].to_string(), doc.info.variables.clone())
+        };
+
+        let mut items = Vec::new();
+        let before_word = line_text.trim_end_matches(is_word_char);
+
+        if before_word.ends_with('.') || before_word.ends_with("::") {
+            // members of `@type.` / `@type::` or, if the type is unknown, of every type
+            let before_sep = before_word.trim_end_matches(['.', ':']);
+            let type_name = before_sep.trim_end_matches(is_word_char);
+            let type_name = if type_name.ends_with('@') {
+                Some(&before_sep[type_name.len()..])
+            } else {
+                None
+            };
+
+            let library = self.library();
+            for (typ, members) in &library.members {
+                if type_name.map(|t| t == typ).unwrap_or(true) {
+                    for member in members {
+                        items.push(completion_item(member, KIND_METHOD, Some(typ)));
+                    }
+                }
+            }
+        } else if before_word.ends_with('@') {
+            for typ in &self.library().types {
+                items.push(completion_item(typ, KIND_CONSTANT, None));
+            }
+        } else {
+            let mut seen = Vec::new();
+            for def in locals.iter().rev() {
+                if !seen.contains(&def.name) {
+                    seen.push(def.name.clone());
+                    items.push(completion_item(def, KIND_VARIABLE, None));
+                }
+            }
+            let library = self.library();
+            for key in &library.obj_props {
+                items.push(json!({ "label": key, "kind": KIND_FIELD, "detail": "obj_props" }));
+            }
+            for def in &library.exports {
+                if !seen.contains(&def.name) {
+                    items.push(completion_item(def, KIND_VARIABLE, None));
+                }
+            }
+        }
+
+        json!(items)
+    }
+}
+
+fn completion_item(def: &Definition, kind: u8, typ: Option<&str>) -> JsonValue {
+    let detail = match typ {
+        Some(t) => format!("@{}: {}", t, def.detail),
+        None => def.detail.clone(),
+    };
+    json!({
+        "label": def.name,
+        "kind": kind,
+        "detail": detail,
+        "documentation": def.desc,
+    })
+}
+
+fn last_before(defs: &[Definition], name: &str, pos: FileRange) -> Option<Definition> {
+    defs.iter()
+        .rev()
+        .filter(|d| d.name == name)
+        .find(|d| d.pos.0 <= pos.0)
+        .or_else(|| defs.iter().find(|d| d.name == name))
+        .cloned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Prefix {
+    None,
+    /// `@name`
+    Type,
+    /// `.name` or `::name`
+    Member,
+}
+
+#[derive(Debug, Clone)]
+struct Word {
+    name: String,
+    prefix: Prefix,
+    pos: FileRange,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Converts an LSP character offset (in UTF-16 code units) to a byte index in the line
+fn byte_index(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+fn utf16_column(line: &str, byte: usize) -> usize {
+    line.get(..byte.min(line.len()))
+        .map(|s| s.encode_utf16().count())
+        .unwrap_or(byte)
+}
+
+fn word_at(text: &str, line: usize, character: usize) -> Option<Word> {
+    let line_text = text.lines().nth(line)?;
+    let index = byte_index(line_text, character);
+
+    let start = line_text[..index]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(index);
+    let end = line_text[index..]
+        .char_indices()
+        .find(|(_, c)| !is_word_char(*c))
+        .map(|(i, _)| index + i)
+        .unwrap_or_else(|| line_text.len());
+    if start == end {
+        return None;
+    }
+
+    let before = &line_text[..start];
+    if before.ends_with("$.") {
+        // built-in functions aren't defined anywhere
+        return None;
+    }
+    let prefix = if before.ends_with('@') {
+        Prefix::Type
+    } else if before.ends_with('.') || before.ends_with("::") {
+        Prefix::Member
+    } else {
+        Prefix::None
+    };
+    Some(Word {
+        name: line_text[start..end].to_string(),
+        prefix,
+        pos: ((line + 1, start), (line + 1, end)),
+    })
+}
+
+fn contains(outer: FileRange, inner: FileRange) -> bool {
+    outer.0 <= inner.0 && inner.1 <= outer.1
+}
+
+fn lsp_range(range: FileRange, text: Option<&str>) -> JsonValue {
+    let position = |(line, col): (usize, usize)| {
+        let line = line.max(1) - 1;
+        let character = match text.and_then(|t| t.lines().nth(line)) {
+            Some(l) => utf16_column(l, col),
+            None => col,
+        };
+        json!({ "line": line, "character": character })
+    };
+    json!({ "start": position(range.0), "end": position(range.1) })
+}
+
+fn location(file: &Path, range: FileRange, text: Option<&str>) -> JsonValue {
+    json!({ "uri": path_to_uri(file), "range": lsp_range(range, text) })
+}
+
+fn lsp_diagnostic(diagnostic: &Diagnostic, text: &str) -> JsonValue {
+    let range = match &diagnostic.primary {
+        Some(span) => span.range,
+        None => ((1, 0), (1, 0)),
+    };
+    json!({
+        "range": lsp_range(range, Some(text)),
+        "severity": match diagnostic.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+            Severity::Note => 3,
+        },
+        "code": diagnostic.code,
+        "source": "spwn",
+        "message": diagnostic.message,
+    })
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let bytes = uri.strip_prefix("file://")?.as_bytes();
+    let hex = |b: Option<&u8>| b.and_then(|b| (*b as char).to_digit(16));
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let (Some(high), Some(low)) = (hex(bytes.get(i + 1)), hex(bytes.get(i + 2))) {
+                decoded.push((high * 16 + low) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // file:///C:/... on windows
+    if cfg!(windows) {
+        Some(PathBuf::from(path.trim_start_matches('/')))
+    } else {
+        Some(PathBuf::from(path))
+    }
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from(if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:///"
+    });
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(b as char)
+            }
+            _ => uri += &format!("%{:02X}", b),
+        }
+    }
+    uri
+}
+
+fn resolve_import(import: &ImportType, current: &Path, includes: &[PathBuf]) -> Option<PathBuf> {
+    let path = match import {
+        ImportType::Script(p) => current.parent()?.join(p),
+        ImportType::Lib(name) => includes
+            .iter()
+            .map(|i| i.join("libraries").join(name))
+            .find(|p| p.exists())?,
+    };
+    if path.is_dir() {
+        Some(path.join("lib.spwn"))
+    } else if path.is_file() {
+        Some(path)
+    } else if path.extension().is_none() && path.with_extension("spwn").is_file() {
+        Some(path.with_extension("spwn"))
+    } else {
+        None
+    }
+}
+
+fn macro_signature(name: &str, mac: &ast::Macro) -> String {
+    let args: Vec<String> = mac
+        .args
+        .iter()
+        .map(|(arg, default, _, typ)| {
+            let mut out = arg.clone();
+            if let Some(typ) = typ {
+                out += &format!(": {}", crate::fmt::SpwnFmt::fmt(typ, 0));
+            }
+            if let Some(default) = default {
+                out += &format!(" = {}", crate::fmt::SpwnFmt::fmt(default, 0));
+            }
+            out
+        })
+        .collect();
+    format!("{}: ({})", name, args.join(", "))
+}
+
+// `tag` is the tag in front of the name, as in `#[desc("...")] name = ...`
+fn value_definition(
+    name: &str,
+    value: &ast::Expression,
+    tag: &ast::Tag,
+    file: &Path,
+    pos: FileRange,
+) -> Definition {
+    let (desc, detail) = match value.values.first().map(|v| &v.value.body) {
+        Some(ast::ValueBody::Macro(m)) if value.values.len() == 1 => {
+            (m.properties.get_desc(), macro_signature(name, m))
+        }
+        _ => (
+            None,
+            format!("{} = {}", name, crate::fmt::SpwnFmt::fmt(value, 0)),
+        ),
+    };
+    Definition {
+        name: name.to_string(),
+        file: file.to_path_buf(),
+        pos,
+        desc: desc.or_else(|| tag.get_desc()),
+        detail,
+    }
+}
+
+fn collect_script_info(statements: &[ast::Statement], file: &Path) -> ScriptInfo {
+    let mut info = ScriptInfo::default();
+    collect_statements(statements, file, &mut info);
+    info.variables.sort_by_key(|d| d.pos);
+    info
+}
+
+fn collect_statements(statements: &[ast::Statement], file: &Path, info: &mut ScriptInfo) {
+    for statement in statements {
+        collect_statement(statement, file, info)
+    }
+}
+
+fn collect_statement(statement: &ast::Statement, file: &Path, info: &mut ScriptInfo) {
+    use ast::StatementBody::*;
+    match &statement.body {
+        Expr(expr) => {
+            // `let a = ...` and `a = ...`
+            if expr.values.len() == 2
+                && expr.operators.first() == Some(&ast::Operator::Assign)
+                && expr.values[0].path.is_empty()
+            {
+                if let ast::ValueBody::Symbol(name) = &expr.values[0].value.body {
+                    let value = ast::Expression {
+                        values: expr.values[1..].to_vec(),
+                        operators: Vec::new(),
+                    };
+                    info.variables.push(value_definition(
+                        name,
+                        &value,
+                        &expr.values[0].tag,
+                        file,
+                        expr.values[0].pos,
+                    ));
+                }
+            }
+            collect_expr(expr, file, info)
+        }
+        Call(call) => collect_variable(&call.function, file, info),
+        TypeDef(name) => info.types.push(Definition {
+            name: name.clone(),
+            file: file.to_path_buf(),
+            pos: statement.pos,
+            desc: None,
+            detail: format!("type @{}", name),
+        }),
+        Return(Some(expr)) | Extract(expr) => collect_expr(expr, file, info),
+        Error(e) => collect_expr(&e.message, file, info),
+        Impl(imp) => {
+            for member in &imp.members {
+                if let ast::DictDef::Def((name, value)) = member {
+                    // members don't keep their own position, the value's is the closest
+                    let pos = value.values.first().map_or(statement.pos, |v| v.pos);
+                    info.members
+                        .push(value_definition(name, value, &ast::Tag::new(), file, pos));
+                }
+            }
+            collect_dict(&imp.members, file, info)
+        }
+        If(i) => {
+            collect_expr(&i.condition, file, info);
+            collect_statements(&i.if_body, file, info);
+            if let Some(body) = &i.else_body {
+                collect_statements(body, file, info);
+            }
+        }
+        For(f) => {
+            info.variables.push(Definition {
+                name: f.symbol.clone(),
+                file: file.to_path_buf(),
+                pos: statement.pos,
+                desc: None,
+                detail: format!(
+                    "for {} in {}",
+                    f.symbol,
+                    crate::fmt::SpwnFmt::fmt(&f.array, 0)
+                ),
+            });
+            collect_expr(&f.array, file, info);
+            collect_statements(&f.body, file, info);
+        }
+        Sync(s) => {
+            collect_expr(&s.part, file, info);
+            collect_statements(&s.body, file, info);
+        }
+        While(w) => {
+            collect_expr(&w.condition, file, info);
+            collect_statements(&w.body, file, info);
+        }
+        Return(None) | Break | Continue | Invalid(_) => (),
+    }
+}
+
+fn collect_dict(defs: &[ast::DictDef], file: &Path, info: &mut ScriptInfo) {
+    for def in defs {
+        match def {
+            ast::DictDef::Def((_, expr)) | ast::DictDef::Extract(expr) => {
+                collect_expr(expr, file, info)
+            }
+        }
+    }
+}
+
+fn collect_expr(expr: &ast::Expression, file: &Path, info: &mut ScriptInfo) {
+    for value in &expr.values {
+        collect_variable(value, file, info)
+    }
+}
+
+fn collect_variable(var: &ast::Variable, file: &Path, info: &mut ScriptInfo) {
+    use ast::ValueBody::*;
+    match &var.value.body {
+        Import(import, _) => info.imports.push((var.pos, import.clone())),
+        CmpStmt(cmp) => collect_statements(&cmp.statements, file, info),
+        Dictionary(defs) => collect_dict(defs, file, info),
+        Expression(e) => collect_expr(e, file, info),
+        Array(values) => {
+            for e in values {
+                collect_expr(e, file, info)
+            }
+        }
+        Obj(obj) => {
+            for (key, value) in &obj.props {
+                collect_expr(key, file, info);
+                collect_expr(value, file, info);
+            }
+        }
+        Macro(m) => {
+            for (name, default, _, _) in &m.args {
+                info.variables.push(Definition {
+                    name: name.clone(),
+                    file: file.to_path_buf(),
+                    pos: var.pos,
+                    desc: None,
+                    detail: format!("argument {}", name),
+                });
+                if let Some(default) = default {
+                    collect_expr(default, file, info)
+                }
+            }
+            collect_statements(&m.body.statements, file, info)
+        }
+        Ternary(t) => {
+            collect_expr(&t.conditional, file, info);
+            collect_expr(&t.do_if, file, info);
+            collect_expr(&t.do_else, file, info);
+        }
+        Switch(value, cases) => {
+            collect_expr(value, file, info);
+            for case in cases {
+                collect_expr(&case.body, file, info)
+            }
+        }
+        _ => (),
+    }
+
+    for path in &var.path {
+        match path {
+            ast::Path::Call(args) => {
+                for arg in args {
+                    collect_expr(&arg.value, file, info)
+                }
+            }
+            ast::Path::Index(e) => collect_expr(e, file, info),
+            ast::Path::Constructor(defs) => collect_dict(defs, file, info),
+            _ => (),
+        }
+    }
+}
+
+/// Compiles the standard library to find its exports, type members and object properties
+fn load_library(includes: &[PathBuf]) -> Option<LibraryInfo> {
+    let mut globals = Globals::new(PathBuf::new());
+    let mut info = CompilerInfo::new();
+    info.includes = includes.to_vec();
+
+    let module = import_module(
+        &ImportType::Lib(STD_PATH.to_string()),
+        &Context::new(),
+        &mut globals,
+        info,
+        false,
+    )
+    .ok()?;
+
+    let mut library = LibraryInfo::default();
+    let mut sources = LibrarySources::new(includes);
+
+    if let Some((exports, _)) = module.first() {
+        if let Value::Dict(d) = &globals.stored_values[*exports] {
+            for (name, val) in d {
+                library.exports.push(library_definition(
+                    name,
+                    *val,
+                    false,
+                    &globals,
+                    &mut sources,
+                ));
+            }
+            if let Some(props) = d.get("obj_props") {
+                if let Value::Dict(props) = &globals.stored_values[*props] {
+                    library.obj_props = props.keys().cloned().collect();
+                    library.obj_props.sort();
+                }
+            }
+        }
+    }
+    library.exports.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for (typ, members) in &globals.implementations {
+        let type_name = match find_key_for_value(&globals.type_ids, *typ) {
+            Some(n) => n.clone(),
+            None => continue,
+        };
+        let mut defs: Vec<Definition> = members
+            .iter()
+            .map(|(name, (val, _))| library_definition(name, *val, true, &globals, &mut sources))
+            .collect();
+        defs.sort_by(|a, b| a.name.cmp(&b.name));
+        library.members.insert(type_name, defs);
+    }
+
+    for (name, (_, file, pos)) in &globals.type_ids {
+        library.types.push(Definition {
+            name: name.clone(),
+            file: file.clone(),
+            pos: (*pos, *pos),
+            desc: None,
+            detail: format!("type @{}", name),
+        });
+    }
+    library.types.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Some(library)
+}
+
+/// Parsed standard library files, for finding where library values are defined
+struct LibrarySources {
+    files: HashMap<PathBuf, ScriptInfo>,
+    // every .spwn file in the standard library, for values that don't know their file
+    all: Vec<PathBuf>,
+}
+
+impl LibrarySources {
+    fn new(includes: &[PathBuf]) -> Self {
+        let lib = resolve_import(
+            &ImportType::Lib(STD_PATH.to_string()),
+            Path::new(""),
+            includes,
+        );
+        let mut all: Vec<PathBuf> = lib
+            .as_ref()
+            .and_then(|lib| std::fs::read_dir(lib.parent()?).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("spwn"))
+            .collect();
+        all.sort();
+        LibrarySources {
+            files: HashMap::new(),
+            all,
+        }
+    }
+
+    fn info(&mut self, file: &Path) -> Option<&ScriptInfo> {
+        if !self.files.contains_key(file) {
+            let text = std::fs::read_to_string(file).ok()?;
+            let (statements, _) = parse_spwn_recovering(text, file.to_path_buf());
+            let info = collect_script_info(&statements, file);
+            self.files.insert(file.to_path_buf(), info);
+        }
+        self.files.get(file)
+    }
+
+    /// Where `name` is defined as a variable, or as a type member if `member` is set.
+    /// Searches `file` if it's known, and every library file otherwise
+    fn find(&mut self, file: Option<&Path>, name: &str, member: bool) -> Option<Definition> {
+        let files = match file {
+            Some(file) => vec![file.to_path_buf()],
+            None => self.all.clone(),
+        };
+        files.iter().find_map(|file| {
+            let info = self.info(file)?;
+            let defs = if member {
+                &info.members
+            } else {
+                &info.variables
+            };
+            defs.iter().rev().find(|d| d.name == name).cloned()
+        })
+    }
+}
+
+fn library_definition(
+    name: &str,
+    val: usize,
+    member: bool,
+    globals: &Globals,
+    sources: &mut LibrarySources,
+) -> Definition {
+    let (file, desc, detail) = match &globals.stored_values[val] {
+        Value::Macro(m) => (
+            Some(m.def_file.as_path()),
+            m.tag.get_desc(),
+            format!(
+                "{}: ({})",
+                name,
+                m.args
+                    .iter()
+                    .map(|a| a.0.clone())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ),
+        _ => (
+            None,
+            None,
+            format!("{}: @{}", name, globals.get_type_str(val)),
+        ),
+    };
+    // exports can be renamed, like `counter: @counter::new`, so fall back to the file itself
+    let (file, pos) = match sources.find(file, name, member) {
+        Some(def) => (def.file, def.pos),
+        None => (
+            file.map(Path::to_path_buf).unwrap_or_default(),
+            ((1, 0), (1, 0)),
+        ),
+    };
+    Definition {
+        name: name.to_string(),
+        file,
+        pos,
+        desc,
+        detail,
+    }
+}
+
+// the helpers of the integration tests
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use super::common::with_stack;
+    use super::*;
+
+    fn server() -> Server {
+        Server {
+            documents: HashMap::new(),
+            includes: vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))],
+            library: None,
+            shutting_down: false,
+        }
+    }
+
+    fn open(server: &mut Server, uri: &str, text: &str) {
+        let _ = server.handle(
+            "textDocument/didOpen",
+            &json!({ "textDocument": { "uri": uri, "text": text } }),
+        );
+    }
+
+    fn definition(server: &mut Server, uri: &str, line: usize, character: usize) -> JsonValue {
+        let params = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character },
+        });
+        server.handle("textDocument/definition", &params).0.unwrap()
+    }
+
+    #[test]
+    fn decodes_uris() {
+        assert_eq!(
+            uri_to_path("file:///home/a%20b/c.spwn"),
+            Some(PathBuf::from("/home/a b/c.spwn"))
+        );
+        // escape at the very end
+        assert_eq!(uri_to_path("file:///a%41"), Some(PathBuf::from("/aA")));
+        // non-ascii characters, escaped and not
+        assert_eq!(
+            uri_to_path("file:///%C3%A6/\u{e6}%"),
+            Some(PathBuf::from("/\u{e6}/\u{e6}%"))
+        );
+        assert_eq!(
+            uri_to_path("file:///\u{e6}%\u{e6}"),
+            Some(PathBuf::from("/\u{e6}%\u{e6}"))
+        );
+        assert_eq!(uri_to_path("file:///a%4"), Some(PathBuf::from("/a%4")));
+        assert_eq!(uri_to_path("untitled:1"), None);
+    }
+
+    #[test]
+    fn uris_round_trip() {
+        let path = PathBuf::from("/home/\u{e6} b/100%.spwn");
+        assert_eq!(uri_to_path(&path_to_uri(&path)), Some(path));
+    }
+
+    #[test]
+    fn goes_to_local_definition() {
+        let mut server = server();
+        let uri = "file:///nonexistent/main.spwn";
+        open(&mut server, uri, "a = 1\nb = 2\na = 3\n$.print(a + b)\n");
+
+        // the last definition before the use
+        let def = definition(&mut server, uri, 3, 8);
+        assert_eq!(def["uri"], uri);
+        assert_eq!(def["range"]["start"], json!({ "line": 2, "character": 0 }));
+        let def = definition(&mut server, uri, 3, 12);
+        assert_eq!(def["range"]["start"], json!({ "line": 1, "character": 0 }));
+
+        // built-ins aren't defined anywhere
+        assert_eq!(definition(&mut server, uri, 3, 2), JsonValue::Null);
+    }
+
+    #[test]
+    fn goes_to_library_definition() {
+        with_stack(library_definition_test);
+    }
+
+    fn library_definition_test() {
+        let mut server = server();
+        let uri = "file:///nonexistent/main.spwn";
+        open(&mut server, uri, "wait(1)\n1g.move(10, 0)\n");
+
+        let std_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("libraries/std");
+        let expected = |file: &str, name: &str| {
+            let text = std::fs::read_to_string(std_dir.join(file)).unwrap();
+            let line = text
+                .lines()
+                .position(|l| l.trim_start().starts_with(&format!("{} ", name)))
+                .unwrap();
+            (path_to_uri(&std_dir.join(file)), line)
+        };
+
+        let def = definition(&mut server, uri, 0, 1);
+        let (file, line) = expected("control_flow.spwn", "wait");
+        assert_eq!(def["uri"], file);
+        assert_eq!(def["range"]["start"]["line"], line);
+
+        // members point at their value, which can start on the line after the name
+        let def = definition(&mut server, uri, 1, 5);
+        let (file, line) = expected("group.spwn", "move:");
+        assert_eq!(def["uri"], file);
+        let start = def["range"]["start"]["line"].as_u64().unwrap() as usize;
+        assert!((line..=line + 1).contains(&start), "{} {}", start, line);
+    }
+}
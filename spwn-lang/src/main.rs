@@ -1,60 +1,173 @@
 //#![feature(arbitrary_enum_discriminant)]
 
-mod ast;
-mod builtin;
-mod compiler;
-mod compiler_types;
-mod documentation;
-mod fmt;
-mod levelstring;
-mod parser;
-
-mod optimize;
-
-#[cfg_attr(target_os = "macos", path = "editorlive_mac.rs")]
-#[cfg_attr(windows, path = "editorlive_win.rs")]
-#[cfg_attr(
-    not(any(target_os = "macos", windows)),
-    path = "editorlive_unavailable.rs"
-)]
-mod editorlive;
-
-use optimize::optimize;
-
-use parser::*;
+use spwn::diagnostic::Diagnostic;
+use spwn::editorlive::editor_paste;
+use spwn::optimize::Pass;
+use spwn::target::TargetProfile;
+use spwn::{
+    documentation, eprint_with_color, fmt, idrange, levelstring, lockfile, parser,
+    print_with_color, repl, testing, Compiler,
+};
 
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use std::fs;
-
-use editorlive::editor_paste;
-
-pub const STD_PATH: &str = "std";
+use std::io::Write;
 
 const ERROR_EXIT_CODE: i32 = 1;
 
-use std::io::Write;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 const HELP: &str = include_str!("../help.txt");
 
-fn print_with_color(text: &str, color: Color) {
-    let mut stdout = StandardStream::stdout(ColorChoice::Always);
-    stdout
-        .set_color(ColorSpec::new().set_fg(Some(color)))
-        .unwrap();
-    writeln!(&mut stdout, "{}", text).unwrap();
-    stdout.set_color(&ColorSpec::new()).unwrap();
+fn report(diagnostic: &Diagnostic, json_messages: bool) {
+    if json_messages {
+        println!("{}", diagnostic.to_json());
+    } else {
+        diagnostic.eprint();
+    }
+}
+
+fn default_save_file() -> PathBuf {
+    if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var("localappdata").expect("No local app data"))
+            .join("GeometryDash/CCLocalLevels.dat")
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var("HOME").expect("No home directory"))
+            .join("Library/Application Support/GeometryDash/CCLocalLevels.dat")
+    } else if cfg!(target_os = "linux") {
+        PathBuf::from(std::env::var("HOME").expect("No home directory"))
+            .join(".steam/steam/steamapps/compatdata/322170/pfx/drive_c/users/steamuser/Local Settings/Application Data/GeometryDash/CCLocalLevels.dat")
+    } else {
+        panic!("Unsupported operating system");
+    }
+}
+
+fn default_included_paths() -> Vec<PathBuf> {
+    //change to current_exe before release (from current_dir)
+    vec![
+        std::env::current_dir().expect("Cannot access current directory"),
+        std::env::current_exe()
+            .expect("Cannot access directory of executable")
+            .parent()
+            .expect("Executable must be in some directory")
+            .to_path_buf(),
+    ]
+}
+
+// writes an extra output of a build, like the trigger graph
+fn write_build_file(path: &Path, content: String, json_messages: bool) {
+    if let Err(e) = fs::write(path, content) {
+        report(
+            &Diagnostic::error(
+                "io_error",
+                format!("Could not write {}: {}", path.display(), e),
+            ),
+            json_messages,
+        );
+        std::process::exit(ERROR_EXIT_CODE);
+    }
+}
+
+fn read_level(gd_path: &Path, level_name: Option<String>) -> Result<String, String> {
+    let file_content =
+        fs::read(gd_path).map_err(|e| format!("Could not read {}: {}", gd_path.display(), e))?;
+    levelstring::get_level_string(file_content, level_name)
+        .map_err(|e| format!("Error reading level:\n{}", e))
 }
 
-fn eprint_with_color(text: &str, color: Color) {
-    let mut stdout = StandardStream::stderr(ColorChoice::Always);
-    stdout
-        .set_color(ColorSpec::new().set_fg(Some(color)))
-        .unwrap();
-    writeln!(&mut stdout, "{}", text).unwrap();
-    stdout.set_color(&ColorSpec::new()).unwrap();
+fn level_command(args: &mut std::slice::Iter<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let command = match args.next() {
+        Some(a) => a.clone(),
+        None => {
+            return Err(Box::from(
+                "Expected level subcommand: list, export, import or remove",
+            ))
+        }
+    };
+
+    let mut save_file = None;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--save-file" | "-s" => save_file = args.next().cloned(),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let save_file = save_file
+        .map(PathBuf::from)
+        .unwrap_or_else(default_save_file);
+    let mut positional = positional.into_iter();
+
+    let level_name = match command.as_ref() {
+        "list" => None,
+        "export" | "import" | "remove" => match positional.next() {
+            Some(name) => Some(name),
+            None => return Err(Box::from("Expected level name argument")),
+        },
+        a => return Err(Box::from(format!("Unknown level subcommand: {}", a))),
+    };
+
+    let file_content = fs::read(&save_file)
+        .map_err(|e| format!("Could not read {}: {}", save_file.display(), e))?;
+
+    match command.as_ref() {
+        "list" => {
+            for name in levelstring::get_level_names(file_content)? {
+                println!("{}", name);
+            }
+        }
+        "export" => {
+            let level_string = levelstring::get_level_string(file_content, level_name)?;
+            match positional.next() {
+                Some(out_path) => fs::write(out_path, level_string)?,
+                None => println!("{}", level_string),
+            }
+        }
+        "import" => {
+            let in_path = match positional.next() {
+                Some(p) => p,
+                None => return Err(Box::from("Expected level string file argument")),
+            };
+            let level_string = fs::read_to_string(in_path)?;
+            let backup = levelstring::encrypt_level_string(
+                level_string.trim().to_string(),
+                String::new(),
+                save_file,
+                level_name,
+            )?;
+            println!("Backup saved to {}", backup.display());
+        }
+        "remove" => {
+            let level_string = levelstring::get_level_string(file_content, level_name.clone())?;
+            let mut level = levelstring::LevelString::parse(&level_string)?;
+            // only the objects of one script, or the objects of all of them
+            match positional.next() {
+                Some(script) => {
+                    if !levelstring::unregister_script(&mut level, &script) {
+                        return Err(Box::from(format!(
+                            "No script called \"{}\" has added objects to this level",
+                            script
+                        )));
+                    }
+                }
+                None => {
+                    levelstring::remove_script_objects(&mut level, None);
+                }
+            }
+            let backup = levelstring::encrypt_level_string(
+                level.to_string(),
+                String::new(),
+                save_file,
+                level_name,
+            )?;
+            println!("Backup saved to {}", backup.display());
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -80,21 +193,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     };
 
                     let mut gd_enabled = true;
-                    let mut opti_enabled = true;
+                    let mut passes = Pass::for_level(2);
                     let mut compile_only = false;
                     let mut level_name = None;
                     let mut live_editor = false;
+                    let mut use_lock = true;
+                    let mut json_messages = false;
+                    let mut graph_file = None;
+                    let mut source_map_file = None;
+                    let mut reserved = Vec::new();
+                    let mut target = None;
+                    let mut signature = None;
+                    let mut recycle_groups = false;
 
                     let mut save_file = None;
-                    let mut included_paths = vec![
-                        std::env::current_dir().expect("Cannot access current directory"),
-                        std::env::current_exe()
-                            .expect("Cannot access directory of executable")
-                            .parent()
-                            .expect("Executable must be in some directory")
-                            .to_path_buf(),
-                    ];
-                    //change to current_exe before release (from current_dir)
+                    let mut included_paths = default_included_paths();
 
                     while let Some(arg) = args_iter.next() {
                         match arg.as_ref() {
@@ -103,10 +216,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 gd_enabled = false;
                                 compile_only = true;
                             }
-                            "--no-optimize" | "-o" => opti_enabled = false,
+                            "--no-optimize" | "-o" | "-O0" => passes = Vec::new(),
+                            "-O1" | "-O2" | "-O3" => passes = Pass::for_level(arg[2..].parse()?),
+                            a if a.starts_with("--passes=") => {
+                                passes = a["--passes=".len()..]
+                                    .split(',')
+                                    .filter(|p| !p.is_empty())
+                                    .map(Pass::from_name)
+                                    .collect::<Result<_, _>>()?;
+                            }
                             "--level-name" | "-n" => level_name = args_iter.next().cloned(),
                             "--live-editor" | "-e" => live_editor = true,
+                            "--no-lock" => use_lock = false,
+                            "--recycle-groups" => recycle_groups = true,
                             "--save-file" | "-s" => save_file = args_iter.next().cloned(),
+                            "--message-format" => {
+                                json_messages = match args_iter.next().map(|a| a.as_str()) {
+                                    Some("json") => true,
+                                    Some("human") => false,
+                                    _ => {
+                                        return Err(Box::from(
+                                            "Expected message format: human or json",
+                                        ))
+                                    }
+                                }
+                            }
                             "--included-path" | "-i" => included_paths.push({
                                 let path = PathBuf::from(
                                     args_iter.next().cloned().expect("No path provided"),
@@ -117,23 +251,162 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     return Err(Box::from("Invalid path".to_string()));
                                 }
                             }),
+                            "--emit-graph" => {
+                                let path = match args_iter.next() {
+                                    Some(p) => PathBuf::from(p),
+                                    None => return Err(Box::from("Expected graph file argument")),
+                                };
+                                match path.extension().and_then(|e| e.to_str()) {
+                                    Some("dot") | Some("gv") | Some("json") => (),
+                                    _ => {
+                                        return Err(Box::from(
+                                            "Graph file should end with .dot, .gv or .json",
+                                        ))
+                                    }
+                                }
+                                graph_file = Some(path);
+                            }
+                            "--emit-sourcemap" => match args_iter.next() {
+                                Some(p) => source_map_file = Some(PathBuf::from(p)),
+                                None => return Err(Box::from("Expected source map file argument")),
+                            },
+                            "--signature" => match args_iter.next() {
+                                Some(name) => signature = Some(name.clone()),
+                                None => return Err(Box::from("Expected signature name argument")),
+                            },
+                            "--target" => match args_iter.next() {
+                                Some(t) => target = Some(TargetProfile::from_name(t)?),
+                                None => return Err(Box::from("Expected target version argument")),
+                            },
+                            "--reserve-groups" | "--reserve-colors" | "--reserve-blocks"
+                            | "--reserve-items" => {
+                                let class = idrange::ID_CLASSES
+                                    .iter()
+                                    .position(|c| arg.strip_prefix("--reserve-") == Some(*c))
+                                    .unwrap();
+                                let range = match args_iter.next() {
+                                    Some(r) => idrange::parse_range(r)?,
+                                    None => return Err(Box::from("Expected ID range argument")),
+                                };
+                                reserved.push((class, range));
+                            }
                             _ => (),
                         };
                     }
 
-                    print_with_color("Parsing ...", Color::Green);
-                    let unparsed = fs::read_to_string(script_path.clone())?;
-
-                    let (statements, notes) = match parse_spwn(unparsed, script_path.clone()) {
-                        Err(err) => {
-                            eprint_with_color(&format!("{}\n", err), Color::White);
-                            std::process::exit(ERROR_EXIT_CODE);
+                    // json output should be the only thing on stdout
+                    let print_with_color = |text: &str, color: Color| {
+                        if !json_messages {
+                            print_with_color(text, color)
                         }
-                        Ok(p) => p,
                     };
 
-                    let tags = notes.tag.tags.iter();
-                    for tag in tags {
+                    print_with_color("Building script ...", Color::Cyan);
+                    print_with_color("———————————————————————————\n", Color::White);
+                    let start_time = std::time::Instant::now();
+
+                    let lock_path = script_path
+                        .parent()
+                        .unwrap_or_else(|| Path::new(""))
+                        .join(lockfile::LOCKFILE_NAME);
+
+                    // keep the IDs from earlier builds
+                    let lock = match (use_lock, fs::read_to_string(&lock_path)) {
+                        (false, _) => None,
+                        (true, Err(_)) => Some(lockfile::IdLock::default()),
+                        (true, Ok(s)) => match lockfile::IdLock::parse(&s) {
+                            Ok(lock) => Some(lock),
+                            Err(e) => {
+                                report(
+                                    &Diagnostic::error(
+                                        "lockfile_error",
+                                        format!("{}: {}", lock_path.display(), e),
+                                    ),
+                                    json_messages,
+                                );
+                                std::process::exit(ERROR_EXIT_CODE);
+                            }
+                        },
+                    };
+
+                    let gd_path = if gd_enabled {
+                        Some(
+                            save_file
+                                .map(PathBuf::from)
+                                .unwrap_or_else(default_save_file),
+                        )
+                    } else {
+                        None
+                    };
+
+                    // the level is read before building, so the optimizer keeps the groups
+                    // that its objects use. Errors are reported once the tags of the script
+                    // are known, since they can turn off adding to the level
+                    let level_string = gd_path.as_ref().map(|gd_path| {
+                        print_with_color("Reading savefile...", Color::Cyan);
+                        read_level(gd_path, level_name.clone())
+                    });
+
+                    let mut compiler = Compiler::new(script_path);
+                    for (class, range) in reserved {
+                        compiler = compiler.reserve(class, range);
+                    }
+                    if let Some(target) = target {
+                        compiler = compiler.target(target);
+                    }
+                    if let Some(name) = signature {
+                        compiler = compiler.signature(name);
+                    }
+                    if let Some(Ok(level_string)) = &level_string {
+                        compiler = compiler.level_string(level_string.clone());
+                    }
+                    if let Some(lock) = lock {
+                        compiler = compiler.id_lock(lock);
+                    }
+                    let compiled = compiler
+                        .include_paths(included_paths)
+                        .passes(passes)
+                        .trigger_graph(graph_file.is_some())
+                        .source_map(source_map_file.is_some())
+                        .recycle_groups(recycle_groups)
+                        .compile();
+
+                    if !compiled.is_ok() {
+                        for diagnostic in &compiled.diagnostics {
+                            report(diagnostic, json_messages);
+                        }
+                        std::process::exit(ERROR_EXIT_CODE);
+                    }
+
+                    print_with_color("———————————————————————————\n", Color::White);
+                    print_with_color(
+                        &format!(
+                            "Built in {} milliseconds!",
+                            start_time.elapsed().as_millis()
+                        ),
+                        Color::Green,
+                    );
+
+                    if !compiled.pass_stats.is_empty() {
+                        print_with_color("\nOptimizer:", Color::Magenta);
+                        for stats in &compiled.pass_stats {
+                            print_with_color(&stats.to_string(), Color::White);
+                        }
+                    }
+
+                    if let (Some(path), Some(graph)) = (&graph_file, &compiled.trigger_graph) {
+                        let content = match path.extension().and_then(|e| e.to_str()) {
+                            Some("json") => graph.to_json().to_string(),
+                            _ => graph.to_dot(),
+                        };
+                        write_build_file(path, content, json_messages);
+                        print_with_color(
+                            &format!("Trigger graph written to {}", path.display()),
+                            Color::White,
+                        );
+                    }
+
+                    for tag in compiled.tags.tags.iter() {
                         match tag.0.as_str() {
                             "console_output" => gd_enabled = false,
                             "no_level" => {
@@ -144,81 +417,61 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
 
-                    let gd_path = if gd_enabled {
-                        Some(if save_file != None {
-                            PathBuf::from(save_file.expect("what"))
-                        } else if cfg!(target_os = "windows") {
-                            PathBuf::from(std::env::var("localappdata").expect("No local app data"))
-                                .join("GeometryDash/CCLocalLevels.dat")
-                        } else if cfg!(target_os = "macos") {
-                            PathBuf::from(std::env::var("HOME").expect("No home directory"))
-                                .join("Library/Application Support/GeometryDash/CCLocalLevels.dat")
-                        } else if cfg!(target_os = "linux") {
-                            PathBuf::from(std::env::var("HOME").expect("No home directory"))
-                                .join(".steam/steam/steamapps/compatdata/322170/pfx/drive_c/users/steamuser/Local Settings/Application Data/GeometryDash/CCLocalLevels.dat")
-                        } else {
-                            panic!("Unsupported operating system");
-                        })
-                    } else {
-                        None
-                    };
+                    let gd_path = if gd_enabled { gd_path } else { None };
 
-                    let mut compiled = match compiler::compile_spwn(
-                        statements,
-                        script_path,
-                        included_paths,
-                        notes,
-                    ) {
-                        Err(err) => {
-                            eprint_with_color(&format!("{}\n", err), Color::White);
-                            std::process::exit(ERROR_EXIT_CODE);
-                        }
-                        Ok(p) => p,
-                    };
+                    if let (true, Some(path), Some(source_map)) =
+                        (compile_only, &source_map_file, &compiled.source_map)
+                    {
+                        write_build_file(path, source_map.to_json().to_string(), json_messages);
+                        print_with_color(
+                            &format!("Source map written to {}", path.display()),
+                            Color::White,
+                        );
+                    }
 
                     if !compile_only {
-                        let level_string = if let Some(gd_path) = &gd_path {
-                            print_with_color("Reading savefile...", Color::Cyan);
-                            let mut file = fs::File::open(gd_path)?;
-                            let mut file_content = Vec::new();
-                            use std::io::Read;
-                            file.read_to_end(&mut file_content)
-                                .expect("Problem reading savefile");
-                            let mut level_string = match levelstring::get_level_string(
-                                file_content,
-                                level_name.clone(),
+                        print_with_color(
+                            &format!("{} objects added", compiled.objects.len()),
+                            Color::White,
+                        );
+
+                        // the IDs are only final once the objects are added to the level
+                        let (new_ls, used_ids, source_map) = match (&gd_path, level_string) {
+                            (Some(_), Some(Err(e))) => {
+                                report(&Diagnostic::error("level_error", e), json_messages);
+                                std::process::exit(ERROR_EXIT_CODE);
+                            }
+                            (Some(_), _) | (None, None) => (
+                                compiled.level_string,
+                                compiled.used_ids,
+                                compiled.source_map,
+                            ),
+                            // the tags turned off the level that the objects were added to
+                            (None, Some(_)) => match spwn::add_to_level_with(
+                                compiled.objects,
+                                String::new(),
+                                &compiled.id_config,
+                                compiled.target,
+                                Some(&compiled.script_name),
+                                source_map_file.is_some(),
                             ) {
-                                Ok(s) => s,
+                                Ok(r) => (r.level_string, r.used_ids, r.source_map),
                                 Err(e) => {
-                                    eprint_with_color(
-                                        &format!("Error reading level:\n{}", e),
-                                        Color::Red,
-                                    );
-
+                                    report(&Diagnostic::error("level_error", e), json_messages);
                                     std::process::exit(ERROR_EXIT_CODE);
                                 }
-                            };
-                            if level_string.is_empty() {}
-                            levelstring::remove_spwn_objects(&mut level_string);
-                            level_string
-                        } else {
-                            String::new()
+                            },
                         };
-                        let has_stuff = compiled.func_ids.iter().any(|x| !x.obj_list.is_empty());
-                        if opti_enabled && has_stuff {
-                            print_with_color("Optimizing triggers...", Color::Cyan);
-                            compiled.func_ids = optimize(compiled.func_ids, compiled.closed_groups);
+                        let lock = compiled.id_lock;
+
+                        if let (Some(path), Some(source_map)) = (&source_map_file, source_map) {
+                            write_build_file(path, source_map.to_json().to_string(), json_messages);
+                            print_with_color(
+                                &format!("Source map written to {}", path.display()),
+                                Color::White,
+                            );
                         }
 
-                        let mut objects = levelstring::apply_fn_ids(&compiled.func_ids);
-
-                        objects.extend(compiled.objects);
-
-                        print_with_color(&format!("{} objects added", objects.len()), Color::White);
-
-                        let (new_ls, used_ids) =
-                            levelstring::append_objects(objects, &level_string)?;
-
                         print_with_color("\nLevel:", Color::Magenta);
                         for (i, len) in used_ids.iter().enumerate() {
                             if *len > 0 {
@@ -236,11 +489,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         if live_editor {
                             match editor_paste(&new_ls) {
                                 Err(e) => {
-                                    eprint_with_color(
-                                        &format!("Error pasting into editor:\n{}", e),
-                                        Color::Red,
+                                    report(
+                                        &Diagnostic::error(
+                                            "live_editor_error",
+                                            format!("Error pasting into editor:\n{}", e),
+                                        ),
+                                        json_messages,
                                     );
-
                                     std::process::exit(ERROR_EXIT_CODE);
                                 }
                                 Ok(_) => {
@@ -251,12 +506,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             match gd_path {
                                 Some(gd_path) => {
                                     print_with_color("\nWriting back to savefile...", Color::Cyan);
-                                    levelstring::encrypt_level_string(
+                                    match levelstring::encrypt_level_string(
                                         new_ls,
-                                        level_string,
+                                        String::new(),
                                         gd_path,
                                         level_name,
-                                    )?;
+                                    ) {
+                                        Ok(backup) => print_with_color(
+                                            &format!("Backup saved to {}", backup.display()),
+                                            Color::White,
+                                        ),
+                                        Err(e) => {
+                                            report(
+                                                &Diagnostic::error(
+                                                    "level_error",
+                                                    format!("Error writing level:\n{}", e),
+                                                ),
+                                                json_messages,
+                                            );
+                                            std::process::exit(ERROR_EXIT_CODE);
+                                        }
+                                    }
+
+                                    // the IDs are only worth keeping if they are in the level
+                                    if let Some(lock) = &lock {
+                                        write_build_file(
+                                            &lock_path,
+                                            serde_json::to_string_pretty(&lock.to_json()).unwrap(),
+                                            json_messages,
+                                        );
+                                    }
 
                                     print_with_color(
                                         "Written to save. You can now open Geometry Dash again!",
@@ -264,7 +543,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     );
                                 }
 
-                                None => println!("Output: {}", new_ls),
+                                None => {
+                                    if !json_messages {
+                                        println!("Output: {}", new_ls)
+                                    }
+                                }
                             };
                         }
                     };
@@ -275,6 +558,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     Ok(())
                 }
 
+                "level" => {
+                    if let Err(e) = level_command(&mut args_iter) {
+                        Diagnostic::error("level_error", e.to_string()).eprint();
+                        std::process::exit(ERROR_EXIT_CODE);
+                    }
+                    Ok(())
+                }
+
+                "lsp" => {
+                    spwn::lsp::run(default_included_paths())?;
+                    Ok(())
+                }
+
                 "doc" => {
                     //use std::fs::File;
 
@@ -288,7 +584,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     match documentation::document_lib(lib_path) {
                         Ok(_) => (),
                         Err(e) => {
-                            eprint_with_color(&format!("{}\n", e), Color::Red);
+                            Diagnostic::from(&e).eprint();
                             std::process::exit(ERROR_EXIT_CODE);
                         }
                     };
@@ -297,33 +593,194 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                     Ok(())
                 }
-                // "format" => {
-                //     use std::fs::File;
-                //     //use std::io::Write;
-                //     let script_path = match args_iter.next() {
-                //         Some(a) => PathBuf::from(a),
-                //         None => return Err(std::boxed::Box::from("Expected script file argument")),
-                //     };
+                "fmt" => {
+                    let mut check = false;
+                    let mut files = Vec::new();
+                    for arg in args_iter {
+                        match arg.as_ref() {
+                            "--check" => check = true,
+                            _ => files.push(PathBuf::from(arg)),
+                        }
+                    }
+                    if files.is_empty() {
+                        return Err(Box::from("Expected script file argument"));
+                    }
 
-                //     println!("Formatting is not good yet, i will finish it before the final version is released.");
+                    let mut failed = false;
+                    let mut unformatted = Vec::new();
 
-                //     let unparsed = fs::read_to_string(script_path.clone())?;
+                    for script_path in files {
+                        let unparsed = fs::read_to_string(&script_path)?;
+                        let (parsed, notes) =
+                            parser::parse_spwn_recovering(unparsed.clone(), script_path.clone());
 
-                //     let (parsed, _) = match parse_spwn(unparsed, script_path) {
-                //         Err(err) => {
-                //             eprintln!("{}\n", err);
-                //             std::process::exit(ERROR_EXIT_CODE);
-                //         }
-                //         Ok(p) => p,
-                //     };
+                        if !notes.errors.is_empty() {
+                            // formatting a partial ast would lose the broken statements
+                            for err in &notes.errors {
+                                Diagnostic::from(err).eprint();
+                            }
+                            failed = true;
+                            continue;
+                        }
 
-                //     let formatted = fmt::format(parsed);
+                        let formatted = fmt::format(&parsed, &notes);
+                        if formatted == unparsed {
+                            continue;
+                        }
+                        if fmt::comments(&formatted) != fmt::comments(&unparsed) {
+                            // like comments in an empty block, which have nowhere to go
+                            eprint_with_color(
+                                &format!(
+                                    "Formatting {} would remove comments, leaving it as it is",
+                                    script_path.display()
+                                ),
+                                Color::Red,
+                            );
+                            failed = true;
+                            continue;
+                        }
 
-                //     let mut output_file = File::create("test/formatted.spwn")?;
-                //     output_file.write_all(formatted.as_bytes())?;
+                        if check {
+                            unformatted.push(script_path);
+                        } else {
+                            fs::write(&script_path, formatted)?;
+                        }
+                    }
 
-                //     Ok(())
-                // }
+                    for script_path in &unformatted {
+                        println!("Would reformat {}", script_path.display());
+                    }
+
+                    if failed || !unformatted.is_empty() {
+                        std::process::exit(ERROR_EXIT_CODE);
+                    }
+
+                    Ok(())
+                }
+                "repl" => {
+                    let mut included_paths = default_included_paths();
+                    while let Some(arg) = args_iter.next() {
+                        if let "--included-path" | "-i" = arg.as_ref() {
+                            included_paths.push(PathBuf::from(
+                                args_iter.next().cloned().expect("No path provided"),
+                            ))
+                        }
+                    }
+                    // imports are relative to the current folder
+                    let path = std::env::current_dir()?.join("<repl>");
+                    let mut session = match repl::Repl::new(path, included_paths) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            e.eprint();
+                            std::process::exit(ERROR_EXIT_CODE);
+                        }
+                    };
+
+                    println!(
+                        "SPWN v{} (type :help for commands)",
+                        env!("CARGO_PKG_VERSION")
+                    );
+                    let stdin = std::io::stdin();
+                    let mut input = String::new();
+                    loop {
+                        print!("{}", if input.is_empty() { "> " } else { ". " });
+                        std::io::stdout().flush()?;
+
+                        let mut line = String::new();
+                        if stdin.read_line(&mut line)? == 0 {
+                            break;
+                        }
+                        input += &line;
+                        if repl::is_incomplete(&input) {
+                            continue;
+                        }
+                        if let ":quit" | ":q" = input.trim() {
+                            break;
+                        }
+
+                        match session.eval(&input) {
+                            Ok(out) => {
+                                for line in out {
+                                    println!("{}", line);
+                                }
+                            }
+                            Err(e) => e.eprint(),
+                        }
+                        input.clear();
+                    }
+                    Ok(())
+                }
+                "test" => {
+                    let mut path = PathBuf::from(".");
+                    let mut included_paths = default_included_paths();
+                    while let Some(arg) = args_iter.next() {
+                        match arg.as_ref() {
+                            "--included-path" | "-i" => included_paths.push(PathBuf::from(
+                                args_iter.next().cloned().expect("No path provided"),
+                            )),
+                            _ => path = PathBuf::from(arg),
+                        }
+                    }
+
+                    let mut passed = 0;
+                    let mut failed = Vec::new();
+                    let mut broken = false;
+
+                    for script_path in testing::collect_scripts(&path)? {
+                        let results = match testing::run_tests(&script_path, included_paths.clone())
+                        {
+                            Ok(r) => r,
+                            Err(diagnostics) => {
+                                for diagnostic in &diagnostics {
+                                    diagnostic.eprint();
+                                }
+                                broken = true;
+                                continue;
+                            }
+                        };
+                        if results.is_empty() {
+                            continue;
+                        }
+
+                        println!(
+                            "\nrunning {} tests in {}",
+                            results.len(),
+                            script_path.display()
+                        );
+                        for result in results {
+                            if result.passed() {
+                                println!("test {} ... ok", result.name);
+                                passed += 1;
+                            } else {
+                                println!("test {} ... FAILED", result.name);
+                                failed.push(result);
+                            }
+                        }
+                    }
+
+                    if !failed.is_empty() {
+                        println!("\nfailures:");
+                        for result in &failed {
+                            println!("\n---- {} ----", result.name);
+                            for diagnostic in &result.diagnostics {
+                                diagnostic.eprint();
+                            }
+                        }
+                    }
+
+                    let ok = failed.is_empty() && !broken;
+                    println!(
+                        "\ntest result: {}. {} passed; {} failed",
+                        if ok { "ok" } else { "FAILED" },
+                        passed,
+                        failed.len()
+                    );
+                    if !ok {
+                        std::process::exit(ERROR_EXIT_CODE);
+                    }
+
+                    Ok(())
+                }
                 a => {
                     eprint_with_color(&format!("Unknown subcommand: {}", a), Color::Red);
                     println!("{}", HELP);
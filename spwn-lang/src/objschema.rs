@@ -0,0 +1,364 @@
+//! Which properties objects can have, and what kind of value each of them takes
+//!
+//! Objects made with `obj {}` and `trigger {}`, and properties set with `$.edit_obj`, are
+//! checked against this when they are made. Properties of triggers that the trigger doesn't
+//! have are errors, and so are values of the wrong kind. A property that is set with a plain
+//! number as the key (like `36: 1` instead of `ACTIVE_TRIGGER: 1`) isn't checked, so
+//! properties that aren't known here can still be used.
+
+use crate::levelstring::ObjParam;
+use crate::target::TargetProfile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    Number,
+    Bool,
+    Text,
+    Group,
+    Color,
+    Block,
+    Item,
+    GroupList,
+    Epsilon,
+}
+
+use ParamKind::*;
+
+impl ParamKind {
+    pub fn of(param: &ObjParam) -> Self {
+        match param {
+            ObjParam::Number(_) => Number,
+            ObjParam::Bool(_) => Bool,
+            ObjParam::Text(_) => Text,
+            ObjParam::Group(_) => Group,
+            ObjParam::Color(_) => Color,
+            ObjParam::Block(_) => Block,
+            ObjParam::Item(_) => Item,
+            ObjParam::GroupList(_) => GroupList,
+            ObjParam::Epsilon => Epsilon,
+        }
+    }
+
+    /// The SPWN type of values of this kind
+    pub fn type_name(self) -> &'static str {
+        match self {
+            Number => "@number",
+            Bool => "@bool",
+            Text => "@string",
+            Group => "@group",
+            Color => "@color",
+            Block => "@block",
+            Item => "@item",
+            GroupList => "[@group]",
+            Epsilon => "@epsilon",
+        }
+    }
+}
+
+type Prop = (u16, &'static str, &'static [ParamKind]);
+// a property of a trigger, with the kinds it takes on it if they are fewer than usual
+type TriggerProp = (u16, Option<&'static [ParamKind]>);
+
+const NUM: &[ParamKind] = &[Number];
+const BOOL: &[ParamKind] = &[Bool];
+const TEXT: &[ParamKind] = &[Text];
+const GROUP: &[ParamKind] = &[Group];
+const COLOR: &[ParamKind] = &[Color];
+const BLOCK: &[ParamKind] = &[Block];
+const ITEM: &[ParamKind] = &[Item];
+
+// every property in `obj_props`, with all the kinds it takes on any object
+const PROPS: &[Prop] = &[
+    (1, "OBJ_ID", NUM),
+    (2, "X", NUM),
+    (3, "Y", NUM),
+    (4, "HORIZONTAL_FLIP", BOOL),
+    (5, "VERTICAL_FLIP", BOOL),
+    (6, "ROTATION", NUM),
+    (7, "TRIGGER_RED", NUM),
+    (8, "TRIGGER_GREEN", NUM),
+    (9, "TRIGGER_BLUE", NUM),
+    (10, "DURATION", NUM),
+    (11, "TOUCH_TRIGGERED", BOOL),
+    (13, "PORTAL_CHECKED", BOOL),
+    (15, "PLAYER_COLOR_1", BOOL),
+    (16, "PLAYER_COLOR_2", BOOL),
+    (17, "BLENDING", BOOL),
+    (20, "EDITOR_LAYER_1", NUM),
+    (21, "COLOR", COLOR),
+    (22, "COLOR_2", COLOR),
+    (23, "TARGET_COLOR", COLOR),
+    (24, "Z_LAYER", NUM),
+    (25, "Z_ORDER", NUM),
+    (28, "MOVE_X", NUM),
+    (29, "MOVE_Y", NUM),
+    (30, "EASING", NUM),
+    (31, "TEXT", TEXT),
+    (32, "SCALING", NUM),
+    (34, "GROUP_PARENT", BOOL),
+    (35, "OPACITY", NUM),
+    (36, "ACTIVE_TRIGGER", BOOL),
+    (41, "HVS_ENABLED", BOOL),
+    (42, "COLOR_2_HVS_ENABLED", BOOL),
+    (43, "HVS", TEXT),
+    (44, "COLOR_2_HVS", TEXT),
+    (45, "FADE_IN", NUM),
+    (46, "HOLD", NUM),
+    (47, "FADE_OUT", NUM),
+    (48, "PULSE_HSV", BOOL),
+    (49, "COPIED_COLOR_HVS", TEXT),
+    (50, "COPIED_COLOR_ID", COLOR),
+    (51, "TARGET", &[Group, Color]),
+    (52, "TARGET_TYPE", NUM),
+    (54, "YELLOW_TELEPORTATION_PORTAL_DISTANCE", NUM),
+    (56, "ACTIVATE_GROUP", BOOL),
+    (57, "GROUPS", &[Group, GroupList]),
+    (58, "LOCK_TO_PLAYER_X", BOOL),
+    (59, "LOCK_TO_PLAYER_Y", BOOL),
+    (60, "COPY_OPACTITY", BOOL),
+    (61, "EDITOR_LAYER_2", NUM),
+    (62, "SPAWN_TRIGGERED", BOOL),
+    (63, "SPAWN_DURATION", &[Number, Epsilon]),
+    (64, "DONT_FADE", BOOL),
+    (65, "MAIN_ONLY", BOOL),
+    (66, "DETAIL_ONLY", BOOL),
+    (67, "DONT_ENTER", BOOL),
+    (68, "ROTATE_DEGREES", NUM),
+    (69, "TIMES_360", NUM),
+    (70, "LOCK_OBJECT_ROTATION", BOOL),
+    (71, "TARGET_POS", GROUP),
+    (72, "X_MOD", NUM),
+    (73, "Y_MOD", NUM),
+    (75, "STRENGTH", NUM),
+    (76, "ANIMATION_ID", NUM),
+    (77, "COUNT", NUM),
+    (78, "SUBTRACT_COUNT", NUM),
+    (79, "PICKUP_MODE", NUM),
+    (80, "ITEM", &[Item, Block]),
+    (81, "HOLD_MODE", BOOL),
+    (82, "TOGGLE_MODE", NUM),
+    (84, "INTERVAL", NUM),
+    (85, "EASING_RATE", NUM),
+    (86, "EXCLUSIVE", BOOL),
+    (87, "MULTI_TRIGGER", BOOL),
+    (88, "COMPARISON", NUM),
+    (89, "DUAL_MODE", BOOL),
+    (90, "SPEED", NUM),
+    (91, "DELAY", NUM),
+    (92, "Y_OFFSET", NUM),
+    (93, "ACTIVATE_ON_EXIT", BOOL),
+    (94, "DYNAMIC_BLOCK", BOOL),
+    (95, "BLOCK_B", BLOCK),
+    (96, "GLOW_DISABLED", BOOL),
+    (97, "ROTATION_SPEED", NUM),
+    (98, "DISABLE_ROTATION", BOOL),
+    (100, "USE_TARGET", BOOL),
+    (101, "TARGET_POS_AXES", NUM),
+    (102, "EDITOR_DISABLE", BOOL),
+    (103, "HIGH_DETAIL", BOOL),
+    (104, "COUNT_MULTI_ACTIVATE", BOOL),
+    (105, "MAX_SPEED", NUM),
+    (106, "RANDOMIZE_START", BOOL),
+    (107, "ANIMATION_SPEED", NUM),
+    (108, "LINKED_GROUP", NUM),
+];
+
+// properties of every object
+const OBJECT_KEYS: &[u16] = &[
+    1, 2, 3, 4, 5, 6, 20, 21, 22, 24, 25, 32, 34, 41, 42, 43, 44, 57, 61, 64, 67, 96, 103, 108,
+];
+
+// properties of every trigger
+const TRIGGER_KEYS: &[u16] = &[11, 36, 62, 87];
+
+// properties of each trigger
+const TRIGGER_PROPS: &[(u16, &[TriggerProp])] = &[
+    (32, &[]),
+    (33, &[]),
+    (
+        899,
+        &[
+            (7, None),
+            (8, None),
+            (9, None),
+            (10, None),
+            (15, None),
+            (16, None),
+            (17, None),
+            (23, None),
+            (35, None),
+            (49, None),
+            (50, None),
+            (60, None),
+        ],
+    ),
+    (
+        901,
+        &[
+            (10, None),
+            (28, None),
+            (29, None),
+            (30, None),
+            (51, Some(GROUP)),
+            (58, None),
+            (59, None),
+            (71, None),
+            (85, None),
+            (100, None),
+            (101, None),
+        ],
+    ),
+    (
+        1006,
+        &[
+            (7, None),
+            (8, None),
+            (9, None),
+            (45, None),
+            (46, None),
+            (47, None),
+            (48, None),
+            (49, None),
+            (50, None),
+            (51, None),
+            (52, None),
+            (65, None),
+            (66, None),
+            (86, None),
+        ],
+    ),
+    (1007, &[(10, None), (35, None), (51, Some(GROUP))]),
+    (1049, &[(51, Some(GROUP)), (56, None)]),
+    (1268, &[(51, Some(GROUP)), (63, None)]),
+    (
+        1346,
+        &[
+            (10, None),
+            (30, None),
+            (51, Some(GROUP)),
+            (68, None),
+            (69, None),
+            (70, None),
+            (71, None),
+            (85, None),
+        ],
+    ),
+    (
+        1347,
+        &[
+            (10, None),
+            (51, Some(GROUP)),
+            (71, None),
+            (72, None),
+            (73, None),
+        ],
+    ),
+    (1520, &[(10, None), (75, None), (84, None)]),
+    (1585, &[(51, Some(GROUP)), (76, None)]),
+    (
+        1595,
+        &[(51, Some(GROUP)), (81, None), (82, None), (89, None)],
+    ),
+    (
+        1611,
+        &[
+            (51, Some(GROUP)),
+            (56, None),
+            (77, None),
+            (80, Some(ITEM)),
+            (104, None),
+        ],
+    ),
+    (1612, &[]),
+    (1613, &[]),
+    (1616, &[(51, Some(GROUP))]),
+    (
+        1811,
+        &[
+            (51, Some(GROUP)),
+            (56, None),
+            (77, None),
+            (80, Some(ITEM)),
+            (88, None),
+        ],
+    ),
+    (1812, &[(51, Some(GROUP)), (56, None)]),
+    (
+        1814,
+        &[
+            (10, None),
+            (51, Some(GROUP)),
+            (90, None),
+            (91, None),
+            (92, None),
+            (105, None),
+        ],
+    ),
+    (
+        1815,
+        &[
+            (51, Some(GROUP)),
+            (56, None),
+            (80, Some(BLOCK)),
+            (93, None),
+            (95, None),
+        ],
+    ),
+    (1817, &[(77, None), (79, None), (80, Some(ITEM))]),
+    (1818, &[]),
+    (1819, &[]),
+];
+
+/// The object ID in the properties of an object
+pub fn obj_id(props: &[(u16, ObjParam)]) -> Option<u16> {
+    props.iter().find_map(|(key, value)| match (key, value) {
+        (1, ObjParam::Number(n)) => Some(*n as u16),
+        _ => None,
+    })
+}
+
+/// Checks a property of an object with the object ID `obj_id` (if it has one)
+pub fn check_prop(obj_id: Option<u16>, key: u16, value: &ObjParam) -> Result<(), String> {
+    // properties that aren't known aren't checked
+    let (_, name, kinds) = match PROPS.iter().find(|p| p.0 == key) {
+        Some(p) => *p,
+        None => return Ok(()),
+    };
+
+    let trigger = obj_id.and_then(|id| TRIGGER_PROPS.iter().find(|t| t.0 == id));
+    let kinds = match trigger {
+        Some((id, props)) => match props.iter().find(|p| p.0 == key) {
+            Some((_, Some(narrower))) => *narrower,
+            Some((_, None)) => kinds,
+            None if OBJECT_KEYS.contains(&key) || TRIGGER_KEYS.contains(&key) => kinds,
+            None => {
+                return Err(format!(
+                    "The {} trigger (object ID {}) has no {} property. Use {} as the key \
+                     instead of {} to set it anyway",
+                    TargetProfile::trigger_name(*id).unwrap_or("unknown"),
+                    id,
+                    name,
+                    key,
+                    name
+                ))
+            }
+        },
+        None => kinds,
+    };
+
+    let kind = ParamKind::of(value);
+    if kinds.contains(&kind) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} expects {}, found {}",
+            name,
+            kinds
+                .iter()
+                .map(|k| k.type_name())
+                .collect::<Vec<_>>()
+                .join(" or "),
+            kind.type_name()
+        ))
+    }
+}
@@ -1,11 +1,14 @@
 use crate::ast::ObjectMode;
-use crate::builtin::{Block, Group, Item, ID};
+use crate::builtin::{Block, Color, Group, Item, ID};
 use crate::compiler_types::FunctionID;
-use crate::levelstring::{GDObj, ObjParam};
-use std::collections::{HashMap, HashSet};
+use crate::levelstring::{self, GDObj, LevelString, ObjParam};
+use crate::lockfile::{IdLock, IdNames};
+use crate::target::TargetProfile;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum TriggerRole {
+pub enum TriggerRole {
     // Spawn triggers have their own catagory
     // because they can be combined by adding their delays
     Spawn,
@@ -18,7 +21,7 @@ enum TriggerRole {
     Func,
 }
 
-fn get_role(obj_id: u16, hd: bool) -> TriggerRole {
+pub fn get_role(obj_id: u16, hd: bool, target: &TargetProfile) -> TriggerRole {
     match obj_id {
         1268 => {
             if hd {
@@ -27,14 +30,14 @@ fn get_role(obj_id: u16, hd: bool) -> TriggerRole {
                 TriggerRole::Spawn
             }
         }
-        1595 | 1611 | 1811 | 1815 | 1812 => TriggerRole::Func,
+        _ if target.func_triggers.contains(&obj_id) => TriggerRole::Func,
         _ => TriggerRole::Output,
     }
 }
 
 type ObjPtr = (usize, usize);
 //                                     triggers      connections in
-type TriggerNetwork = HashMap<Group, TriggerGang>;
+type TriggerNetwork = BTreeMap<Group, TriggerGang>;
 
 #[derive(Debug, Clone)]
 // what do you mean? its a trigger gang!
@@ -93,6 +96,8 @@ fn clone_trigger(
         unique_id: obj.unique_id, //this might cause a problem in the future
         sync_group: obj.sync_group,
         sync_part: obj.sync_part,
+        source: obj.source.clone(),
+        raw: None,
     };
     let fn_id = obj.func_id;
     (*objects.list)[fn_id].obj_list.push((obj.clone(), order));
@@ -124,7 +129,292 @@ const NO_GROUP: Group = Group {
     id: ID::Specific(0),
 };
 
-pub fn optimize(mut obj_in: Vec<FunctionID>, mut closed_group: u16) -> Vec<FunctionID> {
+/// Groups that objects outside of the script use, found in the level the script is built
+/// into. Hand placed triggers can spawn or toggle groups of the script with the ID
+/// `spwn.lock` keeps for a named `?g`, so the optimizer can't remove or merge these groups.
+///
+/// This only matters for arbitrary groups that are mapped to a specific ID through `lock`.
+/// Groups the script gives a specific ID are always kept, so without a lock the groups found
+/// here change nothing: an arbitrary group only gets its ID after optimizing, and objects in
+/// the level can't know it.
+pub fn external_groups(
+    level_string: &str,
+    script: Option<&str>,
+    names: &IdNames,
+    lock: Option<&IdLock>,
+) -> Result<BTreeSet<Group>, String> {
+    let mut level = LevelString::parse(level_string)?;
+    levelstring::remove_script_objects(&mut level, script);
+    let [used_groups, ..] = levelstring::get_used_ids(&level);
+
+    let mut out: BTreeSet<Group> = used_groups
+        .iter()
+        .map(|id| Group {
+            id: ID::Specific(*id),
+        })
+        .collect();
+    if let Some(lock) = lock {
+        out.extend(
+            lock.locked_to(0, names, &used_groups)
+                .into_iter()
+                .map(|a| Group {
+                    id: ID::Arbitrary(a),
+                }),
+        );
+    }
+    Ok(out)
+}
+
+/// Groups of objects that aren't triggers, like blocks the script adds. Moving or toggling
+/// one of these groups also moves or toggles the triggers in it, so merge-gangs can't merge
+/// them (other passes don't care about them).
+pub fn object_groups(objects: &[GDObj]) -> BTreeSet<Group> {
+    let mut out = BTreeSet::new();
+    for obj in objects {
+        for param in obj.params.values() {
+            match param {
+                ObjParam::Group(g) => {
+                    out.insert(*g);
+                }
+                ObjParam::GroupList(list) => out.extend(list),
+                _ => (),
+            }
+        }
+    }
+    out
+}
+
+/// A step of the optimizer. Every pass gets the triggers that the pass before it left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Moves triggers that read an ID after a trigger in the same group wrote to it
+    /// into a new group that is spawned a little later. Not an optimization, more like
+    /// a consistency fix, but most scripts don't work without it
+    ReadWriteOrder,
+    /// Removes triggers that nothing can activate, and triggers that only
+    /// activate groups without any triggers
+    DeadTriggers,
+    /// Merges chains of spawn triggers into one spawn trigger with the combined delay,
+    /// and moves the triggers of groups that are only spawned once into the spawning group
+    SpawnChains,
+    /// Keeps one copy of groups with the same triggers (apart from the group they are in),
+    /// like the ones made by calling the same macro many times, and makes the triggers
+    /// that spawn the other copies spawn that one instead
+    MergeGangs,
+}
+
+pub const PASSES: [Pass; 4] = [
+    Pass::ReadWriteOrder,
+    Pass::DeadTriggers,
+    Pass::SpawnChains,
+    Pass::MergeGangs,
+];
+
+impl Pass {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::ReadWriteOrder => "read-write-order",
+            Pass::DeadTriggers => "dead-triggers",
+            Pass::SpawnChains => "spawn-chains",
+            Pass::MergeGangs => "merge-gangs",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Pass, String> {
+        PASSES
+            .iter()
+            .copied()
+            .find(|p| p.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown optimizer pass \"{}\", expected one of {}",
+                    name,
+                    PASSES
+                        .iter()
+                        .map(|p| p.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+
+    /// Passes run with `-O0` to `-O3` (`-O2` is the default). `-O3` runs spawn-chains
+    /// a second time, which can merge the chains that the earlier passes made.
+    pub fn for_level(level: u8) -> Vec<Pass> {
+        match level {
+            0 => Vec::new(),
+            1 => vec![Pass::ReadWriteOrder, Pass::DeadTriggers],
+            2 => vec![Pass::ReadWriteOrder, Pass::SpawnChains, Pass::MergeGangs],
+            _ => vec![
+                Pass::ReadWriteOrder,
+                Pass::SpawnChains,
+                Pass::MergeGangs,
+                Pass::SpawnChains,
+            ],
+        }
+    }
+}
+
+/// Number of triggers and trigger groups before and after a pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassStats {
+    pub pass: Pass,
+    pub triggers: (usize, usize),
+    pub groups: (usize, usize),
+}
+
+impl PassStats {
+    /// Triggers the pass removed (negative if it added some)
+    pub fn triggers_removed(&self) -> isize {
+        self.triggers.0 as isize - self.triggers.1 as isize
+    }
+
+    /// Groups with triggers in them the pass removed (negative if it added some)
+    pub fn groups_removed(&self) -> isize {
+        self.groups.0 as isize - self.groups.1 as isize
+    }
+}
+
+impl std::fmt::Display for PassStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} triggers ({:+}), {} groups ({:+})",
+            self.pass.name(),
+            self.triggers.1,
+            -self.triggers_removed(),
+            self.groups.1,
+            -self.groups_removed(),
+        )
+    }
+}
+
+// number of triggers and of groups with triggers in them, not counting triggers
+// without a group
+fn count_triggers(func_ids: &[FunctionID]) -> (usize, usize) {
+    let mut triggers = 0;
+    let mut groups = HashSet::new();
+    for fnid in func_ids {
+        for (obj, _) in &fnid.obj_list {
+            triggers += 1;
+            match obj.params.get(&57) {
+                Some(ObjParam::Group(g)) => {
+                    groups.insert(*g);
+                }
+                Some(ObjParam::GroupList(list)) => groups.extend(list),
+                _ => (),
+            }
+        }
+    }
+    groups.remove(&NO_GROUP);
+    (triggers, groups.len())
+}
+
+/// Optimizes the triggers of a script with the default passes (see [`run_passes`])
+pub fn optimize(
+    obj_in: Vec<FunctionID>,
+    closed_group: u16,
+    target: &TargetProfile,
+    external: &BTreeSet<Group>,
+    object_groups: &BTreeSet<Group>,
+) -> Vec<FunctionID> {
+    run_passes(
+        obj_in,
+        closed_group,
+        target,
+        external,
+        object_groups,
+        &Pass::for_level(2),
+    )
+    .0
+}
+
+/// Runs the passes in order. The triggers in the groups in `external` (see
+/// [`external_groups`]) are kept, and those groups aren't merged into other groups.
+/// The groups in `object_groups` (see [`object_groups`]) aren't merged either.
+pub fn run_passes(
+    mut obj_in: Vec<FunctionID>,
+    mut closed_group: u16,
+    target: &TargetProfile,
+    external: &BTreeSet<Group>,
+    object_groups: &BTreeSet<Group>,
+    passes: &[Pass],
+) -> (Vec<FunctionID>, Vec<PassStats>) {
+    let mut stats = Vec::new();
+    for pass in passes {
+        let before = count_triggers(&obj_in);
+        obj_in = run_pass(
+            *pass,
+            obj_in,
+            &mut closed_group,
+            target,
+            external,
+            object_groups,
+        );
+        let after = count_triggers(&obj_in);
+        stats.push(PassStats {
+            pass: *pass,
+            triggers: (before.0, after.0),
+            groups: (before.1, after.1),
+        });
+    }
+    (obj_in, stats)
+}
+
+fn run_pass(
+    pass: Pass,
+    mut obj_in: Vec<FunctionID>,
+    closed_group: &mut u16,
+    target: &TargetProfile,
+    external: &BTreeSet<Group>,
+    object_groups: &BTreeSet<Group>,
+) -> Vec<FunctionID> {
+    let mut network = build_network(&obj_in, target, external);
+    let mut objects = Triggerlist { list: &mut obj_in };
+
+    match pass {
+        Pass::ReadWriteOrder => {
+            network = fix_read_write_order(&mut objects, &network, closed_group);
+            for gang in network.values_mut() {
+                for trigger in &mut gang.triggers {
+                    trigger.deleted = false;
+                }
+            }
+        }
+        Pass::DeadTriggers => remove_dead_triggers(&mut network, &objects, external),
+        Pass::MergeGangs => {
+            let fixed = external.union(object_groups).copied().collect();
+            merge_gangs(&mut network, &mut objects, &fixed)
+        }
+        Pass::SpawnChains => {
+            for (group, gang) in network.clone() {
+                if matches!(group.id, ID::Specific(_)) || external.contains(&group) {
+                    for (i, trigger) in gang.triggers.iter().enumerate() {
+                        if trigger.role != TriggerRole::Output {
+                            optimize_from(
+                                &mut network,
+                                &mut objects,
+                                (group, i),
+                                closed_group,
+                                external,
+                            );
+                        } else {
+                            network.get_mut(&group).unwrap().triggers[i].deleted = false;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    rebuild(&network, &obj_in)
+}
+
+fn build_network(
+    obj_in: &[FunctionID],
+    target: &TargetProfile,
+    external: &BTreeSet<Group>,
+) -> TriggerNetwork {
     let mut network = TriggerNetwork::new();
 
     // sort all triggers by their group
@@ -137,7 +427,7 @@ pub fn optimize(mut obj_in: Vec<FunctionID>, mut closed_group: u16) -> Vec<Funct
                 }
                 let trigger = Trigger {
                     obj: (f, o),
-                    role: get_role(*id as u16, hd),
+                    role: get_role(*id as u16, hd, target),
                     order: *order,
                     deleted: true,
                     optimized: false,
@@ -162,80 +452,222 @@ pub fn optimize(mut obj_in: Vec<FunctionID>, mut closed_group: u16) -> Vec<Funct
     }
 
     // count connection in for all triggers
-    for fnid in &obj_in {
+    for fnid in obj_in {
         for (obj, _) in &fnid.obj_list {
-            //if let Some(ObjParam::Number(id)) = obj.params.get(&1) {
-            //if get_role(*id as u16) != TriggerRole::Output {
             if let Some(ObjParam::Group(id)) = obj.params.get(&51) {
                 if let Some(gang) = network.get_mut(id) {
                     (*gang).connections_in += 1;
                 }
             }
-            //}
-            //}
         }
     }
 
-    //optimize
-    //optimize_network(&mut network);
+    // triggers outside of the script might spawn these
+    for group in external {
+        if let Some(gang) = network.get_mut(group) {
+            gang.connections_in += 1;
+        }
+    }
 
-    let mut objects = Triggerlist { list: &mut obj_in };
+    network
+}
 
-    // fix read write order
-    // not an optimization, more like a consistancy fix
-    // also, like nothing works without this, so i should probably move
-    // this somewhere else if i want to add an option to not have optimization
-    network = fix_read_write_order(&mut objects, &network, &mut closed_group);
+// keeps the triggers in groups that can be activated, starting from the groups with
+// specific IDs and the external groups
+fn remove_dead_triggers(
+    network: &mut TriggerNetwork,
+    objects: &Triggerlist,
+    external: &BTreeSet<Group>,
+) {
+    let mut reached: BTreeSet<Group> = network
+        .keys()
+        .filter(|g| matches!(g.id, ID::Specific(_)) || external.contains(g))
+        .copied()
+        .collect();
+    let mut stack: Vec<Group> = reached.iter().copied().collect();
+
+    while let Some(group) = stack.pop() {
+        for trigger in &network[&group].triggers {
+            if trigger.role == TriggerRole::Output {
+                continue;
+            }
+            if let Some(ObjParam::Group(target)) = objects[trigger.obj].0.params.get(&51) {
+                if network.contains_key(target) && reached.insert(*target) {
+                    stack.push(*target);
+                }
+            }
+        }
+    }
 
-    //println!("{:?}", network);
+    for (group, gang) in network.iter_mut() {
+        for trigger in &mut gang.triggers {
+            let dangling = trigger.role != TriggerRole::Output
+                && match objects[trigger.obj].0.params.get(&51) {
+                    Some(ObjParam::Group(target)) => {
+                        matches!(target.id, ID::Arbitrary(_))
+                            && !reached.contains(target)
+                            && !external.contains(target)
+                    }
+                    _ => true,
+                };
+            trigger.deleted = !reached.contains(group) || dangling;
+        }
+    }
+}
 
-    //return rebuild(&network, &obj_in);
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum IDData {
+    Group(Group),
+    Block(Block),
+    Item(Item),
+}
 
-    // let len = if let Some(gang) = network.get(&NO_GROUP) {
-    //     gang.triggers.len()
-    // } else {
-    //     0
-    // };
-    // for i in 0..len {
-    //     let trigger = network[&NO_GROUP].triggers[i];
+// a parameter of a trigger in the shape of its gang. numbers are compared by their bits so
+// shapes can be hashed, and groups that can be merged are replaced by their class
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ShapeParam {
+    Class(usize),
+    Group(Group),
+    Color(Color),
+    Block(Block),
+    Item(Item),
+    Number(u64),
+    Bool(bool),
+    Text(String),
+    GroupList(Vec<Group>),
+    Epsilon,
+}
 
-    //     // if trigger.optimized {
-    //     //     continue;
-    //     // }
+// the parameters of a trigger (without its groups), and its sync group and part
+type TriggerShape = (Vec<(u16, ShapeParam)>, usize, usize);
+
+// the triggers of a gang in order, with the groups of the triggers left out
+fn gang_shape(
+    gang: &TriggerGang,
+    objects: &Triggerlist,
+    classes: &HashMap<Group, usize>,
+) -> Vec<TriggerShape> {
+    let mut triggers = gang.triggers.clone();
+    triggers.sort_by_key(|t| t.order);
+    triggers
+        .iter()
+        .map(|trigger| {
+            let obj = &objects[trigger.obj].0;
+            let params = obj
+                .params
+                .iter()
+                .filter(|(key, _)| **key != 57)
+                .map(|(key, param)| {
+                    let param = match param {
+                        ObjParam::Group(g) => match classes.get(g) {
+                            Some(class) => ShapeParam::Class(*class),
+                            None => ShapeParam::Group(*g),
+                        },
+                        ObjParam::Color(c) => ShapeParam::Color(*c),
+                        ObjParam::Block(b) => ShapeParam::Block(*b),
+                        ObjParam::Item(i) => ShapeParam::Item(*i),
+                        ObjParam::Number(n) => ShapeParam::Number(n.to_bits()),
+                        ObjParam::Bool(b) => ShapeParam::Bool(*b),
+                        ObjParam::Text(t) => ShapeParam::Text(t.clone()),
+                        ObjParam::GroupList(l) => ShapeParam::GroupList(l.clone()),
+                        ObjParam::Epsilon => ShapeParam::Epsilon,
+                    };
+                    (*key, param)
+                })
+                .collect();
+            (params, obj.sync_group, obj.sync_part)
+        })
+        .collect()
+}
 
-    //     if trigger.role != TriggerRole::Output {
-    //         optimize_from(&mut network, &mut objects, (NO_GROUP, i), &mut closed_group);
-    //     } else {
-    //         (*network.get_mut(&NO_GROUP).unwrap()).triggers[i].deleted = false;
-    //     }
-    // }
+fn merge_gangs(network: &mut TriggerNetwork, objects: &mut Triggerlist, fixed: &BTreeSet<Group>) {
+    for gang in network.values_mut() {
+        for trigger in &mut gang.triggers {
+            trigger.deleted = false;
+        }
+    }
 
-    for (group, gang) in network.clone() {
-        if let ID::Specific(_) = group.id {
-            for (i, trigger) in gang.triggers.iter().enumerate() {
-                if trigger.role != TriggerRole::Output {
-                    optimize_from(&mut network, &mut objects, (group, i), &mut closed_group);
-                } else {
-                    (*network.get_mut(&group).unwrap()).triggers[i].deleted = false;
+    // groups that are used for anything other than being spawned can't be merged,
+    // since toggling or moving one copy would do the same to all of them
+    let mut fixed: HashSet<Group> = fixed.iter().copied().collect();
+    fixed.insert(NO_GROUP);
+    for gang in network.values() {
+        for trigger in &gang.triggers {
+            let params = &objects[trigger.obj].0.params;
+            // toggle triggers, and triggers like count and collision with "activate group"
+            // off, turn their target off instead of spawning it
+            let toggles_off = params.get(&1) == Some(&ObjParam::Number(1049.0))
+                || params.get(&56) == Some(&ObjParam::Bool(false));
+            for (key, param) in params {
+                let activates = *key == 57
+                    || (*key == 51 && trigger.role != TriggerRole::Output && !toggles_off);
+                match param {
+                    ObjParam::Group(g) if !activates => {
+                        fixed.insert(*g);
+                    }
+                    ObjParam::GroupList(list) => fixed.extend(list.iter().copied()),
+                    _ => (),
                 }
             }
         }
     }
 
-    //for (g, len) in group_sizes {}
-
-    // put into new fn ids and lists
-
-    //profit
+    // gangs can be merged if they have the same triggers, up to which gangs of the same
+    // class they spawn. all gangs start in one class, which is split by the shapes of the
+    // gangs until no class splits anymore, so gangs that spawn each other in the same way
+    // (like the chains of two calls of a macro) end up in the same classes too
+    let groups: Vec<Group> = network
+        .keys()
+        .filter(|g| matches!(g.id, ID::Arbitrary(_)) && !fixed.contains(g))
+        .copied()
+        .collect();
+    let mut classes: HashMap<Group, usize> = groups.iter().map(|g| (*g, 0)).collect();
+    let mut class_count = 1;
+    loop {
+        let mut shapes = HashMap::new();
+        let mut new_classes = HashMap::new();
+        for group in &groups {
+            let shape = (
+                classes[group],
+                gang_shape(&network[group], objects, &classes),
+            );
+            let next = shapes.len();
+            let class = *shapes.entry(shape).or_insert(next);
+            new_classes.insert(*group, class);
+        }
+        classes = new_classes;
+        if shapes.len() == class_count {
+            break;
+        }
+        class_count = shapes.len();
+    }
 
-    rebuild(&network, &obj_in)
-}
+    // the first group of every class is kept
+    let mut kept = HashMap::new();
+    let mut merged = BTreeMap::new();
+    for group in &groups {
+        match kept.entry(classes[group]) {
+            Entry::Occupied(first) => {
+                merged.insert(*group, *first.get());
+            }
+            Entry::Vacant(e) => {
+                e.insert(*group);
+            }
+        }
+    }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-enum IDData {
-    Group(Group),
-    Block(Block),
-    Item(Item),
+    for group in merged.keys() {
+        network.remove(group);
+    }
+    for gang in network.values() {
+        for trigger in &gang.triggers {
+            if let Some(ObjParam::Group(g)) = objects[trigger.obj].0.params.get_mut(&51) {
+                if let Some(kept) = merged.get(g) {
+                    *g = *kept;
+                }
+            }
+        }
+    }
 }
 
 fn reads_writes(t: Trigger, objects: &Triggerlist) -> (Vec<IDData>, Vec<IDData>) {
@@ -267,6 +699,7 @@ fn get_targets<'a>(
     delay: u32,
     ignore_optimized: bool,
     closed_group: &mut u16,
+    external: &BTreeSet<Group>,
 ) -> Option<Vec<(Group, u32)>> {
     //u32: delay in millis
 
@@ -286,7 +719,7 @@ fn get_targets<'a>(
     let list: Vec<(usize, Group)>;
 
     if let Some(ObjParam::Group(g)) = start_obj.get(&51) {
-        if let ID::Specific(_) = g.id {
+        if matches!(g.id, ID::Specific(_)) || external.contains(g) {
             (*network.get_mut(&start.0).unwrap()).triggers[start.1].deleted = false;
             return None;
         }
@@ -320,7 +753,7 @@ fn get_targets<'a>(
         _ => 0,
     };
 
-    let mut out = HashSet::<(Group, u32)>::new();
+    let mut out = BTreeSet::<(Group, u32)>::new();
 
     for (i, g) in list {
         let trigger_ptr = (g, i);
@@ -337,7 +770,7 @@ fn get_targets<'a>(
             }
         } else if network[&trigger_ptr.0].connections_in > 1 {
             (*network.get_mut(&trigger_ptr.0).unwrap()).triggers[trigger_ptr.1].deleted = false;
-            if optimize_from(network, objects, trigger_ptr, closed_group) {
+            if optimize_from(network, objects, trigger_ptr, closed_group, external) {
                 out.insert(target_out);
             } else {
                 (*network.get_mut(&trigger_ptr.0).unwrap()).triggers[trigger_ptr.1].deleted = true;
@@ -350,7 +783,7 @@ fn get_targets<'a>(
                     out.insert(target_out);
                 }
                 TriggerRole::Func => {
-                    if optimize_from(network, objects, trigger_ptr, closed_group) {
+                    if optimize_from(network, objects, trigger_ptr, closed_group, external) {
                         (*network.get_mut(&trigger_ptr.0).unwrap()).triggers[trigger_ptr.1]
                             .deleted = false;
                         out.insert(target_out);
@@ -364,6 +797,7 @@ fn get_targets<'a>(
                         delay + added_delay,
                         ignore_optimized,
                         closed_group,
+                        external,
                     ) {
                         Some(children) => out.extend(children),
                         None => {
@@ -392,7 +826,7 @@ fn create_spawn_trigger(
     //         opt   del
     settings: (bool, bool),
 ) {
-    let mut new_obj_map = HashMap::new();
+    let mut new_obj_map = BTreeMap::new();
     new_obj_map.insert(1, ObjParam::Number(1268.0));
     new_obj_map.insert(51, ObjParam::Group(target_group));
     new_obj_map.insert(63, ObjParam::Number(delay));
@@ -406,8 +840,10 @@ fn create_spawn_trigger(
         func_id: trigger.obj.0,
         mode: ObjectMode::Trigger,
         unique_id: objects[trigger.obj].0.unique_id,
-        sync_group: 0,
-        sync_part: 0,
+        sync_group: objects[trigger.obj].0.sync_group,
+        sync_part: objects[trigger.obj].0.sync_part,
+        source: objects[trigger.obj].0.source.clone(),
+        raw: None,
     };
 
     (*objects.list)[trigger.obj.0]
@@ -448,6 +884,7 @@ fn optimize_from<'a>(
     objects: &mut Triggerlist,
     start: (Group, usize),
     closed_group: &mut u16,
+    external: &BTreeSet<Group>,
 ) -> bool {
     //returns weather to keep or delete the trigger
 
@@ -463,7 +900,7 @@ fn optimize_from<'a>(
 
     //let role = trigger.role;
 
-    let targets = get_targets(network, objects, start, 0, false, closed_group);
+    let targets = get_targets(network, objects, start, 0, false, closed_group, external);
     let trigger = network[&start.0].triggers[start.1];
 
     // {
@@ -791,10 +1228,13 @@ fn fix_read_write_order(
             //change object group
             // TODO: enforce single group on trigger
 
-            (*objects)[trigger.obj]
-                .0
-                .params
-                .insert(57, ObjParam::Group(current_group));
+            // triggers that stay where they are keep their groups, even if they have several
+            if current_group != *group {
+                (*objects)[trigger.obj]
+                    .0
+                    .params
+                    .insert(57, ObjParam::Group(current_group));
+            }
         }
     }
     new_network
@@ -15,7 +15,6 @@ use logos::Logos;
 use std::error::Error;
 use std::fmt;
 
-use crate::compiler::print_error_intro;
 use crate::compiler_types::ImportType;
 
 pub type FileRange = ((usize, usize), (usize, usize));
@@ -79,24 +78,14 @@ impl fmt::Display for SyntaxError {
         //write!(f, "SuperErrorSideKick is here!")
         match self {
             SyntaxError::ExpectedErr {
-                expected,
-                found,
-                pos,
-                file,
-            } => {
-                print_error_intro(*pos, file);
-                write!(f, "SyntaxError: Expected {}, found {}", expected, found)
-            }
+                expected, found, ..
+            } => write!(f, "SyntaxError: Expected {}, found {}", expected, found),
 
-            SyntaxError::UnexpectedErr { found, pos, file } => {
-                print_error_intro(*pos, file);
+            SyntaxError::UnexpectedErr { found, .. } => {
                 write!(f, "SyntaxError: Unexpected {}", found)
             }
 
-            SyntaxError::SyntaxError { message, pos, file } => {
-                print_error_intro(*pos, file);
-                write!(f, "SyntaxError: {}", message)
-            }
+            SyntaxError::SyntaxError { message, .. } => write!(f, "SyntaxError: {}", message),
         }
     }
 }
@@ -334,7 +323,8 @@ pub enum Token {
     #[regex(r"//[^\n]*")]
     Comment,
 
-    #[token("/*")]
+    // the whole comment, up to the matching `*/`
+    #[token("/*", block_comment)]
     MultiCommentStart,
 
     #[token("*/")]
@@ -348,6 +338,30 @@ pub enum Token {
     Error,
 }
 
+// skips to the end of a block comment, which can have other block comments in it.
+// an unclosed comment runs to the end of the file
+fn block_comment(lex: &mut Lexer<Token>) -> bool {
+    let rest = lex.remainder().as_bytes();
+    let mut depth = 1;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if rest[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                break;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    lex.bump(i);
+    true
+}
+
 impl Token {
     fn typ(&self) -> &'static str {
         use Token::*;
@@ -366,14 +380,9 @@ impl Token {
             | ClosingSquareBracket | OpenBracket | ClosingBracket | Colon | DoubleColon
             | Period | DotDot | At | Hash | Arrow | ThickArrow => "terminator",
 
-            While | Sync => {
-                "reserved keyword (not currently in use, but may be used in future updates)"
-            }
-
             Return | Implement | For | In | ErrorStatement | If | Else | Object | Trigger
-            | Import | Extract | Null | Type | Let | SelfVal | Break | Continue | Switch | Case => {
-                "keyword"
-            }
+            | Import | Extract | Null | Type | Let | SelfVal | Break | Continue | Switch | Case
+            | While | Sync => "keyword",
             Comment | MultiCommentStart | MultiCommentEnd => "comment",
             StatementSeparator => "statement separator",
             Error => "unknown",
@@ -383,14 +392,21 @@ impl Token {
 
 pub struct ParseNotes {
     pub tag: ast::Tag,
+    // comments at the top of the file, above the tag. without a tag they belong to the first
+    // statement instead, if there is one
+    pub top_comment: Option<String>,
     pub file: PathBuf,
+    // syntax errors the parser recovered from
+    pub errors: Vec<SyntaxError>,
 }
 
 impl ParseNotes {
     pub fn new(path: PathBuf) -> Self {
         ParseNotes {
             tag: ast::Tag::new(),
+            top_comment: None,
             file: path,
+            errors: Vec::new(),
         }
     }
 }
@@ -432,31 +448,6 @@ impl<'a> Tokens<'a> {
     fn next(&mut self, ss: bool, comment: bool) -> Option<Token> {
         //println!("what ok {}", self.index);
 
-        if self.index > 0 {
-            let curr_element = self.stack[self.stack.len() - self.index].0;
-
-            if curr_element == Some(Token::MultiCommentStart) {
-                //println!("comment time");
-                let mut nest = 0;
-                loop {
-                    let next_elem = self.inner_next();
-
-                    if next_elem == Some(Token::MultiCommentStart) {
-                        nest += 1;
-                    } else if next_elem == Some(Token::MultiCommentEnd) {
-                        nest -= 1;
-                    }
-
-                    if nest == 0 {
-                        break;
-                    }
-                }
-                if !comment {
-                    self.inner_next();
-                }
-            }
-        }
-
         let next_element = self.inner_next();
 
         if (!ss && next_element == Some(Token::StatementSeparator))
@@ -493,7 +484,11 @@ impl<'a> Tokens<'a> {
         let len = self.stack.len();
         if len > self.index {
             if (!ss && self.stack[len - self.index - 1].0 == Some(Token::StatementSeparator))
-                || (!comment && self.stack[len - self.index - 1].0 == Some(Token::Comment))
+                || (!comment
+                    && matches!(
+                        self.stack[len - self.index - 1].0,
+                        Some(Token::Comment) | Some(Token::MultiCommentStart)
+                    ))
             {
                 self.previous_no_ignore(ss, comment)
             } else if len - self.index >= 1 {
@@ -525,38 +520,38 @@ impl<'a> Tokens<'a> {
         if self.stack.len() - self.index == 0 {
             return ((1, 0), (1, 0));
         }
-        let file_pos1 = self.stack[self.stack.len() - self.index - 1].2.start;
-        let file_pos2 = self.stack[self.stack.len() - self.index - 1].2.end;
-        /*println!(
-            "file pos: {}, line breaks: {:?}",
-            file_pos, self.line_breaks
-        );*/
-        let mut found_pos_1 = false;
-        let mut found_pos_2 = false;
-        let mut out = ((1, file_pos1), (1, file_pos2));
+        let range = &self.stack[self.stack.len() - self.index - 1].2;
+        (
+            self.file_position(range.start),
+            self.file_position(range.end),
+        )
+    }
 
+    // (line, column) of a byte index in the file
+    fn file_position(&self, file_pos: usize) -> (usize, usize) {
         for (i, lb) in self.line_breaks.iter().enumerate() {
-            let line_break = *lb as usize;
-            if !found_pos_1 && line_break >= file_pos1 {
-                if i == 0 {
-                    out.0 = (1, file_pos1);
-                } else {
-                    out.0 = (i + 1, file_pos1 - self.line_breaks[i - 1] as usize - 1);
-                }
-                found_pos_1 = true;
-            }
-
-            if !found_pos_2 && line_break >= file_pos2 {
+            if *lb as usize >= file_pos {
                 if i == 0 {
-                    out.1 = (1, file_pos2);
+                    return (1, file_pos);
                 } else {
-                    out.1 = (i + 1, file_pos2 - self.line_breaks[i - 1] as usize - 1);
+                    return (i + 1, file_pos - self.line_breaks[i - 1] as usize - 1);
                 }
-                found_pos_2 = true;
             }
         }
+        // after the last line break, on the line after it
+        match self.line_breaks.last() {
+            Some(lb) => (self.line_breaks.len() + 1, file_pos - *lb as usize - 1),
+            None => (1, file_pos),
+        }
+    }
 
-        out
+    // number of tokens read, used to go back to an earlier point with `rewind`
+    fn checkpoint(&self) -> usize {
+        self.stack.len() - self.index
+    }
+
+    fn rewind(&mut self, checkpoint: usize) {
+        self.index = self.stack.len() - checkpoint;
     }
 
     /*fn abs_position(&self) -> usize {
@@ -572,10 +567,26 @@ impl<'a> Tokens<'a> {
 
 const STATEMENT_SEPARATOR_DESC: &str = "Statement separator (line-break or ';')";
 
+/// Parses a script, returning the first syntax error if there are any
 pub fn parse_spwn(
-    mut unparsed: String,
+    unparsed: String,
     path: PathBuf,
 ) -> Result<(Vec<ast::Statement>, ParseNotes), SyntaxError> {
+    let (statements, mut notes) = parse_spwn_recovering(unparsed, path);
+    if notes.errors.is_empty() {
+        Ok((statements, notes))
+    } else {
+        Err(notes.errors.remove(0))
+    }
+}
+
+/// Parses a script and recovers from syntax errors by skipping the statement they are in.
+/// All errors are collected in `ParseNotes::errors`, and every skipped statement is kept in
+/// the AST as a `StatementBody::Invalid`.
+pub fn parse_spwn_recovering(
+    mut unparsed: String,
+    path: PathBuf,
+) -> (Vec<ast::Statement>, ParseNotes) {
     unparsed = unparsed.replace("\r\n", "\n");
 
     let tokens_iter = Token::lexer(&unparsed);
@@ -597,43 +608,160 @@ pub fn parse_spwn(
 
     tokens.line_breaks = line_breaks;
 
-    let start_tag = check_for_tag(&mut tokens, &mut notes)?;
-    notes.tag = start_tag;
-    loop {
-        //+ do something if we have tokens. if no more tokens, leave loop
-        match tokens.next(false, true) {
-            //oops we just advanced the tokens in an attempt to check if we have any
-            Some(_) => {
-                tokens.previous_no_ignore(false, true); //bring tokens back to original
-
-                //+ we are goign to parse the tokens
-                let mut parsed = parse_statement(&mut tokens, &mut notes)?;
-                if parsed.comment.0 == None && !statements.is_empty() {
-                    parsed.comment.0 = statements.last().unwrap().comment.1.clone();
-                    (*statements.last_mut().unwrap()).comment.1 = None;
-                }
+    let leading_comment = check_for_comment(&mut tokens);
 
-                statements.push(parsed)
-            }
-            None => break, //+ no more tokens, probably end of file
+    match check_for_tag(&mut tokens, &mut notes) {
+        Ok(start_tag) => notes.tag = start_tag,
+        Err(e) => {
+            notes.errors.push(e);
+            return (statements, notes);
+        }
+    }
+    //+ do something if we have tokens. if no more tokens (probably end of file), leave loop
+    loop {
+        let start = tokens.checkpoint();
+        if tokens.next(false, false).is_none() {
+            break;
         }
+        //oops we just advanced the tokens in an attempt to check if we have any
+        tokens.rewind(start); //bring tokens back to original
+
+        //+ we are goign to parse the tokens
+        let mut parsed = match parse_statement(&mut tokens, &mut notes) {
+            Ok(s) => s,
+            Err(e) => {
+                statements.push(recover(&mut tokens, &mut notes, start, e));
+                continue;
+            }
+        };
 
         //+ can't find any more tokens that are valid syntax, checking for line separator
         match tokens.next(true, false) {
             Some(Token::StatementSeparator) => {}
             Some(a) => {
-                return Err(SyntaxError::ExpectedErr {
-                    expected: STATEMENT_SEPARATOR_DESC.to_string(),
-                    found: format!("{}: \"{}\"", a.typ(), tokens.slice()),
-                    pos: tokens.position(),
-                    file: notes.file,
-                })
+                let err = missing_separator(&tokens, &notes, a);
+                statements.push(recover(&mut tokens, &mut notes, start, err));
+                continue;
+            }
+            None => (),
+        }
+
+        carry_comment(&mut statements, &mut parsed);
+        statements.push(parsed);
+    }
+
+    match statements.first_mut() {
+        Some(first) if notes.tag.tags.is_empty() => {
+            if let Some(comment) = leading_comment {
+                first.comment.0 = Some(comment + first.comment.0.as_deref().unwrap_or_default());
+            }
+        }
+        _ => notes.top_comment = leading_comment,
+    }
+
+    (statements, notes)
+}
+
+fn missing_separator(tokens: &Tokens, notes: &ParseNotes, found: Token) -> SyntaxError {
+    SyntaxError::ExpectedErr {
+        expected: STATEMENT_SEPARATOR_DESC.to_string(),
+        found: format!("{}: \"{}\"", found.typ(), tokens.slice()),
+        pos: tokens.position(),
+        file: notes.file.clone(),
+    }
+}
+
+fn is_statement_keyword(token: Token) -> bool {
+    use Token::*;
+    matches!(
+        token,
+        Return
+            | Implement
+            | For
+            | ErrorStatement
+            | If
+            | Break
+            | Continue
+            | While
+            | Extract
+            | Type
+            | Let
+            | Sync
+    )
+}
+
+/// Skips the rest of the statement starting at `start` after a syntax error, so parsing can
+/// continue after it. Stops after the next statement separator, or before a closing bracket
+/// or statement keyword, outside of any curly brackets opened in the statement.
+fn recover(
+    tokens: &mut Tokens,
+    notes: &mut ParseNotes,
+    start: usize,
+    err: SyntaxError,
+) -> ast::Statement {
+    // always skip at least one token, and everything up to the error
+    let error_at = tokens.checkpoint().max(start + 1);
+    notes.errors.push(err);
+
+    tokens.rewind(start);
+    let mut depth = 0;
+    let mut skipped_any = false;
+    loop {
+        let before = tokens.checkpoint();
+        let past_error = before >= error_at;
+        let token = tokens.next(true, false);
+        match token {
+            None => {
+                tokens.rewind(before);
+                break;
+            }
+            Some(Token::OpenCurlyBracket) => depth += 1,
+            Some(Token::ClosingCurlyBracket) => {
+                if depth == 0 {
+                    // belongs to the surrounding block (unless it's a stray one at the start)
+                    if skipped_any {
+                        tokens.rewind(before);
+                    }
+                    break;
+                }
+                depth -= 1;
             }
-            None => break,
+            Some(Token::StatementSeparator) if depth == 0 && past_error => break,
+            Some(t) if depth == 0 && past_error && is_statement_keyword(t) => {
+                tokens.rewind(before);
+                break;
+            }
+            _ => (),
+        }
+        if token != Some(Token::StatementSeparator) {
+            skipped_any = true;
         }
     }
 
-    Ok((statements, notes))
+    // the source code that was skipped, without separators around it
+    let end = tokens.checkpoint();
+    let skipped: Vec<&core::ops::Range<usize>> = tokens.stack[start..end]
+        .iter()
+        .filter(|(t, _, _)| t.is_some() && *t != Some(Token::StatementSeparator))
+        .map(|(_, _, range)| range)
+        .collect();
+    let (source, pos) = match (skipped.first(), skipped.last()) {
+        (Some(first), Some(last)) => (
+            tokens.iter.source()[first.start..last.end].to_string(),
+            (
+                tokens.file_position(first.start),
+                tokens.file_position(last.end),
+            ),
+        ),
+        _ => (String::new(), tokens.position()),
+    };
+
+    ast::Statement {
+        body: ast::StatementBody::Invalid(source),
+        arrow: false,
+        pos,
+        comment: (None, None),
+    }
 }
 
 fn parse_cmp_stmt(
@@ -642,19 +770,19 @@ fn parse_cmp_stmt(
 ) -> Result<Vec<ast::Statement>, SyntaxError> {
     let mut statements = Vec::<ast::Statement>::new();
     loop {
-        match tokens.next(false, true) {
+        let start = tokens.checkpoint();
+        let mut parsed = match tokens.next(false, false) {
             Some(Token::ClosingCurlyBracket) => break,
             Some(_) => {
-                tokens.previous_no_ignore(false, true);
+                tokens.rewind(start);
 
-                let mut parsed = parse_statement(tokens, notes)?;
-                if parsed.comment.0 == None && !statements.is_empty() {
-                    parsed.comment.0 = statements.last().unwrap().comment.1.clone();
-                    (*statements.last_mut().unwrap()).comment.1 = None;
+                match parse_statement(tokens, notes) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        statements.push(recover(tokens, notes, start, e));
+                        continue;
+                    }
                 }
-
-                statements.push(parsed) // add to big statement list
-                                        //println!("statement done");
             }
             None => {
                 return Err(SyntaxError::SyntaxError {
@@ -663,18 +791,53 @@ fn parse_cmp_stmt(
                     file: notes.file.clone(),
                 })
             }
-        }
+        };
 
-        match tokens.next(true, false) {
-            Some(Token::StatementSeparator) => {}
-            Some(Token::ClosingCurlyBracket) => break,
-            a => expected!(STATEMENT_SEPARATOR_DESC.to_string(), tokens, notes, a),
+        let end = match tokens.next(true, false) {
+            Some(Token::StatementSeparator) => false,
+            Some(Token::ClosingCurlyBracket) => true,
+            // skip the rest of this statement, the block can still be parsed after it
+            Some(a) => {
+                let err = missing_separator(tokens, notes, a);
+                statements.push(recover(tokens, notes, start, err));
+                continue;
+            }
+            a @ None => expected!(STATEMENT_SEPARATOR_DESC.to_string(), tokens, notes, a),
+        };
+
+        carry_comment(&mut statements, &mut parsed);
+        statements.push(parsed); // add to big statement list
+
+        if end {
+            break;
         }
     }
     //tokens.next(false, false);
     Ok(statements)
 }
 
+// comments after a statement are parsed as a part of it. only the one on the same line
+// is kept there, the rest is moved to the statement after it
+fn carry_comment(statements: &mut [ast::Statement], parsed: &mut ast::Statement) {
+    if parsed.comment.0.is_some() {
+        return;
+    }
+    if let Some(last) = statements.last_mut() {
+        if let Some(comment) = last.comment.1.take() {
+            let (same_line, rest) = match comment.find('\n') {
+                Some(i) => comment.split_at(i),
+                None => (comment.as_str(), ""),
+            };
+            if !same_line.trim().is_empty() {
+                last.comment.1 = Some(same_line.to_string());
+            }
+            if rest.contains("//") || rest.contains("/*") {
+                parsed.comment.0 = Some(rest.to_string());
+            }
+        }
+    }
+}
+
 pub fn parse_statement(
     tokens: &mut Tokens,
     notes: &mut ParseNotes,
@@ -706,6 +869,7 @@ pub fn parse_statement(
             let rest_of_statement = parse_statement(tokens, notes)?; // recursion moment
 
             arrow = true;
+            comment_after = rest_of_statement.comment.1;
             rest_of_statement.body
 
             /* Summary:
@@ -810,6 +974,42 @@ pub fn parse_statement(
             */
         }
 
+        Some(Token::While) => {
+            //parse while loop
+            let condition = parse_expr(tokens, notes, true, true)?;
+            match tokens.next(false, false) {
+                Some(Token::OpenCurlyBracket) => (),
+                a => expected!("'{'".to_string(), tokens, notes, a),
+            }
+            let body = parse_cmp_stmt(tokens, notes)?;
+
+            ast::StatementBody::While(ast::While { condition, body })
+        }
+
+        Some(Token::Sync) => {
+            //parse sync block
+            let group = match tokens.next(false, false) {
+                Some(Token::Symbol) => tokens.slice(),
+                a => expected!("sync group name".to_string(), tokens, notes, a),
+            };
+            match tokens.next(false, false) {
+                Some(Token::OpenSquareBracket) => (),
+                a => expected!("'['".to_string(), tokens, notes, a),
+            }
+            let part = parse_expr(tokens, notes, true, true)?;
+            match tokens.next(false, false) {
+                Some(Token::ClosingSquareBracket) => (),
+                a => expected!("']'".to_string(), tokens, notes, a),
+            }
+            match tokens.next(false, false) {
+                Some(Token::OpenCurlyBracket) => (),
+                a => expected!("'{'".to_string(), tokens, notes, a),
+            }
+            let body = parse_cmp_stmt(tokens, notes)?;
+
+            ast::StatementBody::Sync(ast::SyncBlock { group, part, body })
+        }
+
         Some(Token::For) => {
             //parse for statement
 
@@ -1001,6 +1201,12 @@ pub fn parse_statement(
     })
 }
 
+// comments between list entries are kept as a trailing comment of the entry before them
+fn append_comment(expr: &mut ast::Expression, comment: String) {
+    let last = expr.values.last_mut().unwrap();
+    last.comment.1 = Some(last.comment.1.take().unwrap_or_default() + &comment);
+}
+
 fn check_for_comment(tokens: &mut Tokens) -> Option<String> {
     let mut comment_found = false;
     //let mut line_break_start = false;
@@ -1020,7 +1226,7 @@ fn check_for_comment(tokens: &mut Tokens) -> Option<String> {
 
     loop {
         result += &match tokens.next(true, true) {
-            Some(Token::Comment) => {
+            Some(Token::Comment) | Some(Token::MultiCommentStart) => {
                 comment_found = true;
                 tokens.slice()
             }
@@ -1147,7 +1353,17 @@ fn parse_cases(tokens: &mut Tokens, notes: &mut ParseNotes) -> Result<Vec<ast::C
     //let mut do_we_have_next = true;
 
     let mut cases = Vec::<ast::Case>::new();
+    let mut leading_comment = None;
     loop {
+        if let Some(comment) = check_for_comment(tokens) {
+            match cases.last_mut() {
+                Some(case) => append_comment(&mut case.body, comment),
+                None => leading_comment = Some(comment),
+            }
+        }
+        if let (Some(comment), Some(case)) = (leading_comment.take(), cases.first_mut()) {
+            append_comment(&mut case.body, comment);
+        }
         match tokens.next(false, false) {
             Some(Token::ClosingCurlyBracket) => break,
             Some(Token::Else) => {
@@ -1251,9 +1467,10 @@ fn parse_expr(
     let mut values = Vec::<ast::Variable>::new();
     let mut operators = Vec::<ast::Operator>::new();
 
+    let start = tokens.checkpoint();
     tokens.next(false, false);
     let (start_pos, _) = tokens.position();
-    tokens.previous_no_ignore(false, false);
+    tokens.rewind(start);
 
     values.push(parse_variable(tokens, notes, check_for_comments)?);
     // all expressions begin with a variable
@@ -1286,6 +1503,7 @@ fn parse_expr(
     tokens.previous_no_ignore(false, true);
     let express = fix_precedence(ast::Expression { values, operators }); //pemdas and stuff
 
+    let before_ternary = tokens.checkpoint();
     match tokens.next(true, false) {
         Some(Token::If) => {
             // oooh ternaries
@@ -1366,7 +1584,7 @@ fn parse_expr(
             })
         }
         _ => {
-            tokens.previous_no_ignore(false, false);
+            tokens.rewind(before_ternary);
             Ok(express)
         }
     }
@@ -1413,8 +1631,17 @@ fn parse_dict(
     notes: &mut ParseNotes,
 ) -> Result<Vec<ast::DictDef>, SyntaxError> {
     let mut defs = Vec::<ast::DictDef>::new();
+    let mut leading_comment = None;
 
     loop {
+        if let Some(comment) = check_for_comment(tokens) {
+            match defs.last_mut() {
+                Some(ast::DictDef::Def((_, expr))) | Some(ast::DictDef::Extract(expr)) => {
+                    append_comment(expr, comment)
+                }
+                None => leading_comment = Some(comment),
+            }
+        }
         match tokens.next(false, false) {
             Some(Token::Symbol) | Some(Token::Type) => {
                 let symbol = tokens.slice();
@@ -1476,6 +1703,12 @@ fn parse_dict(
                 a
             ),
         };
+        if let (Some(comment), Some(ast::DictDef::Def((_, expr))))
+        | (Some(comment), Some(ast::DictDef::Extract(expr))) =
+            (leading_comment.take(), defs.first_mut())
+        {
+            append_comment(expr, comment);
+        }
         let next = tokens.next(false, false);
 
         if next == Some(Token::ClosingCurlyBracket) {
@@ -1505,18 +1738,30 @@ fn parse_object(
         a => expected!("'{'".to_string(), tokens, notes, a),
     }
 
+    let mut leading_comment = None;
+
     loop {
+        if let Some(comment) = check_for_comment(tokens) {
+            match defs.last_mut() {
+                Some((_, val)) => append_comment(val, comment),
+                None => leading_comment = Some(comment),
+            }
+        }
+        let before = tokens.checkpoint();
         if tokens.next(false, false) == Some(Token::ClosingCurlyBracket) {
             break;
         } else {
-            tokens.previous();
+            tokens.rewind(before);
         }
         let key = parse_expr(tokens, notes, true, true)?;
         match tokens.next(false, false) {
             Some(Token::Colon) => (),
             a => expected!("':'".to_string(), tokens, notes, a),
         }
-        let val = parse_expr(tokens, notes, true, true)?;
+        let mut val = parse_expr(tokens, notes, true, true)?;
+        if let Some(comment) = leading_comment.take() {
+            append_comment(&mut val, comment);
+        }
 
         defs.push((key, val));
 
@@ -1817,6 +2062,24 @@ pub fn str_content(
     Ok(out)
 }
 
+// the body of a `(args) => value` macro
+fn parse_arrow_body(
+    tokens: &mut Tokens,
+    notes: &mut ParseNotes,
+) -> Result<Vec<ast::Statement>, SyntaxError> {
+    let start = tokens.position().0;
+    let mut expr = parse_expr(tokens, notes, true, true)?;
+    let end = tokens.position().1;
+    // same as for a return statement
+    let comment_after = expr.values.last_mut().unwrap().comment.1.take();
+    Ok(vec![ast::Statement {
+        body: ast::StatementBody::Return(Some(expr)),
+        arrow: false,
+        comment: (None, comment_after),
+        pos: (start, end),
+    }])
+}
+
 fn parse_variable(
     tokens: &mut Tokens,
     notes: &mut ParseNotes,
@@ -1939,15 +2202,7 @@ fn parse_variable(
                         Vec::new()
                     };
 
-                    let start = tokens.position().0;
-                    let expr = parse_expr(tokens, notes, true, true)?;
-                    let end = tokens.position().1;
-                    let macro_body = vec![ast::Statement {
-                        body: ast::StatementBody::Return(Some(expr)),
-                        arrow: false,
-                        comment: (None, None),
-                        pos: (start, end),
-                    }];
+                    let macro_body = parse_arrow_body(tokens, notes)?;
 
                     ast::ValueBody::Macro(ast::Macro {
                         args: arg,
@@ -2031,24 +2286,18 @@ fn parse_variable(
         }
 
         Some(Token::OpenBracket) => {
+            // errors recovered from while trying to parse this as an expression
+            // will be found again when parsing it as a macro
+            let error_count = notes.errors.len();
             let parse_macro_def = |tokens: &mut Tokens,
                                    notes: &mut ParseNotes|
              -> Result<ast::ValueBody, SyntaxError> {
+                notes.errors.truncate(error_count);
                 let args = parse_arg_def(tokens, notes)?;
 
                 let body = match tokens.next(false, false) {
                     Some(Token::OpenCurlyBracket) => parse_cmp_stmt(tokens, notes)?,
-                    Some(Token::ThickArrow) => {
-                        let start = tokens.position().0;
-                        let expr = parse_expr(tokens, notes, true, true)?;
-                        let end = tokens.position().1;
-                        vec![ast::Statement {
-                            body: ast::StatementBody::Return(Some(expr)),
-                            arrow: false,
-                            comment: (None, None),
-                            pos: (start, end),
-                        }]
-                    }
+                    Some(Token::ThickArrow) => parse_arrow_body(tokens, notes)?,
                     a => expected!("'{'".to_string(), tokens, notes, a),
                 };
 
@@ -2158,6 +2407,7 @@ fn parse_variable(
     let mut path = Vec::<ast::Path>::new();
 
     loop {
+        let before = tokens.checkpoint();
         match tokens.next(true, false) {
             Some(Token::OpenSquareBracket) => {
                 let index = parse_expr(tokens, notes, true, true)?;
@@ -2215,10 +2465,12 @@ fn parse_variable(
             Some(Token::Increment) => path.push(ast::Path::Increment),
             Some(Token::Decrement) => path.push(ast::Path::Decrement),
 
-            _ => break,
+            _ => {
+                tokens.rewind(before);
+                break;
+            }
         }
     }
-    tokens.previous_no_ignore(false, true);
 
     let (_, end_pos) = tokens.position();
 
@@ -0,0 +1,223 @@
+//! Interactive SPWN session, where every line continues the same scope
+
+use crate::ast::{Operator, Statement, StatementBody, UnaryOperator};
+use crate::compiler::{compile_scope, import_std, CONTEXT_MAX, NULL_STORAGE};
+use crate::compiler_types::{CompilerInfo, Context, Globals, Value};
+use crate::diagnostic::Diagnostic;
+use crate::levelstring::{self, LevelString};
+use crate::parser::parse_spwn;
+
+use smallvec::{smallvec, SmallVec};
+use std::path::{Path, PathBuf};
+
+pub const REPL_HELP: &str = "\
+:type <expression>  shows the type of an expression
+:objects            shows the objects added so far
+:reset              forgets all variables and objects
+:help               shows this message
+:quit               exits the repl";
+
+pub struct Repl {
+    globals: Globals,
+    contexts: SmallVec<[Context; CONTEXT_MAX]>,
+    path: PathBuf,
+    included_paths: Vec<PathBuf>,
+}
+
+impl Repl {
+    /// Starts a session with the standard library imported. `path` is used
+    /// like the path of a script file, for finding imports relative to it.
+    pub fn new(path: PathBuf, included_paths: Vec<PathBuf>) -> Result<Self, Diagnostic> {
+        let mut globals = Globals::new(path.clone());
+        let info = repl_info(&path, included_paths.clone());
+        let context =
+            import_std(&Context::new(), &mut globals, info).map_err(|e| Diagnostic::from(&e))?;
+
+        Ok(Repl {
+            globals,
+            contexts: smallvec![context],
+            path,
+            included_paths,
+        })
+    }
+
+    /// Runs a line (or a command starting with `:`), and returns what should be printed
+    pub fn eval(&mut self, input: &str) -> Result<Vec<String>, Diagnostic> {
+        let input = input.trim();
+        let (command, rest) = match input.find(char::is_whitespace) {
+            Some(i) => (&input[..i], input[i..].trim()),
+            None => (input, ""),
+        };
+        match command {
+            "" => Ok(Vec::new()),
+            ":help" => Ok(vec![REPL_HELP.to_string()]),
+            ":reset" => {
+                *self = Repl::new(self.path.clone(), self.included_paths.clone())?;
+                Ok(vec!["Cleared all variables and objects".to_string()])
+            }
+            ":objects" => self.objects(),
+            ":type" => {
+                // the expression is only looked at, so whatever it does is undone after
+                let (globals, contexts) = (self.globals.clone(), self.contexts.clone());
+                let types = self.run(rest).map(|values| {
+                    values
+                        .into_iter()
+                        .map(|(val, _)| format!("@{}", self.globals.get_type_str(val)))
+                        .collect()
+                });
+                self.globals = globals;
+                self.contexts = contexts;
+                types
+            }
+            c if c.starts_with(':') => Err(Diagnostic::error(
+                "repl_error",
+                format!("Unknown command {} (try :help)", c),
+            )),
+            _ => {
+                let before = self.contexts.len();
+                let values = self.run(input)?;
+                let count = values.len();
+                let mut out: Vec<String> = values
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, (val, _))| self.globals.stored_values[*val] != Value::Null)
+                    .map(|(i, (val, _))| {
+                        let s = self.globals.stored_values[val].to_str(&self.globals);
+                        if count > 1 {
+                            // show which context each value is from
+                            format!("[context {}/{}] {}", i + 1, count, s)
+                        } else {
+                            s
+                        }
+                    })
+                    .collect();
+                if out.is_empty() && count != before {
+                    out.push(format!("(the context was split into {} contexts)", count));
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    // compiles the input, and returns the value of the last statement in every context
+    fn run(&mut self, input: &str) -> Result<Vec<(usize, Context)>, Diagnostic> {
+        let (mut statements, _) =
+            parse_spwn(input.to_string(), self.path.clone()).map_err(|e| Diagnostic::from(&e))?;
+
+        // the value of an expression comes back as a return value
+        let has_value = match statements.last() {
+            Some(s) => !s.arrow && is_value(&s.body),
+            None => false,
+        };
+        if has_value {
+            let last = statements.pop().unwrap();
+            if let StatementBody::Expr(e) = last.body {
+                statements.push(Statement {
+                    body: StatementBody::Return(Some(e)),
+                    ..last
+                });
+            }
+        }
+
+        let info = repl_info(&self.path, self.included_paths.clone());
+        self.globals.keep_scope_values = true;
+        let (contexts, returns) =
+            compile_scope(&statements, self.contexts.clone(), &mut self.globals, info)
+                .map_err(|e| Diagnostic::from(&e))?;
+
+        if has_value {
+            self.contexts = returns
+                .iter()
+                .map(|(_, c)| Context {
+                    broken: None,
+                    ..c.clone()
+                })
+                .collect();
+            Ok(returns.into_iter().collect())
+        } else {
+            self.contexts = contexts;
+            Ok(self
+                .contexts
+                .iter()
+                .map(|c| (NULL_STORAGE, c.clone()))
+                .collect())
+        }
+    }
+
+    fn objects(&self) -> Result<Vec<String>, Diagnostic> {
+        let mut objects = levelstring::apply_fn_ids(&self.globals.func_ids);
+        levelstring::apply_sync_groups(&mut objects, &self.globals.sync_groups);
+        objects.extend(self.globals.objects.iter().cloned());
+        if objects.is_empty() {
+            return Ok(vec!["No objects".to_string()]);
+        }
+
+        let level_string = crate::add_to_level_with(
+            objects,
+            String::new(),
+            &self.globals.id_config,
+            self.globals.target,
+            None,
+            false,
+        )
+        .map_err(|e| Diagnostic::error("level_error", e))?
+        .level_string;
+        let level =
+            LevelString::parse(&level_string).map_err(|e| Diagnostic::error("level_error", e))?;
+        Ok(level.objects.iter().map(|o| o.to_string()).collect())
+    }
+}
+
+fn repl_info(path: &Path, includes: Vec<PathBuf>) -> CompilerInfo {
+    CompilerInfo {
+        depth: 0,
+        path: vec!["repl".to_string()],
+        pos: ((0, 0), (0, 0)),
+        current_file: path.to_path_buf(),
+        current_module: String::new(),
+        includes,
+        call_stack: Vec::new(),
+        assigned_vars: None,
+        statement_label: None,
+    }
+}
+
+// whether a statement is an expression with a value worth showing, and not a definition
+fn is_value(body: &StatementBody) -> bool {
+    let e = match body {
+        StatementBody::Expr(e) => e,
+        _ => return false,
+    };
+    let assigns = matches!(
+        e.operators.first(),
+        Some(Operator::Assign)
+            | Some(Operator::Add)
+            | Some(Operator::Subtract)
+            | Some(Operator::Multiply)
+            | Some(Operator::Divide)
+            | Some(Operator::IntDivide)
+            | Some(Operator::Exponate)
+            | Some(Operator::Modulate)
+            | Some(Operator::Swap)
+    );
+    !assigns && e.values[0].operator != Some(UnaryOperator::Let)
+}
+
+/// Whether more lines are needed to close all brackets in the input
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '{' | '(' | '[' if !in_string => depth += 1,
+            '}' | ')' | ']' if !in_string => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0
+}
@@ -0,0 +1,633 @@
+//! Headless trigger simulator
+//!
+//! Runs the objects of a compiled script (like [`CompileOutput::objects`](crate::CompileOutput))
+//! without Geometry Dash, so the trigger logic of a script can be checked in tests.
+//!
+//! ```no_run
+//! use spwn::simulate::{Input, Simulation};
+//!
+//! let output = spwn::Compiler::new("main.spwn").compile();
+//! let mut sim = Simulation::new(&output.objects);
+//! sim.schedule(1.0, Input::Touch { dual_side: false });
+//! let timeline = sim.run(10.0).unwrap();
+//! println!("{:?}", timeline.items_at(10.0));
+//! ```
+//!
+//! Only the triggers that matter for control flow are simulated: spawn, toggle, pickup,
+//! instant count, count, collision, touch and death triggers. Other triggers (move, color,
+//! ...) are only recorded in the timeline when they are activated.
+
+use crate::ast::ObjectMode;
+use crate::builtin::{Block, Group, Item, ID};
+use crate::levelstring::{GDObj, ObjParam};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+
+/// Player speed at normal speed, in units per second. Used to find out when
+/// triggers that aren't spawn triggered are passed.
+pub const PLAYER_SPEED: f64 = 311.58;
+/// Delay used for spawn triggers with an epsilon delay (same estimate as the optimizer)
+pub const EPSILON_DELAY: f64 = 0.05;
+/// Activations allowed at a single point in time before the simulation is
+/// assumed to be stuck in a loop
+pub const MAX_ACTIVATIONS: usize = 100_000;
+
+/// Something the player does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Touch { dual_side: bool },
+    TouchEnd { dual_side: bool },
+    Collision(Block, Block),
+    CollisionExit(Block, Block),
+    Death,
+}
+
+/// A change in the state of the level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// An item got a new value
+    Item { item: Item, value: i32 },
+    /// A group was toggled on or off
+    Toggle { group: Group, on: bool },
+    /// A group was spawned
+    Spawn(Group),
+    /// The object at this index was activated
+    Trigger(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelineEntry {
+    pub time: f64,
+    pub change: Change,
+}
+
+/// Every change that happened during a simulation, in order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timeline {
+    pub entries: Vec<TimelineEntry>,
+}
+
+impl Timeline {
+    /// Value of an item at `time`
+    pub fn item_at(&self, item: Item, time: f64) -> i32 {
+        let mut value = 0;
+        for entry in self.entries.iter().take_while(|e| e.time <= time) {
+            if let Change::Item { item: i, value: v } = entry.change {
+                if i == item {
+                    value = v;
+                }
+            }
+        }
+        value
+    }
+
+    /// Whether a group is toggled on at `time`
+    pub fn group_enabled_at(&self, group: Group, time: f64) -> bool {
+        let mut enabled = true;
+        for entry in self.entries.iter().take_while(|e| e.time <= time) {
+            if let Change::Toggle { group: g, on } = entry.change {
+                if g == group {
+                    enabled = on;
+                }
+            }
+        }
+        enabled
+    }
+
+    /// Values of all items that have been changed before `time`
+    pub fn items_at(&self, time: f64) -> HashMap<Item, i32> {
+        let mut out = HashMap::new();
+        for entry in self.entries.iter().take_while(|e| e.time <= time) {
+            if let Change::Item { item, value } = entry.change {
+                out.insert(item, value);
+            }
+        }
+        out
+    }
+
+    /// Times at which a group was spawned
+    pub fn spawns(&self, group: Group) -> Vec<f64> {
+        self.entries
+            .iter()
+            .filter(|e| e.change == Change::Spawn(group))
+            .map(|e| e.time)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulationError {
+    /// More than `MAX_ACTIVATIONS` triggers were activated at the same time
+    InfiniteLoop { time: f64 },
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::InfiniteLoop { time } => write!(
+                f,
+                "More than {} triggers were activated at {:.3} seconds without any delay \
+                 (infinite loop?)",
+                MAX_ACTIVATIONS, time
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    Larger,
+    Smaller,
+}
+
+// what a touch trigger does to its target when the screen is touched. with hold mode,
+// the opposite happens when the touch ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TouchMode {
+    Spawn,
+    On,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Spawn {
+        target: ID,
+        delay: f64,
+    },
+    Toggle {
+        target: ID,
+        on: bool,
+    },
+    Pickup {
+        item: ID,
+        count: i32,
+    },
+    InstantCount {
+        item: ID,
+        count: i32,
+        comparison: Comparison,
+        target: ID,
+        activate: bool,
+    },
+    // the rest only start listening for something when they are activated
+    Count {
+        item: ID,
+        count: i32,
+        target: ID,
+        activate: bool,
+        multi: bool,
+    },
+    Collision {
+        a: ID,
+        b: ID,
+        on_exit: bool,
+        target: ID,
+        activate: bool,
+    },
+    Touch {
+        mode: TouchMode,
+        hold: bool,
+        dual: bool,
+        target: ID,
+    },
+    Death {
+        target: ID,
+        activate: bool,
+    },
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct SimTrigger {
+    obj: usize,
+    groups: Vec<ID>,
+    action: Action,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    Spawn(ID),
+    Activate(usize),
+    Input(Input),
+}
+
+// queued event, ordered so the BinaryHeap pops the earliest (and first scheduled) one
+#[derive(Debug, Clone, Copy)]
+struct Queued {
+    time: f64,
+    seq: usize,
+    event: Event,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .total_cmp(&self.time)
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+fn id_param(obj: &GDObj, key: u16) -> Option<ID> {
+    match obj.params.get(&key)? {
+        ObjParam::Group(Group { id })
+        | ObjParam::Block(Block { id })
+        | ObjParam::Item(Item { id }) => Some(*id),
+        _ => obj.param_ids(key).first().map(|id| ID::Specific(*id)),
+    }
+}
+
+fn groups_of(obj: &GDObj) -> Vec<ID> {
+    match obj.params.get(&57) {
+        Some(ObjParam::Group(g)) => vec![g.id],
+        Some(ObjParam::GroupList(l)) => l.iter().map(|g| g.id).collect(),
+        _ => obj.groups().into_iter().map(ID::Specific).collect(),
+    }
+}
+
+fn flag(obj: &GDObj, key: u16) -> bool {
+    obj.param_number(key).map(|n| n != 0.0).unwrap_or(false)
+}
+
+fn int(obj: &GDObj, key: u16) -> i32 {
+    obj.param_number(key).map(|n| n.round() as i32).unwrap_or(0)
+}
+
+fn get_action(obj: &GDObj) -> Option<Action> {
+    let target = id_param(obj, 51).unwrap_or(ID::Specific(0));
+    let item = id_param(obj, 80).unwrap_or(ID::Specific(0));
+    let activate = flag(obj, 56);
+
+    Some(match obj.obj_id()? {
+        1268 => Action::Spawn {
+            target,
+            delay: match obj.params.get(&63) {
+                Some(ObjParam::Epsilon) => EPSILON_DELAY,
+                _ => obj.param_number(63).unwrap_or(0.0),
+            },
+        },
+        1049 => Action::Toggle {
+            target,
+            on: activate,
+        },
+        1817 => Action::Pickup {
+            item,
+            count: int(obj, 77),
+        },
+        1811 => Action::InstantCount {
+            item,
+            count: int(obj, 77),
+            comparison: match int(obj, 88) {
+                1 => Comparison::Larger,
+                2 => Comparison::Smaller,
+                _ => Comparison::Equal,
+            },
+            target,
+            activate,
+        },
+        1611 => Action::Count {
+            item,
+            count: int(obj, 77),
+            target,
+            activate,
+            multi: flag(obj, 104),
+        },
+        1815 => Action::Collision {
+            a: item,
+            b: id_param(obj, 95).unwrap_or(ID::Specific(0)),
+            on_exit: flag(obj, 93),
+            target,
+            activate,
+        },
+        1595 => Action::Touch {
+            mode: match int(obj, 82) {
+                1 => TouchMode::On,
+                2 => TouchMode::Off,
+                _ => TouchMode::Spawn,
+            },
+            hold: flag(obj, 81),
+            dual: flag(obj, 89),
+            target,
+        },
+        1812 => Action::Death { target, activate },
+        _ if obj.mode == ObjectMode::Trigger || flag(obj, 62) => Action::Other,
+        // not a trigger
+        _ => return None,
+    })
+}
+
+/// State of a running simulation
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    triggers: Vec<SimTrigger>,
+    // spawn triggered triggers in each group, in object order
+    by_group: HashMap<ID, Vec<usize>>,
+    queue: BinaryHeap<Queued>,
+    seq: usize,
+    time: f64,
+
+    items: HashMap<ID, i32>,
+    disabled: HashSet<ID>,
+    // count, collision, touch and death triggers that have been activated
+    listening: Vec<usize>,
+    // count triggers without multi activate that have already fired
+    fired: HashSet<usize>,
+
+    timeline: Timeline,
+}
+
+impl Simulation {
+    pub fn new(objects: &[GDObj]) -> Self {
+        let mut sim = Simulation {
+            triggers: Vec::new(),
+            by_group: HashMap::new(),
+            queue: BinaryHeap::new(),
+            seq: 0,
+            time: 0.0,
+            items: HashMap::new(),
+            disabled: HashSet::new(),
+            listening: Vec::new(),
+            fired: HashSet::new(),
+            timeline: Timeline::default(),
+        };
+
+        for (i, obj) in objects.iter().enumerate() {
+            let action = match get_action(obj) {
+                Some(a) => a,
+                None => continue,
+            };
+            let index = sim.triggers.len();
+            let groups = groups_of(obj);
+
+            if flag(obj, 62) {
+                for g in &groups {
+                    sim.by_group.entry(*g).or_default().push(index);
+                }
+            } else {
+                // activated when the player passes it
+                let x = obj.x().unwrap_or(0.0).max(0.0);
+                sim.push(x / PLAYER_SPEED, Event::Activate(index));
+            }
+            sim.triggers.push(SimTrigger {
+                obj: i,
+                groups,
+                action,
+            });
+        }
+        sim
+    }
+
+    /// Makes the player do something at `time`
+    pub fn schedule(&mut self, time: f64, input: Input) {
+        self.push(time, Event::Input(input));
+    }
+
+    /// Spawns a group at `time`, like a spawn trigger would
+    pub fn spawn_at(&mut self, time: f64, group: Group) {
+        self.push(time, Event::Spawn(group.id));
+    }
+
+    /// Runs everything that happens until `until` (in seconds)
+    pub fn run(&mut self, until: f64) -> Result<&Timeline, SimulationError> {
+        while let Some(next) = self.queue.peek() {
+            if next.time > until {
+                break;
+            }
+            let Queued { time, event, .. } = self.queue.pop().unwrap();
+            self.time = time;
+
+            let mut stack = Vec::new();
+            match event {
+                Event::Spawn(group) => self.spawn(group, &mut stack),
+                Event::Activate(trigger) => stack.push(trigger),
+                Event::Input(input) => self.input(input, &mut stack),
+            }
+            self.execute(stack)?;
+        }
+        self.time = self.time.max(until);
+        Ok(&self.timeline)
+    }
+
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    /// Current value of an item
+    pub fn item(&self, item: Item) -> i32 {
+        self.items.get(&item.id).copied().unwrap_or(0)
+    }
+
+    /// Whether a group is currently toggled on
+    pub fn group_enabled(&self, group: Group) -> bool {
+        !self.disabled.contains(&group.id)
+    }
+
+    fn push(&mut self, time: f64, event: Event) {
+        self.queue.push(Queued {
+            time,
+            seq: self.seq,
+            event,
+        });
+        self.seq += 1;
+    }
+
+    fn record(&mut self, change: Change) {
+        self.timeline.entries.push(TimelineEntry {
+            time: self.time,
+            change,
+        });
+    }
+
+    // puts the triggers of a group on the stack so they run before anything else,
+    // in object order
+    fn spawn(&mut self, group: ID, stack: &mut Vec<usize>) {
+        self.record(Change::Spawn(Group { id: group }));
+        if let Some(triggers) = self.by_group.get(&group) {
+            stack.extend(triggers.iter().rev());
+        }
+    }
+
+    // turning a group on (like a count trigger with "activate group" does) also spawns it
+    fn activate(&mut self, target: ID, activate: bool, stack: &mut Vec<usize>) {
+        self.toggle(target, activate);
+        if activate {
+            self.spawn(target, stack)
+        }
+    }
+
+    fn toggle(&mut self, group: ID, on: bool) {
+        if on {
+            self.disabled.remove(&group);
+        } else {
+            self.disabled.insert(group);
+        }
+        self.record(Change::Toggle {
+            group: Group { id: group },
+            on,
+        });
+    }
+
+    fn execute(&mut self, mut stack: Vec<usize>) -> Result<(), SimulationError> {
+        let mut activations = 0;
+        while let Some(index) = stack.pop() {
+            let trigger = &self.triggers[index];
+            if trigger.groups.iter().any(|g| self.disabled.contains(g)) {
+                continue;
+            }
+            activations += 1;
+            if activations > MAX_ACTIVATIONS {
+                return Err(SimulationError::InfiniteLoop { time: self.time });
+            }
+            let action = trigger.action;
+            self.record(Change::Trigger(trigger.obj));
+
+            match action {
+                Action::Spawn { target, delay } => {
+                    if delay > 0.0 {
+                        self.push(self.time + delay, Event::Spawn(target));
+                    } else {
+                        self.spawn(target, &mut stack);
+                    }
+                }
+                Action::Toggle { target, on } => self.toggle(target, on),
+                Action::Pickup { item, count } => {
+                    let value = self.items.get(&item).copied().unwrap_or(0) + count;
+                    self.items.insert(item, value);
+                    self.record(Change::Item {
+                        item: Item { id: item },
+                        value,
+                    });
+                    self.item_changed(item, value, &mut stack);
+                }
+                Action::InstantCount {
+                    item,
+                    count,
+                    comparison,
+                    target,
+                    activate,
+                } => {
+                    let value = self.items.get(&item).copied().unwrap_or(0);
+                    let passed = match comparison {
+                        Comparison::Equal => value == count,
+                        Comparison::Larger => value > count,
+                        Comparison::Smaller => value < count,
+                    };
+                    if passed {
+                        self.activate(target, activate, &mut stack);
+                    }
+                }
+                Action::Count { .. }
+                | Action::Collision { .. }
+                | Action::Touch { .. }
+                | Action::Death { .. } => {
+                    if !self.listening.contains(&index) {
+                        self.listening.push(index);
+                    }
+                }
+                Action::Other => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn item_changed(&mut self, item: ID, value: i32, stack: &mut Vec<usize>) {
+        for i in 0..self.listening.len() {
+            let index = self.listening[i];
+            if let Action::Count {
+                item: counted,
+                count,
+                target,
+                activate,
+                multi,
+            } = self.triggers[index].action
+            {
+                if counted == item && count == value && !self.fired.contains(&index) {
+                    if !multi {
+                        self.fired.insert(index);
+                    }
+                    self.activate(target, activate, stack);
+                }
+            }
+        }
+    }
+
+    fn input(&mut self, input: Input, stack: &mut Vec<usize>) {
+        for i in 0..self.listening.len() {
+            let index = self.listening[i];
+            match (self.triggers[index].action, input) {
+                (
+                    Action::Collision {
+                        a,
+                        b,
+                        on_exit,
+                        target,
+                        activate,
+                    },
+                    Input::Collision(x, y),
+                )
+                | (
+                    Action::Collision {
+                        a,
+                        b,
+                        on_exit,
+                        target,
+                        activate,
+                    },
+                    Input::CollisionExit(x, y),
+                ) => {
+                    let exit = matches!(input, Input::CollisionExit(..));
+                    let blocks_match = (a, b) == (x.id, y.id) || (a, b) == (y.id, x.id);
+                    if blocks_match && on_exit == exit {
+                        self.activate(target, activate, stack);
+                    }
+                }
+                (
+                    Action::Touch {
+                        mode, dual, target, ..
+                    },
+                    Input::Touch { dual_side },
+                ) if dual_side || !dual => match mode {
+                    TouchMode::Spawn => self.spawn(target, stack),
+                    TouchMode::On => self.activate(target, true, stack),
+                    TouchMode::Off => self.toggle(target, false),
+                },
+                (
+                    Action::Touch {
+                        mode,
+                        hold,
+                        dual,
+                        target,
+                    },
+                    Input::TouchEnd { dual_side },
+                ) if hold && (dual_side || !dual) => match mode {
+                    // the group is toggled back
+                    TouchMode::Off => self.activate(target, true, stack),
+                    TouchMode::On | TouchMode::Spawn => self.toggle(target, false),
+                },
+                (Action::Death { target, activate }, Input::Death) => {
+                    self.activate(target, activate, stack)
+                }
+                _ => (),
+            }
+        }
+    }
+}
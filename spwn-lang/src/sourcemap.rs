@@ -0,0 +1,96 @@
+//! Maps the objects added to a level back to the SPWN source that made them
+
+use crate::builtin::{Group, SpecificID, ID};
+use crate::levelstring::{LevelString, SourceLocation};
+use crate::parser::FileRange;
+
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct SourceMapEntry {
+    /// Index of the object in the level
+    pub index: usize,
+    pub obj_id: Option<u16>,
+    /// Final groups of the object, not counting the signature group of the script
+    pub groups: Vec<SpecificID>,
+    pub source: SourceLocation,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub objects: Vec<SourceMapEntry>,
+}
+
+fn location_json(file: &Path, pos: FileRange) -> JsonValue {
+    json!({
+        "file": file.to_string_lossy(),
+        "start": { "line": pos.0 .0, "column": pos.0 .1 + 1 },
+        "end": { "line": pos.1 .0, "column": pos.1 .1 + 1 },
+    })
+}
+
+impl SourceMap {
+    /// Source locations of every object in the level that was made by SPWN, which are
+    /// marked with `signature`. The IDs in the level should already be resolved by
+    /// `append_objects`.
+    pub fn new(level: &LevelString, signature: Group) -> Self {
+        let signature = match signature.id {
+            ID::Specific(n) => n,
+            _ => unreachable!(),
+        };
+        let objects = level
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, obj)| {
+                Some(SourceMapEntry {
+                    index,
+                    obj_id: obj.obj_id(),
+                    groups: obj
+                        .groups()
+                        .into_iter()
+                        .filter(|g| *g != signature)
+                        .collect(),
+                    source: obj.source.clone()?,
+                })
+            })
+            .collect();
+        SourceMap { objects }
+    }
+
+    /// Indexes of the objects in every group
+    pub fn groups(&self) -> BTreeMap<SpecificID, Vec<usize>> {
+        let mut groups = BTreeMap::<SpecificID, Vec<usize>>::new();
+        for entry in &self.objects {
+            for group in &entry.groups {
+                groups.entry(*group).or_default().push(entry.index);
+            }
+        }
+        groups
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "objects": self.objects.iter().map(|e| {
+                let mut location = location_json(&e.source.file, e.source.pos);
+                location["index"] = json!(e.index);
+                location["obj_id"] = json!(e.obj_id);
+                location["groups"] = json!(e.groups);
+                location["call_stack"] = e
+                    .source
+                    .call_stack
+                    .iter()
+                    .map(|(file, pos)| location_json(file, *pos))
+                    .collect();
+                location
+            }).collect::<Vec<_>>(),
+            "groups": self
+                .groups()
+                .into_iter()
+                .map(|(g, objects)| (g.to_string(), json!(objects)))
+                .collect::<serde_json::Map<_, _>>(),
+        })
+    }
+}
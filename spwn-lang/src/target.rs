@@ -0,0 +1,175 @@
+//! Versions of Geometry Dash that levels can be built for
+//!
+//! The target is chosen with `--target 2.2` or `#[target("2.2")]` at the top of the main
+//! file, and is 2.1 by default. It decides how many IDs a level can use, which triggers
+//! scripts can add and which of them the optimizer has to treat like spawn triggers.
+
+use crate::builtin::SpecificID;
+use crate::levelstring::ObjParam;
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TargetProfile {
+    pub name: &'static str,
+    /// Highest group, color, block ID and item ID in the version
+    pub id_limits: [SpecificID; 4],
+    /// Triggers in the version, as lists of object IDs and names
+    pub triggers: &'static [&'static [(u16, &'static str)]],
+    /// Triggers that activate the group they target, like spawn triggers do
+    pub func_triggers: &'static [u16],
+    /// Highest object property key, if it is known
+    pub max_property: Option<u16>,
+}
+
+// color triggers for the fixed color channels
+const TRIGGERS_1_9: &[(u16, &str)] = &[
+    (29, "BG color"),
+    (30, "ground color"),
+    (104, "line color"),
+    (105, "object color"),
+    (221, "color 1"),
+    (717, "color 2"),
+    (718, "color 3"),
+    (743, "color 4"),
+    (744, "3DL color"),
+    (900, "ground 2 color"),
+    (915, "line 2 color"),
+];
+
+const TRIGGERS_2_1: &[(u16, &str)] = &[
+    (32, "enable trail"),
+    (33, "disable trail"),
+    (899, "color"),
+    (901, "move"),
+    (1006, "pulse"),
+    (1007, "alpha"),
+    (1049, "toggle"),
+    (1268, "spawn"),
+    (1346, "rotate"),
+    (1347, "follow"),
+    (1520, "shake"),
+    (1585, "animate"),
+    (1595, "touch"),
+    (1611, "count"),
+    (1612, "hide player"),
+    (1613, "show player"),
+    (1616, "stop"),
+    (1811, "instant count"),
+    (1812, "on death"),
+    (1814, "follow player Y"),
+    (1815, "collision"),
+    (1817, "pickup"),
+    (1818, "BG effect on"),
+    (1819, "BG effect off"),
+];
+
+const TRIGGERS_2_2: &[(u16, &str)] = &[
+    (1912, "random"),
+    (1913, "camera zoom"),
+    (1914, "static camera"),
+    (1916, "camera offset"),
+    (1934, "song"),
+    (2066, "gravity"),
+    (2068, "advanced random"),
+    (3600, "end"),
+    (3604, "event"),
+    (3607, "sequence"),
+    (3609, "instant collision"),
+    (3614, "time"),
+    (3615, "time event"),
+    (3617, "time control"),
+    (3618, "reset group"),
+    (3619, "item edit"),
+    (3620, "item compare"),
+    (3641, "persistent item"),
+];
+
+pub const GD_1_9: TargetProfile = TargetProfile {
+    name: "1.9",
+    // groups, numbered colors, block IDs and item IDs came with 2.0
+    id_limits: [0, 0, 0, 0],
+    triggers: &[TRIGGERS_1_9],
+    func_triggers: &[],
+    max_property: Some(108),
+};
+
+pub const GD_2_1: TargetProfile = TargetProfile {
+    name: "2.1",
+    id_limits: [999, 999, 999, 999],
+    triggers: &[TRIGGERS_1_9, TRIGGERS_2_1],
+    func_triggers: &[1268, 1595, 1611, 1811, 1812, 1815],
+    max_property: Some(108),
+};
+
+pub const GD_2_2: TargetProfile = TargetProfile {
+    name: "2.2",
+    id_limits: [9999, 999, 9999, 9999],
+    triggers: &[TRIGGERS_1_9, TRIGGERS_2_1, TRIGGERS_2_2],
+    func_triggers: &[
+        1268, 1595, 1611, 1811, 1812, 1815, 1912, 2068, 3604, 3607, 3609, 3615, 3620,
+    ],
+    max_property: None,
+};
+
+pub const TARGETS: [&TargetProfile; 3] = [&GD_1_9, &GD_2_1, &GD_2_2];
+
+impl TargetProfile {
+    pub fn from_name(name: &str) -> Result<&'static TargetProfile, String> {
+        TARGETS
+            .iter()
+            .copied()
+            .find(|t| t.name == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown target \"{}\", expected one of {}",
+                    name,
+                    TARGETS
+                        .iter()
+                        .map(|t| t.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+
+    /// Name of a trigger in any version
+    pub fn trigger_name(obj_id: u16) -> Option<&'static str> {
+        let mut known = TARGETS
+            .iter()
+            .flat_map(|t| t.triggers.iter())
+            .flat_map(|l| l.iter());
+        known.find(|(id, _)| *id == obj_id).map(|(_, name)| *name)
+    }
+
+    pub fn has_trigger(&self, obj_id: u16) -> bool {
+        self.triggers
+            .iter()
+            .any(|l| l.iter().any(|(id, _)| *id == obj_id))
+    }
+
+    /// Checks that an object only uses triggers and properties that exist in this version
+    pub fn check_object(&self, params: &BTreeMap<u16, ObjParam>) -> Result<(), String> {
+        if let Some(ObjParam::Number(id)) = params.get(&1) {
+            let id = *id as u16;
+            if !self.has_trigger(id) {
+                // triggers of other versions
+                if let Some(name) = TargetProfile::trigger_name(id) {
+                    return Err(format!(
+                        "The {} trigger (object ID {}) doesn't exist in GD {}",
+                        name, id, self.name
+                    ));
+                }
+            }
+        }
+        if let Some(max) = self.max_property {
+            if let Some(key) = params.keys().find(|k| **k > max) {
+                return Err(format!(
+                    "Object property {} doesn't exist in GD {}",
+                    key, self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
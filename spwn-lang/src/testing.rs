@@ -0,0 +1,217 @@
+//! Runs the macros tagged with `#[test]` in SPWN scripts
+//!
+//! Tests are macros defined in the main scope, like `name = #[test] () { ... }`. Every
+//! test compiles the whole script again in a fresh `Globals`, with a call to the test
+//! macro at the end. A test fails if compiling or calling it gives an error (like a `throw`
+//! statement or a failed `$.assert`). If the tag has an argument, like
+//! `#[test("expected.txt")]`, the objects generated by the test are also compared to the
+//! objects in that file (relative to the script).
+
+use crate::ast::{self, Statement, StatementBody, ValueBody};
+use crate::compiler;
+use crate::compiler_types::Globals;
+use crate::diagnostic::Diagnostic;
+use crate::levelstring::{self, LevelString};
+use crate::parser::{self, FileRange};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    /// Errors that made the test fail, empty if it passed
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+/// Macros tagged with `#[test]` that are defined in the main scope, with the
+/// statement that defines them
+pub fn find_tests(statements: &[Statement]) -> Vec<(String, ast::Tag, FileRange)> {
+    let mut tests = Vec::new();
+    for statement in statements {
+        let e = match &statement.body {
+            StatementBody::Expr(e) => e,
+            _ => continue,
+        };
+        // name = #[test] () { ... }
+        if let ([var, val], [ast::Operator::Assign]) = (&e.values[..], &e.operators[..]) {
+            if let (ValueBody::Symbol(name), ValueBody::Macro(m)) =
+                (&var.value.body, &val.value.body)
+            {
+                if var.path.is_empty() && m.properties.get("test").is_some() {
+                    tests.push((name.clone(), m.properties.clone(), statement.pos));
+                }
+            }
+        }
+    }
+    tests
+}
+
+/// All SPWN files in a directory and its subdirectories, or the file itself
+pub fn collect_scripts(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut out = Vec::new();
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        if entry.is_dir() {
+            out.extend(collect_scripts(&entry)?);
+        } else if entry.extension().map(|e| e == "spwn").unwrap_or(false) {
+            out.push(entry);
+        }
+    }
+    Ok(out)
+}
+
+/// Runs every test in a script. Returns the errors of the script itself if it
+/// can't be parsed or compiled.
+pub fn run_tests(
+    path: &Path,
+    included_paths: Vec<PathBuf>,
+) -> Result<Vec<TestResult>, Vec<Diagnostic>> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        vec![Diagnostic::error(
+            "io_error",
+            format!("Could not read {}: {}", path.to_string_lossy(), e),
+        )]
+    })?;
+
+    let (statements, notes) = parser::parse_spwn_recovering(source, path.to_path_buf());
+    if !notes.errors.is_empty() {
+        return Err(notes.errors.iter().map(Diagnostic::from).collect());
+    }
+
+    let mut results = Vec::new();
+    for (name, tag, pos) in find_tests(&statements) {
+        // call the test at the end of the script
+        let mut test_statements = statements.clone();
+        test_statements.push(Statement {
+            body: StatementBody::Expr(ast::Expression {
+                values: vec![ast::Variable {
+                    operator: None,
+                    value: ast::ValueLiteral::new(ValueBody::Symbol(name.clone())),
+                    path: vec![ast::Path::Call(Vec::new())],
+                    pos,
+                    comment: (None, None),
+                    tag: ast::Tag::new(),
+                }],
+                operators: Vec::new(),
+            }),
+            arrow: false,
+            pos,
+            comment: (None, None),
+        });
+        let test_notes = parser::ParseNotes {
+            tag: notes.tag.clone(),
+            top_comment: None,
+            file: notes.file.clone(),
+            errors: Vec::new(),
+        };
+
+        let diagnostics = match compiler::compile_spwn(
+            test_statements,
+            path.to_path_buf(),
+            included_paths.clone(),
+            test_notes,
+            None,
+        ) {
+            Ok(globals) => match expected_output(&tag, path) {
+                Some(expected) => compare_output(&globals, &expected)
+                    .err()
+                    .into_iter()
+                    .collect(),
+                None => Vec::new(),
+            },
+            Err(e) => vec![Diagnostic::from(&e)],
+        };
+        results.push(TestResult { name, diagnostics });
+    }
+    Ok(results)
+}
+
+// path of the expected objects, from the first argument of the tag
+fn expected_output(tag: &ast::Tag, script: &Path) -> Option<PathBuf> {
+    let args = tag.get("test")?;
+    match &args.first()?.value.values.first()?.value.body {
+        ValueBody::Str(s) => Some(script.parent().unwrap_or_else(|| Path::new("")).join(s)),
+        _ => None,
+    }
+}
+
+fn compare_output(globals: &Globals, expected: &Path) -> Result<(), Diagnostic> {
+    let mismatch = |message: String| Diagnostic::error("test_output_mismatch", message);
+
+    let expected_str = fs::read_to_string(expected).map_err(|e| {
+        mismatch(format!(
+            "Could not read expected objects from {}: {}",
+            expected.to_string_lossy(),
+            e
+        ))
+    })?;
+
+    let mut objects = levelstring::apply_fn_ids(&globals.func_ids);
+    levelstring::apply_sync_groups(&mut objects, &globals.sync_groups);
+    objects.extend(globals.objects.clone());
+    let generated = crate::add_to_level_with(
+        objects,
+        String::new(),
+        &globals.id_config,
+        globals.target,
+        None,
+        false,
+    )
+    .map_err(mismatch)?
+    .level_string;
+
+    let sorted_objects = |ls: &str| -> Result<Vec<String>, Diagnostic> {
+        let mut objects: Vec<String> = LevelString::parse(ls)
+            .map_err(mismatch)?
+            .objects
+            .iter()
+            .map(|o| o.to_string())
+            .collect();
+        objects.sort();
+        Ok(objects)
+    };
+    let mut missing = sorted_objects(expected_str.trim())?;
+    let mut unexpected = Vec::new();
+    for obj in sorted_objects(&generated)? {
+        match missing.iter().position(|o| *o == obj) {
+            Some(i) => {
+                missing.remove(i);
+            }
+            None => unexpected.push(obj),
+        }
+    }
+
+    if missing.is_empty() && unexpected.is_empty() {
+        return Ok(());
+    }
+    let mut diagnostic = mismatch(format!(
+        "Generated objects don't match {} ({} missing, {} unexpected)",
+        expected.to_string_lossy(),
+        missing.len(),
+        unexpected.len()
+    ));
+    for obj in missing.iter().take(5) {
+        diagnostic = diagnostic.with_note(format!("missing: {}", obj));
+    }
+    for obj in unexpected.iter().take(5) {
+        diagnostic = diagnostic.with_note(format!("unexpected: {}", obj));
+    }
+    Err(diagnostic)
+}
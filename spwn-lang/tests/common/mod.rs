@@ -0,0 +1,16 @@
+// helpers shared by the tests
+
+/// Runs `f` on a thread with as much stack as the main thread gets. Compiling a script
+/// (with the standard library) needs more stack than test threads have.
+pub fn with_stack<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    std::thread::Builder::new()
+        .stack_size(8 * 1024 * 1024)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap()
+}
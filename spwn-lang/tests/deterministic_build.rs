@@ -0,0 +1,52 @@
+// the same script and level should always give the same level string, byte for byte
+
+mod common;
+
+use common::with_stack;
+use spwn::Compiler;
+
+const SCRIPT: &str = "
+groups = {a: ?g, b: ?g, c: ?g, d: ?g, e: ?g}
+items = {x: counter(), y: counter(), z: counter()}
+for pair in groups {
+    -> pair[1].move(10, 0)
+}
+for pair in items {
+    pair[1] += 3
+}
+f = !{
+    items.x += 1
+    groups.a.toggle_on()
+    wait(0.5)
+    groups.b.toggle_off()
+}
+f!
+wait(1)
+f!
+";
+
+// some groups are already used, and the last object is from an earlier build
+const LEVEL: &str = concat!(
+    "kS38,1_40_2_125_3_255_11_255_12_255_13_255_4_-1_6_1000_7_1_15_1_18_0_8_1|,kA13,0;",
+    "1,1,2,15,3,15,57,3.5;1,1,2,45,3,15,57,12;1,901,2,75,3,15,51,3,57,1001,108,1;",
+);
+
+fn build() -> String {
+    let output = Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/deterministic.spwn"))
+        .source(SCRIPT)
+        .include_path(env!("CARGO_MANIFEST_DIR"))
+        .level_string(LEVEL)
+        .compile();
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+    output.level_string
+}
+
+#[test]
+fn builds_are_byte_identical() {
+    with_stack(|| {
+        let first = build();
+        for _ in 0..10 {
+            assert_eq!(build(), first);
+        }
+    });
+}
@@ -0,0 +1,122 @@
+// `spwn fmt` keeps every comment, and formatting formatted code changes nothing
+
+use spwn::fmt::comments;
+use spwn::parser::parse_spwn;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn format(source: &str) -> String {
+    let (statements, notes) = parse_spwn(source.to_string(), PathBuf::from("t.spwn"))
+        .unwrap_or_else(|e| panic!("{:?} in\n{}", e, source));
+    spwn::fmt::format(&statements, &notes)
+}
+
+fn check(source: &str) {
+    let formatted = format(source);
+    assert_eq!(
+        comments(&formatted),
+        comments(source),
+        "comments changed:\n{}",
+        formatted
+    );
+    assert_eq!(format(&formatted), formatted, "not idempotent");
+}
+
+#[test]
+fn keeps_block_comments() {
+    let cases = [
+        "a = 1\n/* block\n comment */\nb = 2\n",
+        "a = 1 /* after */\nb = 2\n",
+        "/* before */ a = 1\n",
+        "a = 1\n/* a /* nested */ b */\nb = 2\n",
+        "a = [1, /* x */ 2]\n",
+        "f = () {\n    /* in a block */\n    a = 1\n}\n",
+        "/* at the end */\n",
+    ];
+    for case in &cases {
+        assert_eq!(&format(case), case);
+    }
+}
+
+#[test]
+fn block_comments_keep_their_text() {
+    // the lines in a block comment aren't reindented
+    let source = "if true {\n    a = 1\n    /*\n      indented\n        more\n    */\n}\n";
+    assert_eq!(format(source), source);
+}
+
+#[test]
+fn keeps_comments_above_the_file_tag() {
+    let source = "// about this file\n/* more */\n#[no_std]\n\na = 1\n";
+    assert_eq!(format(source), source);
+
+    // without a tag they belong to the first statement
+    let source = "// about this file\na = 1\n";
+    assert_eq!(format(source), source);
+}
+
+#[test]
+fn comments_round_trip() {
+    check("// a\na = 1 // b\n// c\n\n/* d */\nb = {\n    // e\n    x: 1, // f\n    y: 2,\n}\n");
+    check("f = (a, b) {\n    /* sum */ return a + b // of both\n}\n");
+    check("#[no_std] // tag\n\nx = 1 /* one */ + 2 /* two */\n");
+    check("if a {\n    b = 1\n\n    // end of the block\n} else {\n    c = 2 /* end */\n}\n");
+}
+
+fn spwn_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            spwn_files(&path, files);
+        } else if path.extension().and_then(|e| e.to_str()) == Some("spwn") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = Vec::new();
+    spwn_files(&root.join("libraries"), &mut files);
+    spwn_files(&root.join("test"), &mut files);
+    assert!(!files.is_empty());
+
+    for file in files {
+        let source = std::fs::read_to_string(&file).unwrap();
+        if parse_spwn(source.clone(), file.clone()).is_err() {
+            continue;
+        }
+        let formatted = format(&source);
+        assert_eq!(format(&formatted), formatted, "{}", file.display());
+        assert_eq!(
+            comments(&formatted),
+            comments(&source),
+            "{}",
+            file.display()
+        );
+    }
+}
+
+#[test]
+fn fmt_leaves_files_it_would_remove_comments_from() {
+    let dir = std::env::temp_dir().join(format!("spwn-fmt-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lossy = dir.join("lossy.spwn");
+    let fine = dir.join("fine.spwn");
+    let lossy_source = "if true {\n    // nothing yet\n}\n";
+    std::fs::write(&lossy, lossy_source).unwrap();
+    std::fs::write(&fine, "a   =   1 /* one */\n").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_spwn"))
+        .arg("fmt")
+        .arg(&lossy)
+        .arg(&fine)
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    assert_eq!(std::fs::read_to_string(&lossy).unwrap(), lossy_source);
+    assert_eq!(std::fs::read_to_string(&fine).unwrap(), "a = 1 /* one */\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
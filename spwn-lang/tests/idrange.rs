@@ -0,0 +1,172 @@
+// which specific IDs arbitrary IDs are given, with reserved ranges and module ranges
+
+mod common;
+
+use common::with_stack;
+use spwn::idrange::{IdConfig, ModuleIds};
+use spwn::levelstring::{GDObj, LevelString};
+use spwn::{CompileOutput, Compiler};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+const GROUPS: usize = 0;
+
+fn module(path: &str, range: std::ops::Range<u16>) -> ModuleIds {
+    ModuleIds {
+        name: "module".to_string(),
+        path: PathBuf::from(path),
+        ranges: [Some(range), None, None, None],
+    }
+}
+
+#[test]
+fn reserved_ranges_are_never_allocated() {
+    let mut config = IdConfig::default();
+    config.reserve(GROUPS, 1..10);
+    config.reserve(GROUPS, 20..30);
+    let mut closed = HashSet::new();
+    assert_eq!(config.allocate(GROUPS, 1, &closed, 999), Ok(10));
+    closed.extend(10..20);
+    assert_eq!(config.allocate(GROUPS, 1, &closed, 999), Ok(30));
+    // other ID classes are not affected
+    assert_eq!(config.allocate(1, 1, &HashSet::new(), 999), Ok(1));
+}
+
+#[test]
+fn modules_only_get_ids_from_their_range() {
+    let mut config = IdConfig::default();
+    config.add_module(module("/lib/module", 500..600));
+    config.claim(GROUPS, 1, Path::new("/lib/module/lib.spwn"));
+    config.claim(GROUPS, 2, Path::new("/main.spwn"));
+
+    assert_eq!(config.allocate(GROUPS, 1, &HashSet::new(), 999), Ok(500));
+    assert_eq!(config.allocate(GROUPS, 2, &HashSet::new(), 999), Ok(1));
+    // IDs made outside of the module skip its range
+    let closed = (1..500).collect();
+    assert_eq!(config.allocate(GROUPS, 2, &closed, 999), Ok(600));
+}
+
+#[test]
+fn exhausted_ranges_are_errors() {
+    let mut config = IdConfig::default();
+    config.add_module(module("/lib/module", 500..502));
+    config.claim(GROUPS, 1, Path::new("/lib/module/lib.spwn"));
+    let closed = [500, 501].iter().copied().collect();
+    let err = config.allocate(GROUPS, 1, &closed, 999).unwrap_err();
+    assert!(err.contains("\"module\" ran out of groups"), "{}", err);
+
+    let closed = (1..=999).collect();
+    let err = config.allocate(GROUPS, 2, &closed, 999).unwrap_err();
+    assert_eq!(err, "This level exceeds the group limit!");
+}
+
+fn build(source: &'static str) -> CompileOutput {
+    with_stack(move || {
+        Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/idrange.spwn"))
+            .source(source)
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .compile()
+    })
+}
+
+// the targets of the move triggers of a level
+fn moved_groups(output: &CompileOutput) -> Vec<u16> {
+    let level = LevelString::parse(&output.level_string).unwrap();
+    let param = |o: &GDObj, key: u16| o.params.get(&key).map(|p| p.to_string());
+    level
+        .objects
+        .iter()
+        .filter(|o| param(o, 1).as_deref() == Some("901"))
+        .map(|o| param(o, 51).unwrap().parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn reserve_tag_keeps_ids_free() {
+    let output = build(
+        "
+#[reserve(groups = 1..100)]
+a = ?g
+b = ?g
+a.move(10, 0)
+b.move(10, 0)
+",
+    );
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+    let groups = moved_groups(&output);
+    assert_eq!(groups.len(), 2);
+    assert!(groups.iter().all(|g| *g >= 100), "{:?}", groups);
+
+    let output = build("#[reserve(groups = 5)]\na = 1\n");
+    assert!(!output.is_ok());
+    let message = &output.diagnostics[0].message;
+    assert!(
+        message.contains("Expected a range of numbers"),
+        "{}",
+        message
+    );
+}
+
+// a script that imports a module with its own group range, written to a temporary folder
+fn build_with_module(name: &str, module: &str, main: &str) -> CompileOutput {
+    let dir = std::env::temp_dir().join(format!("spwn-idrange-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(format!("{}.spwn", name)), module).unwrap();
+    let source = format!("module = import \"{}.spwn\"\n{}", name, main);
+    let main = dir.join("main.spwn");
+    let output = with_stack(move || {
+        Compiler::new(main)
+            .source(source)
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .compile()
+    });
+    std::fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn id_range_tag_keeps_module_ids_in_its_range() {
+    let output = build_with_module(
+        "move_new",
+        "
+#[id_range(groups = 500..510)]
+
+return !{
+    g = ?g
+    g.move(10, 0)
+}
+",
+        "
+a = ?g
+a.move(10, 0)
+module!
+",
+    );
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+    let groups = moved_groups(&output);
+    assert_eq!(groups.len(), 2);
+    assert!(
+        groups.iter().any(|g| (500..510).contains(g)),
+        "{:?}",
+        groups
+    );
+    assert!(groups.iter().any(|g| *g < 500), "{:?}", groups);
+
+    let output = build_with_module(
+        "small",
+        "
+#[id_range(groups = 500..502)]
+
+a = ?g
+b = ?g
+c = ?g
+a.move(10, 0)
+b.move(10, 0)
+c.move(10, 0)
+",
+        "",
+    );
+    assert!(!output.is_ok());
+    let message = &output.diagnostics[0].message;
+    assert!(message.contains("ran out of groups"), "{}", message);
+}
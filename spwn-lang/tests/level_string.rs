@@ -0,0 +1,88 @@
+// level strings are written back exactly as they were read, unless something changed
+
+mod common;
+
+use common::with_stack;
+use spwn::levelstring::{LevelString, ObjParam};
+use spwn::Compiler;
+
+// what the editor saves, with settings for colors and a few kinds of objects
+const HEADER: &str = concat!(
+    "kS38,1_40_2_125_3_255_11_255_12_255_13_255_4_-1_6_1000_7_1_15_1_18_0_8_1|",
+    "1_0_2_102_3_255_11_255_12_255_13_255_4_-1_6_1001_7_1_15_1_18_0_8_1|,",
+    "kA13,0,kA15,0,kA16,0,kA14,,kA6,0,kA7,0,kA17,0,kA18,0,kS39,0,kA2,0,kA3,0;",
+);
+const OBJECTS: &str = concat!(
+    "1,1,2,15,3,15;",
+    "1,1,3,15,2,15;",
+    "1,8,2,45,3,15,2,60;",
+    "1,1,2,75,3,15,;",
+    "1,914,2,105,3,45,31,SGVsbG8gd29ybGQ=,32,0.5;",
+    "1,901,2,135,3,15,51,3,57,20.21,28,10,29,0,10,0.50,30,0,85,2,36,1;",
+    "1,1268,2,165,3,15,51,4,63,0,57,22,62,1,87,1,36,1;",
+);
+
+fn round_trip(ls: &str) {
+    let level = LevelString::parse(ls).unwrap();
+    assert_eq!(level.to_string(), ls);
+}
+
+#[test]
+fn keeps_level_strings_as_they_are() {
+    round_trip(&format!("{}{}", HEADER, OBJECTS));
+    round_trip(OBJECTS);
+    round_trip(HEADER);
+    round_trip("");
+    // trailing comma after the settings
+    round_trip("kS38,1_40|,kA13,0,;1,1,2,15,3,15;");
+}
+
+#[test]
+fn keeps_key_order_duplicates_and_trailing_commas() {
+    for obj in [
+        "1,1,3,15,2,15;",
+        "1,1,2,15,2,30;",
+        "1,1,3,15,;",
+        "2,15,1,1,2,30,;",
+    ] {
+        round_trip(obj);
+    }
+    // the last value of a duplicate key is the one that counts
+    let level = LevelString::parse("1,1,2,15,2,30;").unwrap();
+    assert_eq!(level.objects[0].x(), Some(30.0));
+}
+
+#[test]
+fn changed_objects_are_written_again() {
+    let mut level = LevelString::parse("1,1,3,15,2,15,;1,1,3,30,2,30;").unwrap();
+    level.objects[0]
+        .params
+        .insert(57, ObjParam::Text("5".to_string()));
+    level.objects.push(level.objects[1].clone());
+    level.header.push(("kA13".to_string(), "0".to_string()));
+    assert_eq!(
+        level.to_string(),
+        "kA13,0;1,1,2,15,3,15,57,5;1,1,3,30,2,30;1,1,3,30,2,30;"
+    );
+}
+
+#[test]
+fn building_keeps_the_objects_in_the_level() {
+    let level = format!("{}{}", HEADER, OBJECTS);
+    let output = with_stack(move || {
+        Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/level.spwn"))
+            .source("10g.move(10, 0)\n")
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .level_string(&level)
+            .compile()
+    });
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+    assert!(
+        output
+            .level_string
+            .starts_with(&format!("{}{}", HEADER, OBJECTS)),
+        "{}",
+        output.level_string
+    );
+    assert!(output.level_string.len() > HEADER.len() + OBJECTS.len());
+}
@@ -0,0 +1,94 @@
+// spwn.lock keeps the IDs of `?g` and friends when the script is edited
+
+mod common;
+
+use common::with_stack;
+use spwn::lockfile::IdLock;
+use spwn::Compiler;
+
+fn build(source: &str, lock: IdLock) -> IdLock {
+    let source = source.to_string();
+    with_stack(move || {
+        let output = Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/lock.spwn"))
+            .source(format!("extract obj_props\n{}", source))
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .optimize(false)
+            .id_lock(lock)
+            .compile();
+        assert!(output.is_ok(), "{:?}", output.diagnostics);
+        output.id_lock.unwrap()
+    })
+}
+
+fn groups(lock: &IdLock) -> Vec<(&str, u16)> {
+    lock.ids[0].iter().map(|(k, v)| (k.as_str(), *v)).collect()
+}
+
+const SCRIPT: &str = "a = ?g
+f = () {
+    b = ?g
+    return b
+}
+x = f()
+y = f()
+$.add(obj {OBJ_ID: 1, X: 15, GROUPS: [a, x, y]})
+$.add(obj {OBJ_ID: 1, X: 45, GROUPS: ?g})
+$.add(obj {OBJ_ID: 1, X: 75, GROUPS: ?g})
+";
+
+#[test]
+fn names_come_from_variables_and_statements() {
+    let lock = build(SCRIPT, IdLock::default());
+    assert_eq!(
+        groups(&lock),
+        vec![
+            ("`$.add(obj {OBJ_ID: 1, X: 45, GROUPS: ?g}`#0", 4),
+            ("`$.add(obj {OBJ_ID: 1, X: 75, GROUPS: ?g}`#0", 5),
+            ("a#0", 1),
+            ("x.b#0", 2),
+            ("y.b#0", 3),
+        ]
+    );
+
+    // IDs made by imported files are named after the variable in the main file too
+    let lock = build("c = counter()\nc += 1\n", IdLock::default());
+    assert_eq!(lock.ids[3].keys().collect::<Vec<_>>(), vec!["c.id#0"]);
+}
+
+#[test]
+fn rebuilding_keeps_the_ids() {
+    let lock = build(SCRIPT, IdLock::default());
+    assert_eq!(build(SCRIPT, lock.clone()), lock);
+}
+
+#[test]
+fn new_groups_above_dont_move_the_others() {
+    let lock = build(SCRIPT, IdLock::default());
+    // new groups at the top, another `b = ?g` in a different scope and extra lines
+    let edited = format!(
+        "new = ?g\n\n\ng = () {{\n    b = ?g\n    return b\n}}\nz = g()\n$.add(obj {{OBJ_ID: 1, X: 0, GROUPS: ?g}})\n{}$.add(obj {{OBJ_ID: 1, X: 105, GROUPS: [new, z]}})\n",
+        SCRIPT
+    );
+    let edited_lock = build(&edited, lock.clone());
+    for (name, id) in groups(&lock) {
+        assert_eq!(edited_lock.ids[0].get(name), Some(&id), "{} moved", name);
+    }
+    // the new ones get IDs that aren't used yet
+    for name in &[
+        "new#0",
+        "z.b#0",
+        "`$.add(obj {OBJ_ID: 1, X: 0, GROUPS: ?g})`#0",
+    ] {
+        assert!(edited_lock.ids[0][*name] > 5, "{} reuses an ID", name);
+    }
+}
+
+#[test]
+fn removed_groups_leave_the_lock() {
+    let lock = build(SCRIPT, IdLock::default());
+    let edited = SCRIPT.replace("$.add(obj {OBJ_ID: 1, X: 45, GROUPS: ?g})\n", "");
+    let edited_lock = build(&edited, lock.clone());
+    let mut expected = groups(&lock);
+    expected.remove(0);
+    assert_eq!(groups(&edited_lock), expected);
+}
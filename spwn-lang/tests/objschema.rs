@@ -0,0 +1,146 @@
+// the kinds of values object properties take, checked when objects are made or edited
+
+mod common;
+
+use common::with_stack;
+use spwn::builtin::{Color, Group, ID};
+use spwn::levelstring::ObjParam;
+use spwn::objschema::check_prop;
+use spwn::{CompileOutput, Compiler};
+
+fn build(source: &'static str) -> CompileOutput {
+    with_stack(move || {
+        Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/objschema.spwn"))
+            .source(source)
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .compile()
+    })
+}
+
+fn error(source: &'static str) -> String {
+    let output = build(source);
+    assert!(!output.is_ok(), "{}", source);
+    output.diagnostics[0].message.clone()
+}
+
+fn group(id: u16) -> ObjParam {
+    ObjParam::Group(Group {
+        id: ID::Specific(id),
+    })
+}
+
+fn color(id: u16) -> ObjParam {
+    ObjParam::Color(Color {
+        id: ID::Specific(id),
+    })
+}
+
+#[test]
+fn wrong_kinds_are_rejected() {
+    assert_eq!(
+        check_prop(Some(1), 2, &group(10)),
+        Err("X expects @number, found @group".to_string())
+    );
+    // TARGET takes groups and colors, but spawn triggers can only target groups
+    assert_eq!(check_prop(None, 51, &color(1)), Ok(()));
+    assert_eq!(
+        check_prop(Some(1268), 51, &color(1)),
+        Err("TARGET expects @group, found @color".to_string())
+    );
+    let err = check_prop(Some(1268), 77, &ObjParam::Number(1.0)).unwrap_err();
+    assert!(err.contains("has no COUNT property"), "{}", err);
+    // keys that aren't known are never checked
+    assert_eq!(check_prop(Some(1), 9999, &group(10)), Ok(()));
+}
+
+#[test]
+fn objects_are_checked_when_they_are_made() {
+    // the pattern of the key in `obj_props` already only takes numbers
+    let message = error(
+        "
+extract obj_props
+$.add(obj {OBJ_ID: 1, X: 10g, Y: 45})
+",
+    );
+    assert!(
+        message.contains("required value to match @number, found 10g"),
+        "{}",
+        message
+    );
+
+    let message = error(
+        "
+extract obj_props
+$.add(trigger {OBJ_ID: 1268, X: 45, Y: 45, TARGET: 1c})
+",
+    );
+    assert!(
+        message.contains("TARGET expects @group, found @color"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn numeric_keys_are_not_checked() {
+    let output = build(
+        "
+extract obj_props
+$.add(obj {OBJ_ID: 1, 2: 10g, Y: 45})
+$.add(trigger {OBJ_ID: 1268, X: 45, Y: 45, 51: 1c})
+",
+    );
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+}
+
+#[test]
+fn edit_obj_is_checked() {
+    let message = error(
+        "
+extract obj_props
+let o = obj {OBJ_ID: 1, X: 45, Y: 45}
+$.edit_obj(o, X, 10g)
+",
+    );
+    assert!(
+        message.contains("required value to match @number, found 10g"),
+        "{}",
+        message
+    );
+
+    let message = error(
+        "
+extract obj_props
+let t = trigger {OBJ_ID: 1268, X: 45, Y: 45}
+$.edit_obj(t, TARGET, 1c)
+",
+    );
+    assert!(
+        message.contains("TARGET expects @group, found @color"),
+        "{}",
+        message
+    );
+
+    let message = error(
+        "
+extract obj_props
+let t = trigger {OBJ_ID: 1268, X: 45, Y: 45}
+$.edit_obj(t, COUNT, 1)
+",
+    );
+    assert!(
+        message.contains("The spawn trigger (object ID 1268) has no COUNT property"),
+        "{}",
+        message
+    );
+
+    let output = build(
+        "
+extract obj_props
+let o = obj {OBJ_ID: 1, X: 45, Y: 45}
+$.edit_obj(o, 2, 10g)
+o.set(Y, 60)
+",
+    );
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+}
@@ -0,0 +1,558 @@
+// what the optimizer keeps, removes and merges
+
+mod common;
+
+use common::with_stack;
+use spwn::ast::ObjectMode;
+use spwn::builtin::{Block, Group, Item, ID};
+use spwn::compiler_types::FunctionID;
+use spwn::levelstring::{GDObj, ObjParam};
+use spwn::lockfile::IdLock;
+use spwn::optimize::{self, Pass, PassStats};
+use spwn::target::GD_2_1;
+use spwn::Compiler;
+
+use std::collections::BTreeSet;
+use std::process::Command;
+
+const HEADER: &str =
+    "kS38,1_40_2_125_3_255_11_255_12_255_13_255_4_-1_6_1000_7_1_15_1_18_0_8_1|,kA13,0;";
+
+fn compile(compiler: Compiler) -> Vec<GDObj> {
+    with_stack(move || {
+        let output = compiler.include_path(env!("CARGO_MANIFEST_DIR")).compile();
+        assert!(output.is_ok(), "{:?}", output.diagnostics);
+        output.objects
+    })
+}
+
+fn script(source: &str) -> Compiler {
+    Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/optimize.spwn")).source(source)
+}
+
+fn obj_id(obj: &GDObj) -> Option<f64> {
+    match obj.params.get(&1) {
+        Some(ObjParam::Number(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn groups(obj: &GDObj) -> Vec<Group> {
+    match obj.params.get(&57) {
+        Some(ObjParam::Group(g)) => vec![*g],
+        Some(ObjParam::GroupList(list)) => list.clone(),
+        _ => Vec::new(),
+    }
+}
+
+fn group(id: u16) -> Group {
+    Group {
+        id: ID::Specific(id),
+    }
+}
+
+fn item(id: u16) -> Item {
+    Item {
+        id: ID::Specific(id),
+    }
+}
+
+// nothing in the script spawns `f`
+const UNUSED_GROUP: &str = "
+f = ?g
+$.extend_trigger_func(f, () {
+    10g.move(10, 0)
+})
+";
+
+#[test]
+fn keeps_locked_groups_that_the_level_spawns() {
+    let lock = IdLock::parse(r#"{"groups": {"f#0": 50}}"#).unwrap();
+    // a spawn trigger placed by hand that spawns group 50
+    let level = format!("{}1,1268,2,15,3,15,51,50;", HEADER);
+
+    let objects = compile(
+        script(UNUSED_GROUP)
+            .level_string(&level)
+            .id_lock(lock.clone()),
+    );
+    let moves: Vec<&GDObj> = objects
+        .iter()
+        .filter(|o| obj_id(o) == Some(901.0))
+        .collect();
+    assert_eq!(moves.len(), 1);
+    assert_eq!(groups(moves[0]), vec![group(50)]);
+
+    // without the trigger in the level, the group is never spawned and its triggers are removed
+    let objects = compile(script(UNUSED_GROUP).level_string(HEADER).id_lock(lock));
+    assert!(objects.iter().all(|o| obj_id(o) != Some(901.0)));
+
+    // and without the lock, nothing in the level can know the group's ID
+    let objects = compile(script(UNUSED_GROUP).level_string(&level));
+    assert!(objects.iter().all(|o| obj_id(o) != Some(901.0)));
+}
+
+fn arbitrary(id: u16) -> Group {
+    Group {
+        id: ID::Arbitrary(id),
+    }
+}
+
+// a trigger in `in_group`, or one the player passes if it's `None`
+fn trigger(obj_id: u16, in_group: Option<Group>, params: Vec<(u16, ObjParam)>) -> GDObj {
+    let mut obj = GDObj {
+        mode: ObjectMode::Trigger,
+        ..GDObj::default()
+    };
+    obj.params.insert(1, ObjParam::Number(obj_id as f64));
+    if let Some(g) = in_group {
+        obj.params.insert(57, ObjParam::Group(g));
+        obj.params.insert(62, ObjParam::Bool(true));
+    }
+    obj.params.extend(params);
+    obj
+}
+
+fn spawn(in_group: Option<Group>, target: Group, delay: f64) -> GDObj {
+    trigger(
+        1268,
+        in_group,
+        vec![(51, ObjParam::Group(target)), (63, ObjParam::Number(delay))],
+    )
+}
+
+fn move_trigger(in_group: Option<Group>, target: Group) -> GDObj {
+    trigger(
+        901,
+        in_group,
+        vec![(51, ObjParam::Group(target)), (28, ObjParam::Number(10.0))],
+    )
+}
+
+fn run(triggers: Vec<GDObj>, passes: &[Pass]) -> (Vec<GDObj>, Vec<PassStats>) {
+    run_with(triggers, passes, &BTreeSet::new(), &BTreeSet::new())
+}
+
+fn run_with(
+    triggers: Vec<GDObj>,
+    passes: &[Pass],
+    external: &BTreeSet<Group>,
+    object_groups: &BTreeSet<Group>,
+) -> (Vec<GDObj>, Vec<PassStats>) {
+    let func_ids = vec![FunctionID {
+        parent: None,
+        width: None,
+        obj_list: triggers
+            .into_iter()
+            .enumerate()
+            .map(|(i, o)| (o, i))
+            .collect(),
+    }];
+    let (func_ids, stats) =
+        optimize::run_passes(func_ids, 100, &GD_2_1, external, object_groups, passes);
+    let objects = func_ids
+        .into_iter()
+        .flat_map(|f| f.obj_list.into_iter().map(|(o, _)| o))
+        .collect();
+    (objects, stats)
+}
+
+fn count(objects: &[GDObj], id: u16) -> usize {
+    objects
+        .iter()
+        .filter(|o| obj_id(o) == Some(id as f64))
+        .count()
+}
+
+#[test]
+fn optimization_levels() {
+    use Pass::*;
+    assert_eq!(Pass::for_level(0), vec![]);
+    assert_eq!(Pass::for_level(1), vec![ReadWriteOrder, DeadTriggers]);
+    assert_eq!(
+        Pass::for_level(2),
+        vec![ReadWriteOrder, SpawnChains, MergeGangs]
+    );
+    assert_eq!(
+        Pass::for_level(3),
+        vec![ReadWriteOrder, SpawnChains, MergeGangs, SpawnChains]
+    );
+}
+
+#[test]
+fn passes_by_name() {
+    for pass in &[
+        Pass::ReadWriteOrder,
+        Pass::DeadTriggers,
+        Pass::SpawnChains,
+        Pass::MergeGangs,
+    ] {
+        assert_eq!(Pass::from_name(pass.name()), Ok(*pass));
+    }
+    assert_eq!(
+        Pass::from_name("spawn-chain"),
+        Err("Unknown optimizer pass \"spawn-chain\", expected one of read-write-order, dead-triggers, spawn-chains, merge-gangs".to_string())
+    );
+}
+
+#[test]
+fn dead_triggers() {
+    let (a, b) = (arbitrary(1), arbitrary(2));
+    let (objects, stats) = run(
+        vec![
+            spawn(None, a, 0.0),
+            move_trigger(Some(a), group(10)),
+            move_trigger(Some(b), group(11)),
+        ],
+        &[Pass::DeadTriggers],
+    );
+    assert_eq!(count(&objects, 901), 1);
+    assert!(objects.iter().all(|o| groups(o) != vec![b]));
+    assert_eq!(
+        stats,
+        vec![PassStats {
+            pass: Pass::DeadTriggers,
+            triggers: (3, 2),
+            groups: (2, 1),
+        }]
+    );
+    assert_eq!(
+        stats[0].to_string(),
+        "dead-triggers: 2 triggers (-1), 1 groups (-1)"
+    );
+
+    // unless something outside of the script spawns them
+    let external = vec![b].into_iter().collect();
+    let (objects, _) = run_with(
+        vec![
+            spawn(None, a, 0.0),
+            move_trigger(Some(a), group(10)),
+            move_trigger(Some(b), group(11)),
+        ],
+        &[Pass::DeadTriggers],
+        &external,
+        &BTreeSet::new(),
+    );
+    assert_eq!(count(&objects, 901), 2);
+}
+
+#[test]
+fn spawn_chains() {
+    let (a, b) = (arbitrary(1), arbitrary(2));
+    let (objects, stats) = run(
+        vec![
+            spawn(None, a, 0.25),
+            spawn(Some(a), b, 0.5),
+            move_trigger(Some(b), group(10)),
+        ],
+        &[Pass::SpawnChains],
+    );
+    // the two spawn triggers become one, with both delays
+    assert_eq!(count(&objects, 1268), 1);
+    let spawn = objects.iter().find(|o| obj_id(o) == Some(1268.0)).unwrap();
+    assert_eq!(spawn.params.get(&51), Some(&ObjParam::Group(b)));
+    assert_eq!(spawn.params.get(&63), Some(&ObjParam::Number(0.75)));
+    assert_eq!(
+        stats,
+        vec![PassStats {
+            pass: Pass::SpawnChains,
+            triggers: (3, 2),
+            groups: (2, 1),
+        }]
+    );
+}
+
+#[test]
+fn merge_gangs() {
+    let (a, b) = (arbitrary(1), arbitrary(2));
+    let (objects, stats) = run(
+        vec![
+            spawn(None, a, 0.0),
+            spawn(None, b, 0.5),
+            move_trigger(Some(a), group(10)),
+            move_trigger(Some(b), group(10)),
+        ],
+        &[Pass::MergeGangs],
+    );
+    // both groups have the same triggers, so both spawn triggers spawn one of them
+    assert_eq!(count(&objects, 901), 1);
+    for obj in objects.iter().filter(|o| obj_id(o) == Some(1268.0)) {
+        assert_eq!(obj.params.get(&51), Some(&ObjParam::Group(a)));
+    }
+    assert_eq!(
+        stats,
+        vec![PassStats {
+            pass: Pass::MergeGangs,
+            triggers: (4, 3),
+            groups: (2, 1),
+        }]
+    );
+}
+
+#[test]
+fn merge_gangs_that_spawn_each_other() {
+    // every call makes two groups that spawn each other, so the groups of the two calls
+    // only have the same triggers if the groups they spawn are merged too
+    let objects = compile(
+        script(
+            "
+ping_pong = (g: @group) {
+    ping = ?g
+    pong = ?g
+    $.extend_trigger_func(ping, () {
+        g.move(10, 0)
+        pong!
+    })
+    $.extend_trigger_func(pong, () {
+        g.move(-10, 0)
+        ping!
+    })
+    ping!
+}
+ping_pong(10g)
+ping_pong(10g)
+",
+        )
+        .passes(vec![Pass::MergeGangs]),
+    );
+    assert_eq!(count(&objects, 901), 2);
+    assert_eq!(count(&objects, 1268), 4);
+}
+
+// a group spawned at the start, and another one with the same triggers that `other` is in
+fn twin_groups(other: GDObj) -> Vec<GDObj> {
+    let (a, b) = (arbitrary(1), arbitrary(2));
+    vec![
+        spawn(None, a, 0.0),
+        spawn(None, b, 0.5),
+        move_trigger(Some(a), group(10)),
+        move_trigger(Some(b), group(10)),
+        other,
+    ]
+}
+
+fn merged(objects: &[GDObj]) -> bool {
+    count(objects, 901) == 1
+}
+
+#[test]
+fn groups_that_are_toggled_off_are_not_merged() {
+    let b = arbitrary(2);
+    let off = |obj_id: u16, params: Vec<(u16, ObjParam)>| {
+        let mut params = params;
+        params.push((51, ObjParam::Group(b)));
+        params.push((56, ObjParam::Bool(false)));
+        twin_groups(trigger(obj_id, None, params))
+    };
+    let item = || (80, ObjParam::Item(item(1)));
+    let block = |key, id| {
+        (
+            key,
+            ObjParam::Block(Block {
+                id: ID::Specific(id),
+            }),
+        )
+    };
+
+    // count, instant count, collision, touch and death triggers
+    for triggers in [
+        off(1611, vec![item(), (77, ObjParam::Number(1.0))]),
+        off(1811, vec![item(), (77, ObjParam::Number(1.0))]),
+        off(1815, vec![block(80, 1), block(95, 2)]),
+        off(1595, vec![]),
+        off(1812, vec![]),
+    ] {
+        let (objects, _) = run(triggers, &[Pass::MergeGangs]);
+        assert!(!merged(&objects));
+    }
+
+    // but the same triggers with "activate group" on just spawn it
+    let mut on = off(1611, vec![item(), (77, ObjParam::Number(1.0))]);
+    on[4].params.insert(56, ObjParam::Bool(true));
+    let (objects, _) = run(on, &[Pass::MergeGangs]);
+    assert!(merged(&objects));
+}
+
+#[test]
+fn groups_that_are_toggled_are_not_merged() {
+    for on in &[true, false] {
+        let toggle = trigger(
+            1049,
+            None,
+            vec![
+                (51, ObjParam::Group(arbitrary(2))),
+                (56, ObjParam::Bool(*on)),
+            ],
+        );
+        let (objects, _) = run(twin_groups(toggle), &[Pass::MergeGangs]);
+        assert!(!merged(&objects));
+    }
+}
+
+#[test]
+fn groups_of_other_objects_are_only_kept_from_merging() {
+    let b = arbitrary(2);
+    let object_groups = vec![b].into_iter().collect();
+    let blocks = move_trigger(None, group(11));
+
+    let (objects, _) = run_with(
+        twin_groups(blocks.clone()),
+        &[Pass::MergeGangs],
+        &BTreeSet::new(),
+        &object_groups,
+    );
+    assert!(!merged(&objects));
+
+    // blocks in a group don't make its triggers run, so they are still removed if nothing
+    // spawns them
+    let (objects, _) = run_with(
+        vec![move_trigger(Some(b), group(10))],
+        &[Pass::DeadTriggers],
+        &BTreeSet::new(),
+        &object_groups,
+    );
+    assert_eq!(count(&objects, 901), 0);
+}
+
+#[test]
+fn read_write_order() {
+    let a = arbitrary(1);
+    let (objects, stats) = run(
+        vec![
+            spawn(None, a, 0.0),
+            trigger(
+                1817,
+                Some(a),
+                vec![(80, ObjParam::Item(item(1))), (77, ObjParam::Number(1.0))],
+            ),
+            trigger(
+                1811,
+                Some(a),
+                vec![
+                    (80, ObjParam::Item(item(1))),
+                    (77, ObjParam::Number(1.0)),
+                    (51, ObjParam::Group(group(10))),
+                    (56, ObjParam::Bool(true)),
+                ],
+            ),
+        ],
+        &[Pass::ReadWriteOrder],
+    );
+    // the instant count reads the item after the pickup writes it, so it's moved to a new
+    // group that is spawned a bit later
+    let instant_count = objects.iter().find(|o| obj_id(o) == Some(1811.0)).unwrap();
+    let later = groups(instant_count);
+    assert_eq!(later, vec![arbitrary(101)]);
+    let delay = objects
+        .iter()
+        .find(|o| groups(o) == vec![a] && obj_id(o) == Some(1268.0))
+        .unwrap();
+    assert_eq!(delay.params.get(&51), Some(&ObjParam::Group(later[0])));
+    assert_eq!(delay.params.get(&63), Some(&ObjParam::Number(0.05)));
+    // the trigger that isn't in a group stays that way
+    assert!(groups(&objects[0]).is_empty());
+    assert_eq!(
+        stats,
+        vec![PassStats {
+            pass: Pass::ReadWriteOrder,
+            triggers: (3, 4),
+            groups: (1, 2),
+        }]
+    );
+}
+
+#[test]
+fn stats_count_every_group_of_a_trigger() {
+    let (a, b) = (arbitrary(1), arbitrary(2));
+    let mut shared = move_trigger(None, group(10));
+    shared.params.insert(57, ObjParam::GroupList(vec![a, b]));
+    let (objects, stats) = run(vec![shared], &[Pass::ReadWriteOrder]);
+    assert_eq!(groups(&objects[0]), vec![a, b]);
+    assert_eq!(stats[0].groups, (2, 2));
+}
+
+#[test]
+fn stats_of_every_pass() {
+    const SCRIPT: &str = "
+f = !{
+    wait(0.5)
+    10g.move(10, 0)
+}
+g = !{
+    10g.move(10, 0)
+}
+unused = !{
+    11g.move(10, 0)
+}
+f!
+g!
+";
+    for level in 0..4 {
+        let output = with_stack(move || {
+            script(SCRIPT)
+                .include_path(env!("CARGO_MANIFEST_DIR"))
+                .opt_level(level)
+                .compile()
+        });
+        assert!(output.is_ok(), "{:?}", output.diagnostics);
+        let passes: Vec<Pass> = output.pass_stats.iter().map(|s| s.pass).collect();
+        assert_eq!(passes, Pass::for_level(level));
+        // every pass starts with what the one before it left
+        for pair in output.pass_stats.windows(2) {
+            assert_eq!(pair[0].triggers.1, pair[1].triggers.0);
+            assert_eq!(pair[0].groups.1, pair[1].groups.0);
+        }
+        if let Some(last) = output.pass_stats.last() {
+            let triggers = output
+                .objects
+                .iter()
+                .filter(|o| o.mode == ObjectMode::Trigger)
+                .count();
+            assert_eq!(last.triggers.1, triggers);
+            assert!(last.triggers.1 < output.pass_stats[0].triggers.0);
+        }
+    }
+}
+
+#[test]
+fn cli_pass_selection() {
+    let dir = std::env::temp_dir().join(format!("spwn_passes_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("passes.spwn");
+    std::fs::write(&path, "f = !{\n    10g.move(10, 0)\n}\nf!\n").unwrap();
+    let build = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_spwn"))
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .arg("build")
+            .arg(&path)
+            .arg("-l")
+            .args(args)
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).to_string()
+                + &String::from_utf8_lossy(&output.stderr),
+        )
+    };
+
+    let (ok, out) = build(&["--passes=dead-triggers,spawn-chains"]);
+    assert!(ok, "{}", out);
+    assert!(out.contains("dead-triggers: "));
+    assert!(out.contains("spawn-chains: "));
+    assert!(!out.contains("merge-gangs: "));
+
+    let (ok, out) = build(&["-O0"]);
+    assert!(ok, "{}", out);
+    assert!(!out.contains("Optimizer:"));
+
+    let (ok, out) = build(&["-O3"]);
+    assert!(ok, "{}", out);
+    assert_eq!(out.matches("spawn-chains: ").count(), 2);
+
+    let (ok, out) = build(&["--passes=spawn-chain"]);
+    assert!(!ok);
+    assert!(out.contains("Unknown optimizer pass"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,90 @@
+// syntax errors are recovered from at statement boundaries, so every error gets reported
+
+use spwn::ast::{Statement, StatementBody, ValueBody};
+use spwn::parser::{parse_spwn_recovering, FileRange, SyntaxError};
+use std::path::PathBuf;
+
+fn parse(source: &str) -> (Vec<Statement>, Vec<SyntaxError>) {
+    let (statements, notes) = parse_spwn_recovering(source.to_string(), PathBuf::from("t.spwn"));
+    (statements, notes.errors)
+}
+
+fn position(err: &SyntaxError) -> FileRange {
+    match err {
+        SyntaxError::ExpectedErr { pos, .. }
+        | SyntaxError::UnexpectedErr { pos, .. }
+        | SyntaxError::SyntaxError { pos, .. } => *pos,
+    }
+}
+
+fn is_invalid(statement: &Statement) -> bool {
+    matches!(statement.body, StatementBody::Invalid(_))
+}
+
+// statements in the body of `name = () { ... }`
+fn macro_body(statement: &Statement) -> &[Statement] {
+    match &statement.body {
+        StatementBody::Expr(e) => match &e.values[1].value.body {
+            ValueBody::Macro(m) => &m.body.statements,
+            other => panic!("not a macro: {:?}", other),
+        },
+        other => panic!("not an expression: {:?}", other),
+    }
+}
+
+#[test]
+fn reports_every_error_in_a_block() {
+    let (statements, errors) = parse("f = () { x = 3 3 \n y = ) }\ng = 1\n");
+    assert_eq!(
+        errors.iter().map(position).collect::<Vec<_>>(),
+        vec![((1, 15), (1, 16)), ((2, 5), (2, 6))]
+    );
+
+    // the macro itself is still valid, only the statements in it are skipped
+    assert_eq!(statements.len(), 2);
+    assert!(!statements.iter().any(is_invalid));
+    let body = macro_body(&statements[0]);
+    assert_eq!(body.len(), 2);
+    assert!(body.iter().all(is_invalid));
+}
+
+#[test]
+fn keeps_valid_statements_between_errors() {
+    let (statements, errors) =
+        parse("f = () {\n    a = 1 1\n    b = 2\n    c = )\n}\nd = ]\ne = 3\n");
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert_eq!(position(&errors[0]).0, (2, 10));
+    assert_eq!(position(&errors[1]).0, (4, 8));
+    assert_eq!(position(&errors[2]).0, (6, 4));
+
+    assert_eq!(statements.len(), 3);
+    assert!(!is_invalid(&statements[0]));
+    assert!(is_invalid(&statements[1]));
+    assert!(!is_invalid(&statements[2]));
+
+    let body = macro_body(&statements[0]);
+    assert_eq!(body.len(), 3);
+    assert!(is_invalid(&body[0]));
+    assert!(!is_invalid(&body[1]));
+    assert!(is_invalid(&body[2]));
+}
+
+#[test]
+fn nested_blocks_recover_separately() {
+    let (statements, errors) =
+        parse("if true {\n    if false {\n        x = 1 2\n    }\n    y = 3 4\n}\nz = 5 6\n");
+    assert_eq!(
+        errors.iter().map(|e| position(e).0).collect::<Vec<_>>(),
+        vec![(3, 14), (5, 10), (7, 6)]
+    );
+    assert_eq!(statements.len(), 2);
+    assert!(!is_invalid(&statements[0]));
+    assert!(is_invalid(&statements[1]));
+}
+
+#[test]
+fn unclosed_block_error_is_at_the_end_of_the_file() {
+    let (_, errors) = parse("a = 1\nf = () {\n    b = 2\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(position(&errors[0]).0, (4, 0));
+}
@@ -0,0 +1,181 @@
+// which arbitrary groups can share a specific group with `Compiler::recycle_groups`
+
+mod common;
+
+use common::with_stack;
+use spwn::ast::ObjectMode;
+use spwn::builtin::{Group, ID};
+use spwn::levelstring::{GDObj, GroupInterference, LevelString, ObjParam};
+use spwn::Compiler;
+
+fn build(source: &'static str, recycle_groups: bool) -> LevelString {
+    let output = with_stack(move || {
+        Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/recycle.spwn"))
+            .source(source)
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .passes(Vec::new())
+            .recycle_groups(recycle_groups)
+            .compile()
+    });
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+    LevelString::parse(&output.level_string).unwrap()
+}
+
+fn param(obj: &GDObj, key: u16) -> Option<String> {
+    obj.params.get(&key).map(|p| p.to_string())
+}
+
+// the first group of every object with this object ID, which is the group made by the script
+fn first_groups(level: &LevelString, obj_id: &str) -> Vec<String> {
+    level
+        .objects
+        .iter()
+        .filter(|o| param(o, 1).as_deref() == Some(obj_id))
+        .map(|o| {
+            let groups = param(o, 57).unwrap();
+            groups.split('.').next().unwrap().to_string()
+        })
+        .collect()
+}
+
+// the group of `blocks` is only moved, and the group of `f` is only spawned
+const FUNCTION_AND_BLOCKS: &str = "
+extract obj_props
+blocks = ?g
+$.add(obj {OBJ_ID: 1, X: 45, Y: 45, GROUPS: blocks})
+f = !{
+    blocks.move(10, 0)
+}
+f!
+";
+
+#[test]
+fn independent_groups_share_one_group() {
+    let level = build(FUNCTION_AND_BLOCKS, true);
+    let trigger = first_groups(&level, "901");
+    assert_eq!(trigger, first_groups(&level, "1"));
+    // the move trigger moves the group it is in
+    let move_trigger = level
+        .objects
+        .iter()
+        .find(|o| param(o, 1).as_deref() == Some("901"))
+        .unwrap();
+    assert_eq!(param(move_trigger, 51), Some(trigger[0].clone()));
+}
+
+#[test]
+fn groups_are_not_shared_without_the_option() {
+    let level = build(FUNCTION_AND_BLOCKS, false);
+    assert_ne!(first_groups(&level, "901"), first_groups(&level, "1"));
+}
+
+#[test]
+fn spawned_groups_are_not_shared() {
+    // spawning a shared group would run the triggers of both functions
+    let level = build(
+        "
+f = !{
+    10g.move(10, 0)
+}
+g = !{
+    11g.move(10, 0)
+}
+f!
+g!
+",
+        true,
+    );
+    let groups = first_groups(&level, "901");
+    assert_eq!(groups.len(), 2);
+    assert_ne!(groups[0], groups[1]);
+}
+
+#[test]
+fn moved_groups_are_not_shared() {
+    // moving a shared group would move the blocks of both groups
+    let level = build(
+        "
+extract obj_props
+a = ?g
+b = ?g
+$.add(obj {OBJ_ID: 1, X: 45, Y: 45, GROUPS: a})
+$.add(obj {OBJ_ID: 1, X: 75, Y: 45, GROUPS: b})
+a.move(10, 0)
+b.move(0, 10)
+",
+        true,
+    );
+    let groups = first_groups(&level, "1");
+    assert_eq!(groups.len(), 2);
+    assert_ne!(groups[0], groups[1]);
+}
+
+fn arbitrary(id: u16) -> Group {
+    Group {
+        id: ID::Arbitrary(id),
+    }
+}
+
+// a trigger in `in_group`, or one the player passes if it's `None`
+fn trigger(obj_id: u16, in_group: Option<Group>, params: Vec<(u16, ObjParam)>) -> GDObj {
+    let mut obj = GDObj {
+        mode: ObjectMode::Trigger,
+        ..GDObj::default()
+    };
+    obj.params.insert(1, ObjParam::Number(obj_id as f64));
+    if let Some(g) = in_group {
+        obj.params.insert(57, ObjParam::Group(g));
+        obj.params.insert(62, ObjParam::Bool(true));
+    }
+    obj.params.extend(params);
+    obj
+}
+
+fn block(group: Group) -> GDObj {
+    let mut obj = GDObj::default();
+    obj.params.insert(1, ObjParam::Number(1.0));
+    obj.params.insert(57, ObjParam::Group(group));
+    obj
+}
+
+#[test]
+fn interference_comes_from_what_triggers_do() {
+    let (f, g, blocks, other) = (1, 2, 3, 4);
+    let mut objects = vec![
+        trigger(1268, None, vec![(51, ObjParam::Group(arbitrary(f)))]),
+        trigger(1268, None, vec![(51, ObjParam::Group(arbitrary(g)))]),
+        trigger(
+            901,
+            Some(arbitrary(f)),
+            vec![(51, ObjParam::Group(arbitrary(blocks)))],
+        ),
+        trigger(
+            901,
+            Some(arbitrary(g)),
+            vec![(51, ObjParam::Group(arbitrary(other)))],
+        ),
+        block(arbitrary(blocks)),
+        block(arbitrary(other)),
+    ];
+    let interference = GroupInterference::of_objects(&objects);
+    // sharing a group, spawning `f` would run `g` and moving `blocks` would move `other`
+    assert!(interference.interferes(f, g));
+    assert!(interference.interferes(blocks, other));
+    assert!(!interference.interferes(f, blocks));
+    assert!(!interference.interferes(g, other));
+    assert!(!interference.interferes(f, other));
+
+    // toggling `f` off would also turn off the blocks of a group that shares it
+    objects.push(trigger(
+        1049,
+        Some(arbitrary(g)),
+        vec![
+            (51, ObjParam::Group(arbitrary(f))),
+            (56, ObjParam::Bool(false)),
+        ],
+    ));
+    let interference = GroupInterference::of_objects(&objects);
+    assert!(interference.interferes(f, blocks));
+    assert!(interference.interferes(f, other));
+    assert!(!interference.interferes(g, other));
+}
@@ -0,0 +1,73 @@
+// commands and session state of the repl
+
+mod common;
+
+use common::with_stack;
+use spwn::repl::Repl;
+use std::path::PathBuf;
+
+// runs every line in one session, and returns what each of them printed
+fn session(lines: &'static [&'static str]) -> Vec<Result<Vec<String>, String>> {
+    with_stack(move || {
+        let mut repl = Repl::new(
+            PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/repl.spwn")),
+            vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))],
+        )
+        .unwrap();
+        lines
+            .iter()
+            .map(|line| repl.eval(line).map_err(|e| e.message))
+            .collect()
+    })
+}
+
+fn lines(strs: &[&str]) -> Result<Vec<String>, String> {
+    Ok(strs.iter().map(|s| s.to_string()).collect())
+}
+
+#[test]
+fn type_shows_the_type_without_running_the_expression() {
+    let out = session(&[
+        "let a = [1]",
+        ":type a",
+        ":type a.push(2)",
+        "a",
+        ":type 10g.move(10, 0)",
+        ":objects",
+    ]);
+    assert_eq!(out[1], lines(&["@array"]));
+    assert_eq!(out[2], lines(&["@NULL"]));
+    assert_eq!(out[3], lines(&["[1]"]));
+    assert_eq!(out[4], lines(&["@NULL"]));
+    assert_eq!(out[5], lines(&["No objects"]));
+}
+
+#[test]
+fn reset_forgets_variables_and_objects() {
+    let out = session(&["x = 1", "10g.move(10, 0)", ":reset", "x", ":objects"]);
+    assert_eq!(out[2], lines(&["Cleared all variables and objects"]));
+    assert!(out[3].is_err());
+    assert_eq!(out[4], lines(&["No objects"]));
+}
+
+#[test]
+fn objects_shows_the_added_objects() {
+    let out = session(&[
+        ":objects",
+        "extract obj_props",
+        "$.add(obj {OBJ_ID: 1, X: 45, Y: 45})",
+        ":objects",
+    ]);
+    assert_eq!(out[0], lines(&["No objects"]));
+    let objects = out[3].clone().unwrap();
+    assert_eq!(objects.len(), 1);
+    assert!(objects[0].starts_with("1,1,"), "{}", objects[0]);
+}
+
+#[test]
+fn split_contexts_show_their_values() {
+    let out = session(&["c = counter(0)", "c.to_const(0..2)", "1"]);
+    assert_eq!(out[1], lines(&["[context 1/2] 0", "[context 2/2] 1"]));
+    // later lines run in every context
+    assert_eq!(out[2], lines(&["[context 1/2] 1", "[context 2/2] 1"]));
+}
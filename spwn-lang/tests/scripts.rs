@@ -0,0 +1,73 @@
+// several scripts building into the same level, each removing only its own earlier objects
+
+mod common;
+
+use common::with_stack;
+use spwn::levelstring::{unregister_script, GDObj, LevelString, ScriptRegistry};
+use spwn::Compiler;
+
+// builds a script that moves `group` into `level` under the name `name`
+fn build(name: &'static str, group: u16, level: String) -> LevelString {
+    let output = with_stack(move || {
+        Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/scripts.spwn"))
+            .source(format!("{}g.move(10, 0)\n", group))
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .level_string(level)
+            .signature(name)
+            .compile()
+    });
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+    LevelString::parse(&output.level_string).unwrap()
+}
+
+fn param(obj: &GDObj, key: u16) -> Option<String> {
+    obj.params.get(&key).map(|p| p.to_string())
+}
+
+// the groups moved by the move triggers in the level, sorted
+fn moved(level: &LevelString) -> Vec<String> {
+    let mut groups: Vec<String> = level
+        .objects
+        .iter()
+        .filter(|o| param(o, 1).as_deref() == Some("901"))
+        .map(|o| param(o, 51).unwrap())
+        .collect();
+    groups.sort();
+    groups
+}
+
+#[test]
+fn scripts_keep_each_others_objects() {
+    let level = build("a", 10, String::new());
+    let level = build("b", 11, level.to_string());
+    let mut level = build("a", 10, level.to_string());
+    assert_eq!(moved(&level), ["10", "11"]);
+
+    let registry = ScriptRegistry::read(&level);
+    assert_eq!(registry.scripts.keys().collect::<Vec<_>>(), ["a", "b"]);
+    assert_ne!(registry.scripts["a"], registry.scripts["b"]);
+
+    assert!(unregister_script(&mut level, "a"));
+    assert_eq!(moved(&level), ["11"]);
+    let registry = ScriptRegistry::read(&level);
+    assert_eq!(registry.scripts.keys().collect::<Vec<_>>(), ["b"]);
+    assert!(!unregister_script(&mut level, "a"));
+    assert_eq!(moved(&level), ["11"]);
+}
+
+#[test]
+fn first_script_takes_over_objects_of_older_versions() {
+    // a block placed by hand, and a trigger built by an older version of SPWN
+    let old = "1,1,2,15,3,15;1,901,2,45,3,15,51,12,57,1001,28,10,29,0;";
+    let level = build("a", 10, old.to_string());
+    assert_eq!(moved(&level), ["10"]);
+    assert!(level
+        .objects
+        .iter()
+        .any(|o| param(o, 1).as_deref() == Some("1")));
+    assert_eq!(ScriptRegistry::read(&level).scripts["a"], 1001);
+
+    let level = build("b", 11, level.to_string());
+    assert_eq!(moved(&level), ["10", "11"]);
+    assert_ne!(ScriptRegistry::read(&level).scripts["b"], 1001);
+}
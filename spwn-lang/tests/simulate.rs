@@ -0,0 +1,351 @@
+// behaviour of the triggers in the headless simulator
+
+use spwn::ast::ObjectMode;
+use spwn::builtin::{Block, Group, Item, ID};
+use spwn::levelstring::{GDObj, ObjParam};
+use spwn::simulate::{Input, Simulation};
+use std::collections::BTreeMap;
+
+fn group(id: u16) -> Group {
+    Group {
+        id: ID::Specific(id),
+    }
+}
+
+fn item(id: u16) -> Item {
+    Item {
+        id: ID::Specific(id),
+    }
+}
+
+// a trigger that is spawned by `in_group`, or passed by the player at the start without one
+fn trigger(obj_id: u16, in_group: Option<u16>, params: Vec<(u16, ObjParam)>) -> GDObj {
+    let mut all: BTreeMap<u16, ObjParam> = params.into_iter().collect();
+    all.insert(1, ObjParam::Number(obj_id as f64));
+    if let Some(g) = in_group {
+        all.insert(57, ObjParam::Group(group(g)));
+        all.insert(62, ObjParam::Bool(true));
+    }
+    GDObj {
+        params: all,
+        mode: ObjectMode::Trigger,
+        ..GDObj::default()
+    }
+}
+
+fn spawn(in_group: u16, target: u16, delay: f64) -> GDObj {
+    trigger(
+        1268,
+        Some(in_group),
+        vec![
+            (51, ObjParam::Group(group(target))),
+            (63, ObjParam::Number(delay)),
+        ],
+    )
+}
+
+fn toggle(in_group: u16, target: u16, on: bool) -> GDObj {
+    trigger(
+        1049,
+        Some(in_group),
+        vec![
+            (51, ObjParam::Group(group(target))),
+            (56, ObjParam::Bool(on)),
+        ],
+    )
+}
+
+fn pickup(in_group: u16, id: u16, count: i32) -> GDObj {
+    trigger(
+        1817,
+        Some(in_group),
+        vec![
+            (80, ObjParam::Item(item(id))),
+            (77, ObjParam::Number(count as f64)),
+        ],
+    )
+}
+
+fn count(in_group: Option<u16>, id: u16, value: i32, target: u16, multi: bool) -> GDObj {
+    trigger(
+        1611,
+        in_group,
+        vec![
+            (80, ObjParam::Item(item(id))),
+            (77, ObjParam::Number(value as f64)),
+            (51, ObjParam::Group(group(target))),
+            (56, ObjParam::Bool(true)),
+            (104, ObjParam::Bool(multi)),
+        ],
+    )
+}
+
+// comparison: 0 is equal, 1 is larger, 2 is smaller
+fn instant_count(in_group: u16, id: u16, value: i32, comparison: u8, target: u16) -> GDObj {
+    trigger(
+        1811,
+        Some(in_group),
+        vec![
+            (80, ObjParam::Item(item(id))),
+            (77, ObjParam::Number(value as f64)),
+            (88, ObjParam::Number(comparison as f64)),
+            (51, ObjParam::Group(group(target))),
+            (56, ObjParam::Bool(true)),
+        ],
+    )
+}
+
+// mode: 0 spawns, 1 toggles on, 2 toggles off
+fn touch(target: u16, mode: u8, hold: bool, dual: bool) -> GDObj {
+    trigger(
+        1595,
+        None,
+        vec![
+            (51, ObjParam::Group(group(target))),
+            (82, ObjParam::Number(mode as f64)),
+            (81, ObjParam::Bool(hold)),
+            (89, ObjParam::Bool(dual)),
+        ],
+    )
+}
+
+#[test]
+fn spawn_runs_the_triggers_in_a_group() {
+    let objects = vec![
+        pickup(1, 1, 1),
+        spawn(1, 2, 0.0),
+        pickup(2, 1, 10),
+        pickup(1, 1, 100),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(0.0, group(1));
+    let timeline = sim.run(1.0).unwrap();
+    assert_eq!(timeline.item_at(item(1), 1.0), 111);
+    assert_eq!(timeline.spawns(group(2)), vec![0.0]);
+    // the spawned group runs before the rest of the group that spawned it
+    assert_eq!(
+        timeline
+            .entries
+            .iter()
+            .filter_map(|e| match e.change {
+                spwn::simulate::Change::Item { value, .. } => Some(value),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        vec![1, 11, 111]
+    );
+}
+
+#[test]
+fn spawn_delay() {
+    let objects = vec![
+        spawn(1, 2, 0.5),
+        pickup(2, 1, 1),
+        spawn(2, 3, 0.25),
+        pickup(3, 1, 1),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(1.0, group(1));
+    let timeline = sim.run(10.0).unwrap();
+    assert_eq!(timeline.spawns(group(2)), vec![1.5]);
+    assert_eq!(timeline.spawns(group(3)), vec![1.75]);
+    assert_eq!(timeline.item_at(item(1), 1.6), 1);
+    assert_eq!(timeline.item_at(item(1), 1.8), 2);
+}
+
+#[test]
+fn toggled_off_groups_do_nothing() {
+    let objects = vec![
+        toggle(1, 2, false),
+        spawn(1, 2, 0.0),
+        pickup(2, 1, 1),
+        toggle(3, 2, true),
+        spawn(3, 2, 0.0),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(0.0, group(1));
+    sim.spawn_at(1.0, group(3));
+    let timeline = sim.run(2.0).unwrap();
+    assert!(!timeline.group_enabled_at(group(2), 0.5));
+    assert_eq!(timeline.item_at(item(1), 0.5), 0);
+    assert!(timeline.group_enabled_at(group(2), 1.0));
+    assert_eq!(timeline.item_at(item(1), 1.0), 1);
+}
+
+#[test]
+fn pickup_adds_to_items() {
+    let objects = vec![pickup(1, 1, 5), pickup(1, 2, 3), pickup(1, 1, -7)];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(0.0, group(1));
+    sim.spawn_at(1.0, group(1));
+    sim.run(2.0).unwrap();
+    assert_eq!(sim.item(item(1)), -4);
+    assert_eq!(sim.item(item(2)), 6);
+}
+
+#[test]
+fn instant_count_compares_once() {
+    let objects = vec![
+        pickup(1, 1, 3),
+        instant_count(1, 1, 3, 0, 10),
+        instant_count(1, 1, 2, 1, 11),
+        instant_count(1, 1, 3, 2, 12),
+        instant_count(1, 1, 4, 2, 13),
+        pickup(1, 1, 1),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(0.0, group(1));
+    let timeline = sim.run(1.0).unwrap();
+    assert_eq!(timeline.spawns(group(10)), vec![0.0]);
+    assert_eq!(timeline.spawns(group(11)), vec![0.0]);
+    assert!(timeline.spawns(group(12)).is_empty());
+    assert_eq!(timeline.spawns(group(13)), vec![0.0]);
+}
+
+#[test]
+fn count_waits_for_the_item() {
+    let objects = vec![
+        // passed by the player at the start
+        count(None, 1, 2, 10, false),
+        count(None, 1, 2, 11, true),
+        pickup(1, 1, 1),
+        pickup(2, 1, -1),
+    ];
+    let mut sim = Simulation::new(&objects);
+    for t in 1..=3 {
+        sim.spawn_at(t as f64, group(1));
+    }
+    sim.spawn_at(4.0, group(2));
+    sim.spawn_at(5.0, group(1));
+    let timeline = sim.run(10.0).unwrap();
+    // without multi activate it only fires the first time, with it also when counting down to 2
+    assert_eq!(timeline.spawns(group(10)), vec![2.0]);
+    assert_eq!(timeline.spawns(group(11)), vec![2.0, 4.0]);
+}
+
+#[test]
+fn count_turns_groups_on() {
+    let objects = vec![
+        count(None, 1, 1, 2, false),
+        toggle(3, 2, false),
+        pickup(4, 1, 1),
+        pickup(2, 2, 1),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(0.5, group(3));
+    sim.spawn_at(1.0, group(4));
+    let timeline = sim.run(2.0).unwrap();
+    assert!(timeline.group_enabled_at(group(2), 1.0));
+    assert_eq!(timeline.item_at(item(2), 1.0), 1);
+}
+
+#[test]
+fn touch_spawns() {
+    let objects = vec![touch(2, 0, false, false), pickup(2, 1, 1)];
+    let mut sim = Simulation::new(&objects);
+    sim.schedule(1.0, Input::Touch { dual_side: false });
+    sim.schedule(1.5, Input::TouchEnd { dual_side: false });
+    sim.schedule(2.0, Input::Touch { dual_side: true });
+    let timeline = sim.run(3.0).unwrap();
+    assert_eq!(timeline.spawns(group(2)), vec![1.0, 2.0]);
+    assert_eq!(timeline.item_at(item(1), 3.0), 2);
+}
+
+#[test]
+fn touch_hold_toggles_off_on_release() {
+    let objects = vec![
+        touch(2, 0, true, false),
+        touch(3, 1, true, false),
+        toggle(4, 3, false),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(0.5, group(4));
+    sim.schedule(1.0, Input::Touch { dual_side: false });
+    sim.schedule(2.0, Input::TouchEnd { dual_side: false });
+    let timeline = sim.run(3.0).unwrap();
+
+    assert_eq!(timeline.spawns(group(2)), vec![1.0]);
+    assert!(timeline.group_enabled_at(group(2), 1.5));
+    assert!(!timeline.group_enabled_at(group(2), 2.0));
+
+    // on mode turns the group on, even if it was off
+    assert!(!timeline.group_enabled_at(group(3), 0.9));
+    assert!(timeline.group_enabled_at(group(3), 1.5));
+    assert_eq!(timeline.spawns(group(3)), vec![1.0]);
+    assert!(!timeline.group_enabled_at(group(3), 2.0));
+}
+
+#[test]
+fn touch_off_toggles_back_on_release() {
+    let objects = vec![
+        touch(2, 2, true, false),
+        pickup(2, 1, 1),
+        touch(3, 2, false, false),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.schedule(1.0, Input::Touch { dual_side: false });
+    sim.schedule(2.0, Input::TouchEnd { dual_side: false });
+    let timeline = sim.run(3.0).unwrap();
+
+    assert!(!timeline.group_enabled_at(group(2), 1.5));
+    assert!(timeline.group_enabled_at(group(2), 2.0));
+    // turning it back on runs it, like `touch_end` in the std library expects
+    assert_eq!(timeline.spawns(group(2)), vec![2.0]);
+    assert_eq!(timeline.item_at(item(1), 2.0), 1);
+
+    // without hold mode it stays off
+    assert!(!timeline.group_enabled_at(group(3), 3.0));
+}
+
+#[test]
+fn touch_dual_mode_only_listens_to_the_dual_side() {
+    let objects = vec![touch(2, 0, false, true)];
+    let mut sim = Simulation::new(&objects);
+    sim.schedule(1.0, Input::Touch { dual_side: false });
+    sim.schedule(2.0, Input::Touch { dual_side: true });
+    let timeline = sim.run(3.0).unwrap();
+    assert_eq!(timeline.spawns(group(2)), vec![2.0]);
+}
+
+#[test]
+fn collision_and_death() {
+    let block = |id| Block {
+        id: ID::Specific(id),
+    };
+    let objects = vec![
+        trigger(
+            1815,
+            None,
+            vec![
+                (80, ObjParam::Block(block(1))),
+                (95, ObjParam::Block(block(2))),
+                (51, ObjParam::Group(group(10))),
+                (56, ObjParam::Bool(true)),
+            ],
+        ),
+        trigger(
+            1812,
+            None,
+            vec![
+                (51, ObjParam::Group(group(11))),
+                (56, ObjParam::Bool(false)),
+            ],
+        ),
+    ];
+    let mut sim = Simulation::new(&objects);
+    sim.schedule(1.0, Input::Collision(block(2), block(1)));
+    sim.schedule(1.5, Input::CollisionExit(block(2), block(1)));
+    sim.schedule(2.0, Input::Death);
+    let timeline = sim.run(3.0).unwrap();
+    assert_eq!(timeline.spawns(group(10)), vec![1.0]);
+    assert!(timeline.group_enabled_at(group(11), 1.9));
+    assert!(!timeline.group_enabled_at(group(11), 2.0));
+}
+
+#[test]
+fn infinite_loops_are_an_error() {
+    let objects = vec![spawn(1, 1, 0.0)];
+    let mut sim = Simulation::new(&objects);
+    sim.spawn_at(0.0, group(1));
+    assert!(sim.run(1.0).is_err());
+}
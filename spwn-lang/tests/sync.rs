@@ -0,0 +1,121 @@
+// the parts of a sync group, which share their start groups and never run at the same time
+
+mod common;
+
+use common::with_stack;
+use spwn::builtin::{Group, Item, ID};
+use spwn::levelstring::{GDObj, LevelString};
+use spwn::simulate::Simulation;
+use spwn::Compiler;
+
+fn build(source: &'static str) -> LevelString {
+    let output = with_stack(move || {
+        Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sync.spwn"))
+            .source(source)
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .compile()
+    });
+    assert!(output.is_ok(), "{:?}", output.diagnostics);
+    LevelString::parse(&output.level_string).unwrap()
+}
+
+fn param(obj: &GDObj, key: u16) -> Option<String> {
+    obj.params.get(&key).map(|p| p.to_string())
+}
+
+fn triggers<'a>(level: &'a LevelString, obj_id: &str) -> Vec<&'a GDObj> {
+    level
+        .objects
+        .iter()
+        .filter(|o| param(o, 1).as_deref() == Some(obj_id))
+        .collect()
+}
+
+fn groups(obj: &GDObj) -> Vec<String> {
+    param(obj, 57)
+        .map(|g| g.split('.').map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn group(id: u16) -> Group {
+    Group {
+        id: ID::Specific(id),
+    }
+}
+
+fn item(id: u16) -> Item {
+    Item {
+        id: ID::Specific(id),
+    }
+}
+
+// part 20g counts item 1 and part 21g counts item 2, a second after they start
+const TWO_PARTS: &str = "
+f = !{
+    sync s[20g] {
+        wait(1)
+        1i.add(1)
+    }
+}
+g = !{
+    sync s[21g] {
+        wait(1)
+        2i.add(1)
+    }
+}
+f!
+wait(2)
+g!
+";
+
+#[test]
+fn parts_share_start_groups() {
+    let level = build(TWO_PARTS);
+    let pickups = triggers(&level, "1817");
+    assert_eq!(pickups.len(), 2);
+    let (mut first, mut second) = (groups(pickups[0]), groups(pickups[1]));
+    // every trigger of a part is also in the group of its part
+    assert!(first.contains(&"20".to_string()) != second.contains(&"20".to_string()));
+    assert!(first.contains(&"21".to_string()) != second.contains(&"21".to_string()));
+    first.retain(|g| g != "20" && g != "21");
+    second.retain(|g| g != "20" && g != "21");
+    assert_eq!(first, second);
+}
+
+#[test]
+fn only_the_running_part_fires() {
+    let level = build(TWO_PARTS);
+    let mut sim = Simulation::new(&level.objects);
+    let timeline = sim.run(5.0).unwrap();
+    // the shared start group is spawned twice, and each time only one part is on
+    assert_eq!(timeline.item_at(item(1), 1.5), 1);
+    assert_eq!(timeline.item_at(item(2), 1.5), 0);
+    assert!(timeline.group_enabled_at(group(20), 1.5));
+    assert!(!timeline.group_enabled_at(group(21), 1.5));
+    assert_eq!(timeline.item_at(item(1), 4.0), 1);
+    assert_eq!(timeline.item_at(item(2), 4.0), 1);
+    assert!(!timeline.group_enabled_at(group(20), 4.0));
+    assert!(timeline.group_enabled_at(group(21), 4.0));
+}
+
+#[test]
+fn one_part_adds_no_toggles() {
+    let level = build(
+        "
+f = !{
+    sync s[20g] {
+        wait(1)
+        1i.add(1)
+    }
+}
+f!
+",
+    );
+    assert!(triggers(&level, "1049").is_empty());
+    assert!(level
+        .objects
+        .iter()
+        .all(|o| !groups(o).contains(&"20".to_string())));
+    let timeline = Simulation::new(&level.objects).run(2.0).unwrap().clone();
+    assert_eq!(timeline.item_at(item(1), 2.0), 1);
+}
@@ -0,0 +1,143 @@
+// `spwn test`: finding and running the macros tagged with `#[test]`
+
+mod common;
+
+use common::with_stack;
+use spwn::parser;
+use spwn::testing::{self, TestResult};
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/testing/tests.spwn")
+}
+
+fn run(path: PathBuf) -> Result<Vec<TestResult>, Vec<String>> {
+    with_stack(move || {
+        testing::run_tests(&path, vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+            .map_err(|e| e.into_iter().map(|d| d.message).collect())
+    })
+}
+
+fn result<'a>(results: &'a [TestResult], name: &str) -> &'a TestResult {
+    results
+        .iter()
+        .find(|r| r.name == name)
+        .unwrap_or_else(|| panic!("test {} did not run", name))
+}
+
+fn messages(result: &TestResult) -> Vec<String> {
+    result
+        .diagnostics
+        .iter()
+        .flat_map(|d| std::iter::once(d.message.clone()).chain(d.notes.clone()))
+        .collect()
+}
+
+#[test]
+fn finds_test_macros() {
+    let source = std::fs::read_to_string(fixture()).unwrap();
+    let (statements, notes) = parser::parse_spwn(source, fixture()).unwrap();
+    assert!(notes.errors.is_empty());
+    let names: Vec<String> = testing::find_tests(&statements)
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "passes",
+            "fails_assert",
+            "fails_assert_eq",
+            "throws",
+            "matches_output",
+            "misses_output",
+            "missing_file",
+        ]
+    );
+}
+
+#[test]
+fn collects_scripts_in_directories() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    assert_eq!(testing::collect_scripts(&dir).unwrap(), vec![fixture()]);
+    assert_eq!(
+        testing::collect_scripts(&fixture()).unwrap(),
+        vec![fixture()]
+    );
+}
+
+#[test]
+fn runs_every_test() {
+    let results = run(fixture()).unwrap();
+    assert_eq!(results.len(), 7);
+    assert!(results.iter().all(|r| r.name != "not_a_test"));
+
+    assert!(result(&results, "passes").passed());
+    assert!(result(&results, "matches_output").passed());
+}
+
+#[test]
+fn failed_assertions() {
+    let results = run(fixture()).unwrap();
+    assert_eq!(
+        messages(result(&results, "fails_assert")),
+        vec!["Assertion failed: condition was false"]
+    );
+    assert_eq!(
+        messages(result(&results, "fails_assert_eq")),
+        vec!["Assertion failed: 2 is not equal to 3"]
+    );
+    let throws = result(&results, "throws");
+    assert!(!throws.passed());
+    // the error points at the throw statement
+    assert_eq!(
+        throws.diagnostics[0].primary.as_ref().unwrap().range.0,
+        (21, 4)
+    );
+}
+
+#[test]
+fn compares_expected_output() {
+    let results = run(fixture()).unwrap();
+    assert_eq!(
+        messages(result(&results, "misses_output")),
+        vec![
+            format!(
+                "Generated objects don't match {} (1 missing, 1 unexpected)",
+                fixture().with_file_name("expected.txt").display()
+            ),
+            "missing: 1,1,2,15,3,45,57,1001".to_string(),
+            "unexpected: 1,1,2,15,3,75,57,1001".to_string(),
+        ]
+    );
+    let missing_file = messages(result(&results, "missing_file"));
+    assert_eq!(missing_file.len(), 1);
+    assert!(missing_file[0].starts_with("Could not read expected objects from"));
+}
+
+#[test]
+fn scripts_that_dont_parse() {
+    let dir = std::env::temp_dir().join(format!("spwn_testing_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("broken.spwn");
+    std::fs::write(&path, "ok = #[test] () {\n    let a = \n").unwrap();
+    let errors = run(path).unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn cli_reports_failures() {
+    let output = Command::new(env!("CARGO_BIN_EXE_spwn"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["test", "tests/fixtures/testing"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("test passes ... ok"));
+    assert!(stdout.contains("test throws ... FAILED"));
+    assert!(stdout.contains("test result: FAILED. 2 passed; 5 failed"));
+}
@@ -0,0 +1,47 @@
+// while loops run at compile time, and give up if they look like they never end
+
+mod common;
+
+use common::with_stack;
+use spwn::compiler::WHILE_ITERATION_LIMIT;
+use spwn::Compiler;
+
+fn compile(source: &str) -> Result<(), String> {
+    let source = source.to_string();
+    with_stack(move || {
+        let output = Compiler::new(concat!(env!("CARGO_MANIFEST_DIR"), "/while.spwn"))
+            .source(&source)
+            .include_path(env!("CARGO_MANIFEST_DIR"))
+            .compile();
+        match output.diagnostics.first() {
+            Some(d) => Err(d.message.clone()),
+            None => Ok(()),
+        }
+    })
+}
+
+#[test]
+fn loops_until_the_condition_is_false() {
+    compile("let i = 0\nwhile i < 5 {\n    i += 1\n}\n$.assert(i == 5)\n").unwrap();
+    compile("let i = 10\nwhile i < 5 {\n    i += 1\n}\n$.assert(i == 10)\n").unwrap();
+}
+
+#[test]
+fn break_and_continue() {
+    compile(
+        "let i = 0\nlet odd = 0\nwhile true {\n    i += 1\n    if i > 9 {\n        break\n    }\n    if i % 2 == 0 {\n        continue\n    }\n    odd += 1\n}\n$.assert(i == 10)\n$.assert(odd == 5)\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn endless_loops_are_an_error() {
+    let err = compile("while true {}\n").unwrap_err();
+    assert_eq!(
+        err,
+        format!(
+            "While loop ran more than {} times, it might never end",
+            WHILE_ITERATION_LIMIT
+        )
+    );
+}